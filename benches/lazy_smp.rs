@@ -0,0 +1,63 @@
+//! How Lazy SMP scales with the number of threads, for picking a thread count on a given machine.
+//! Each game is searched to a fixed depth from a fresh table with 1, 2, 4, ... threads up to twice
+//! the number of cores, and the table printed shows the nodes searched per second and how much
+//! sooner the search reached that depth than with one thread.
+//!
+//! Run with `cargo bench --bench lazy_smp`.
+use chess::Board;
+use minimax_game::{
+    chess::MaterialEvaluator,
+    connect_four::{ConnectFour, ConnectFourEvaluator},
+    evaluate::{EvaluationValue, Evaluator},
+    game::*,
+    lazy_smp::LazySmpStrategy,
+    strategy::Strategy,
+};
+use std::{hash::Hash, str::FromStr, thread, time::Duration};
+
+fn scaling<G, E>(name: &str, state: &G, evaluator: &E, depth: usize)
+where
+    G: UndoMove + Clone + Hash + Send + Sync,
+    E: Evaluator<G> + Sync,
+    E::Evaluation: EvaluationValue,
+{
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    println!("{} to depth {} ({} cores)", name, depth, cores);
+    println!(
+        "{:>8} {:>12} {:>10} {:>12} {:>8}",
+        "threads", "nodes", "time", "nodes/s", "speedup"
+    );
+    let mut single: Option<Duration> = None;
+    let mut threads = 1;
+    while threads <= 2 * cores {
+        let mut strategy = LazySmpStrategy::with_threads(threads);
+        strategy.max_depth = depth;
+        strategy.table_size = 1 << 22;
+        let info = strategy.search(state, evaluator, &mut |_| {});
+        let single = *single.get_or_insert(info.elapsed);
+        println!(
+            "{:>8} {:>12} {:>9.2}s {:>12} {:>7.2}x",
+            threads,
+            info.nodes,
+            info.elapsed.as_secs_f64(),
+            info.nodes_per_second(),
+            single.as_secs_f64() / info.elapsed.as_secs_f64()
+        );
+        threads *= 2;
+    }
+    println!();
+}
+
+fn main() {
+    // A quiet middlegame, with captures to find but no forced lines.
+    let italian =
+        Board::from_str("r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R w KQkq - 1 5")
+            .unwrap();
+    scaling("chess", &italian, &MaterialEvaluator, 5);
+    scaling(
+        "Connect Four",
+        &ConnectFour::new(),
+        &ConnectFourEvaluator,
+        11,
+    );
+}
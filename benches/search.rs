@@ -0,0 +1,68 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use minimax_game::{
+    evaluate::TerminalStateEvaluator,
+    game::*,
+    mcts::{MctsStrategy, RaveSchedule},
+    mnk::FiveInARow,
+    strategy::Strategy,
+    tic_tac_toe::BoardState,
+};
+
+/// The exhaustive search as it would be written without UndoMove or legal_moves_into(): a fresh
+/// Vec of moves and a cloned state at every node.
+fn allocating_negamax<G: GameState>(state: &G, mut alpha: i8, beta: i8) -> i8 {
+    let result = state.game_result();
+    if result.is_determined() {
+        return result.score(state.current_player());
+    }
+    let mut best = -1;
+    for mov in state.legal_moves() {
+        let score = -allocating_negamax(&state.next_state(&mov), -beta, -alpha);
+        best = best.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
+
+fn solve_tic_tac_toe(c: &mut Criterion) {
+    let mut group = c.benchmark_group("solve tic-tac-toe");
+    group.bench_function("allocating", |b| {
+        b.iter(|| allocating_negamax(black_box(&BoardState::new()), -1, 1))
+    });
+    group.bench_function("move buffer and undo", |b| {
+        b.iter(|| TerminalStateEvaluator::solve(black_box(&mut BoardState::new())))
+    });
+    group.finish();
+}
+
+/// The cost of RAVE's extra bookkeeping over plain UCT for the same number of iterations, on a
+/// small board and on five in a row, where playouts are longer and RAVE credits more moves.
+fn rave_overhead(c: &mut Criterion) {
+    let uct = MctsStrategy::with_iterations(1000, 0);
+    let rave = MctsStrategy {
+        rave: Some(RaveSchedule::Equivalence(300.0)),
+        ..uct
+    };
+    let mut group = c.benchmark_group("mcts tic-tac-toe");
+    for (name, strategy) in [("uct", uct), ("rave", rave)] {
+        group.bench_function(name, |b| {
+            b.iter(|| strategy.choose_move(black_box(&BoardState::new()), &TerminalStateEvaluator))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("mcts five in a row");
+    group.sample_size(10);
+    for (name, strategy) in [("uct", uct), ("rave", rave)] {
+        group.bench_function(name, |b| {
+            b.iter(|| strategy.choose_move(black_box(&FiveInARow::new()), &TerminalStateEvaluator))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, solve_tic_tac_toe, rave_overhead);
+criterion_main!(benches);
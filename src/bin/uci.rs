@@ -0,0 +1,36 @@
+//! Runs the UCI chess engine on standard input and output.
+use minimax_game::uci::UciEngine;
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+fn main() -> io::Result<()> {
+    // Commands are read on their own thread so the engine can send a best move as soon as a
+    // search runs out of time, without waiting for the next line.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let mut engine = UciEngine::new(io::stdout());
+    loop {
+        match receiver.recv_timeout(Duration::from_millis(1)) {
+            Ok(line) => {
+                if !engine.handle(&line)? {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        engine.poll()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,747 @@
+use crate::{evaluate::*, game::*, search::StopFlag, strategy::*};
+use rand::Rng;
+use std::time::Instant;
+
+/// The probability of a chance outcome.
+pub type Probability = f64;
+
+/// Games with chance events, like dice rolls, between the players' moves. At a chance node nobody
+/// chooses: the game moves on to one of chance_outcomes() at random, and legal_moves() is empty.
+/// current_player() should still say whose turn the chance event belongs to.
+pub trait StochasticGame: GameState {
+    /// Returns true if the next thing to happen is a chance event rather than a move.
+    fn is_chance_node(&self) -> bool;
+
+    /// Returns every state the chance event can lead to with its probability. The probabilities
+    /// add up to 1. Empty if this isn't a chance node.
+    fn chance_outcomes(&self) -> Vec<(Self, Probability)>;
+
+    /// Returns one of chance_outcomes() picked at random with its probability, e.g. for playouts.
+    fn sample_outcome<R: Rng + ?Sized>(&self, rng: &mut R) -> Self {
+        let mut outcomes = self.chance_outcomes();
+        let mut pick: Probability = rng.gen();
+        let last = outcomes.pop().expect("chance nodes have outcomes");
+        for (outcome, probability) in outcomes {
+            if pick < probability {
+                return outcome;
+            }
+            pick -= probability;
+        }
+        last.0
+    }
+}
+
+/// How ExpectimaxStrategy searches chance nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChanceSearch {
+    /// Searches every outcome of every chance node with alpha-beta pruning only at the players'
+    /// nodes.
+    Expectimax,
+    /// Ballard's Star1: since values are bounded, the outcomes searched so far bound the value of
+    /// a chance node, so the rest can be cut off once it can't land inside the window.
+    Star1,
+    /// Star2: Star1 after a probing phase that searches only the first move after each outcome,
+    /// which bounds the chance node cheaply and often cuts it off before the full search. It only
+    /// beats Star1 if games list a good move first, since failed probes are wasted work: in a race
+    /// that lists the longest move first it searches fewer nodes than Star1, but in Pig, where
+    /// rolling comes first whether or not it is wise, it searches more.
+    Star2,
+}
+
+/// Searches stochastic games to a fixed depth, averaging chance nodes by their probabilities.
+/// Values are between -1 and 1 for the player to move at the root. A position at the depth limit
+/// is worth the expected result of the evaluator's best move there, so the evaluator has to return
+/// a ResultDistribution. Chance events don't use up depth.
+#[derive(Debug, Clone, Copy)]
+pub struct ExpectimaxStrategy {
+    pub depth: usize,
+    pub algorithm: ChanceSearch,
+}
+
+/// The smallest and largest values.
+const LOWER: f64 = -1.0;
+const UPPER: f64 = 1.0;
+
+/// The state shared by every node of one search.
+struct ChanceSearcher<'a, E> {
+    algorithm: ChanceSearch,
+    /// The player the values are for.
+    player: Player,
+    nodes: u64,
+    evaluator: &'a E,
+    stop: &'a StopFlag,
+}
+
+impl<E> ChanceSearcher<'_, E> {
+    /// Returns the value of a position at the depth limit: the expected result of the best move
+    /// for the player to move, according to the evaluator.
+    fn leaf_value<G>(&self, state: &G) -> f64
+    where
+        G: StochasticGame,
+        E: Evaluator<G>,
+        E::Evaluation: ResultDistribution,
+    {
+        let best = state
+            .legal_moves()
+            .iter()
+            .map(|mov| f64::from(self.evaluator.evaluate(state, mov).expected_result()))
+            .reduce(f64::max);
+        let Some(best) = best else {
+            return 0.0;
+        };
+        if state.current_player() == self.player {
+            best
+        } else {
+            -best
+        }
+    }
+
+    /// Returns the value of the state within the (alpha, beta) window: exact if it lies inside,
+    /// otherwise a bound on the side it fell outside of.
+    fn search<G>(&mut self, state: &G, depth: usize, mut alpha: f64, mut beta: f64) -> f64
+    where
+        G: StochasticGame,
+        E: Evaluator<G>,
+        E::Evaluation: ResultDistribution,
+    {
+        self.nodes += 1;
+        let result = state.game_result();
+        if result.is_determined() {
+            return f64::from(result.score(self.player));
+        }
+        if state.is_chance_node() {
+            return match self.algorithm {
+                ChanceSearch::Expectimax => state
+                    .chance_outcomes()
+                    .iter()
+                    .map(|(outcome, probability)| {
+                        probability * self.search(outcome, depth, LOWER, UPPER)
+                    })
+                    .sum(),
+                ChanceSearch::Star1 => self.star1(state.chance_outcomes(), depth, alpha, beta),
+                ChanceSearch::Star2 => self.star2(state.chance_outcomes(), depth, alpha, beta),
+            };
+        }
+        if depth == 0 || self.stop.is_stopped() {
+            return self.leaf_value(state);
+        }
+
+        let moves = state.legal_moves();
+        if moves.is_empty() {
+            return 0.0;
+        }
+        let maximising = state.current_player() == self.player;
+        let mut best = if maximising { LOWER } else { UPPER };
+        for mov in &moves {
+            let value = self.search(&state.next_state(mov), depth - 1, alpha, beta);
+            if maximising {
+                best = best.max(value);
+                alpha = alpha.max(value);
+            } else {
+                best = best.min(value);
+                beta = beta.min(value);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    /// Searches the outcomes in turn, giving each the window that would still let the chance
+    /// node land inside (alpha, beta) given what is known about the others.
+    fn star1<G>(
+        &mut self,
+        outcomes: Vec<(G, Probability)>,
+        depth: usize,
+        alpha: f64,
+        beta: f64,
+    ) -> f64
+    where
+        G: StochasticGame,
+        E: Evaluator<G>,
+        E::Evaluation: ResultDistribution,
+    {
+        let mut searched = 0.0;
+        let mut remaining: Probability = 1.0;
+        for (outcome, probability) in outcomes {
+            remaining -= probability;
+            let child_alpha = (alpha - searched - UPPER * remaining) / probability;
+            let child_beta = (beta - searched - LOWER * remaining) / probability;
+            let value = self.search(
+                &outcome,
+                depth,
+                child_alpha.max(LOWER),
+                child_beta.min(UPPER),
+            );
+            searched += probability * value;
+            if value <= child_alpha {
+                return searched + UPPER * remaining;
+            }
+            if value >= child_beta {
+                return searched + LOWER * remaining;
+            }
+        }
+        searched
+    }
+
+    /// Bounds the chance node by searching only the first move after each outcome, which gives a
+    /// lower bound where the player the values are for is to move and an upper bound where their
+    /// opponent is, and returns early if that already puts it outside (alpha, beta). Each probe is
+    /// searched with the narrowest window that can still decide this, and probing ends as soon as
+    /// neither cutoff is possible. Otherwise falls back to star1().
+    fn star2<G>(
+        &mut self,
+        outcomes: Vec<(G, Probability)>,
+        depth: usize,
+        alpha: f64,
+        beta: f64,
+    ) -> f64
+    where
+        G: StochasticGame,
+        E: Evaluator<G>,
+        E::Evaluation: ResultDistribution,
+    {
+        if depth > 0 {
+            let mut lower = 0.0;
+            let mut upper = 0.0;
+            let mut remaining: Probability = 1.0;
+            let mut fails_high = true;
+            let mut fails_low = true;
+            for (outcome, probability) in &outcomes {
+                remaining -= probability;
+                // What this outcome has to be worth for the node to fail whatever the rest are.
+                let needed_high = (beta - lower - UPPER * remaining) / probability;
+                let needed_low = (alpha - upper - LOWER * remaining) / probability;
+                let (outcome_lower, outcome_upper) = self.probe(
+                    outcome,
+                    depth,
+                    fails_high.then_some(needed_high),
+                    fails_low.then_some(needed_low),
+                );
+                lower += probability * outcome_lower;
+                upper += probability * outcome_upper;
+                fails_high &= outcome_lower >= needed_high;
+                fails_low &= outcome_upper <= needed_low;
+                if !fails_high && !fails_low {
+                    break;
+                }
+            }
+            if fails_high {
+                return lower;
+            }
+            if fails_low {
+                return upper;
+            }
+        }
+        self.star1(outcomes, depth, alpha, beta)
+    }
+
+    /// Returns bounds on the value of the state from searching only its first move, looking for a
+    /// lower bound of at least needed_high or an upper bound of at most needed_low.
+    fn probe<G>(
+        &mut self,
+        state: &G,
+        depth: usize,
+        needed_high: Option<f64>,
+        needed_low: Option<f64>,
+    ) -> (f64, f64)
+    where
+        G: StochasticGame,
+        E: Evaluator<G>,
+        E::Evaluation: ResultDistribution,
+    {
+        let result = state.game_result();
+        if result.is_determined() {
+            let value = f64::from(result.score(self.player));
+            return (value, value);
+        }
+        if state.is_chance_node() {
+            return (LOWER, UPPER);
+        }
+        let maximising = state.current_player() == self.player;
+        let needed = if maximising { needed_high } else { needed_low };
+        let Some(needed) = needed else {
+            return (LOWER, UPPER);
+        };
+        let Some(first) = state.legal_moves().into_iter().next() else {
+            return (0.0, 0.0);
+        };
+        let next = state.next_state(&first);
+        if maximising {
+            let alpha = needed.max(LOWER);
+            let value = self.search(&next, depth - 1, alpha, UPPER);
+            (if value > alpha { value } else { LOWER }, UPPER)
+        } else {
+            let beta = needed.min(UPPER);
+            let value = self.search(&next, depth - 1, LOWER, beta);
+            (LOWER, if value < beta { value } else { UPPER })
+        }
+    }
+}
+
+impl ExpectimaxStrategy {
+    pub fn with_depth(depth: usize, algorithm: ChanceSearch) -> Self {
+        Self { depth, algorithm }
+    }
+}
+
+impl<G, E> Strategy<G, E> for ExpectimaxStrategy
+where
+    G: StochasticGame,
+    G::Move: Clone,
+    E: Evaluator<G>,
+    E::Evaluation: ResultDistribution,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Searches 4 plies deep with Star1.
+    fn new() -> Self {
+        Self::with_depth(4, ChanceSearch::Star1)
+    }
+
+    fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search_until(state, evaluator, &StopFlag::new(), report)
+    }
+
+    /// Reports once. The principal variation is only the chosen move, since what follows depends
+    /// on chance. When stopped, the rest of the search treats every position as a leaf.
+    fn search_until(
+        &self,
+        state: &G,
+        evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let mut searcher = ChanceSearcher {
+            algorithm: self.algorithm,
+            player: state.current_player(),
+            nodes: 0,
+            evaluator,
+            stop,
+        };
+        let mut best: Option<(G::Move, f64)> = None;
+        if !state.game_result().is_determined() && !state.is_chance_node() {
+            for mov in state.legal_moves() {
+                let alpha = best.as_ref().map_or(LOWER, |(_, value)| *value);
+                let value = searcher.search(
+                    &state.next_state(&mov),
+                    self.depth.saturating_sub(1),
+                    alpha,
+                    UPPER,
+                );
+                if best.is_none() || value > alpha {
+                    best = Some((mov, value));
+                }
+            }
+        }
+        let info = SearchInfo {
+            depth: self.depth,
+            score: best.as_ref().map(|(_, value)| Score::Value(*value)),
+            principal_variation: best.map(|(mov, _)| mov).into_iter().collect(),
+            nodes: searcher.nodes,
+            elapsed: start.elapsed(),
+        };
+        report(&info);
+        info
+    }
+}
+
+/// play_match() for games with chance: plays games from the given start between two strategies,
+/// alternating which one moves first, with chance outcomes drawn from the random number generator,
+/// and returns the score of the first. A strategy that returns no move before the game is over
+/// loses the game.
+pub fn play_chance_match<G, E, A, B, R>(
+    start: &G,
+    first: &A,
+    second: &B,
+    evaluator: &E,
+    games: usize,
+    rng: &mut R,
+) -> MatchScore
+where
+    G: StochasticGame + Clone,
+    E: Evaluator<G>,
+    A: Strategy<G, E, Evaluation = E::Evaluation>,
+    B: Strategy<G, E, Evaluation = E::Evaluation>,
+    R: Rng + ?Sized,
+{
+    let mut score = MatchScore::default();
+    for game in 0..games {
+        let first_player = if game % 2 == 0 {
+            start.current_player()
+        } else {
+            start.current_player().other_player()
+        };
+        let mut state = start.clone();
+        let result = loop {
+            let result = state.game_result();
+            if result.is_determined() {
+                break result;
+            }
+            if state.is_chance_node() {
+                state = state.sample_outcome(rng);
+                continue;
+            }
+            let mov = if state.current_player() == first_player {
+                first.choose_move(&state, evaluator)
+            } else {
+                second.choose_move(&state, evaluator)
+            };
+            match mov {
+                Some(mov) => {
+                    first.notify_move(&state, &mov);
+                    second.notify_move(&state, &mov);
+                    state.apply_move(&mov);
+                }
+                None => break GameResult::Win(state.current_player().other_player()),
+            }
+        };
+        score.add(result, first_player);
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChanceSearch, ExpectimaxStrategy, Probability, StochasticGame};
+    use crate::evaluate::{Distribution, Evaluator};
+    use crate::game::{GameResult, GameState, MoveError, Player, UndoMove};
+    use crate::strategy::{Score, Strategy};
+
+    /// A race to 5 points. On their turn a player either steps forward 1 point or gambles on a coin
+    /// flip for 2 points or nothing.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct DiceRace {
+        points: [u8; 2],
+        to_move: Player,
+        flipping: bool,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum RaceMove {
+        Step,
+        Gamble,
+    }
+
+    /// Values a move by how far ahead it leaves the mover, counting a gamble as a step.
+    struct RaceEvaluator;
+
+    impl Evaluator<DiceRace> for RaceEvaluator {
+        type Evaluation = Distribution;
+
+        fn new() -> Self {
+            Self
+        }
+
+        fn evaluate(&self, state: &DiceRace, _mov: &RaceMove) -> Distribution {
+            let next = state.advance(1);
+            let player = state.to_move.index();
+            let lead = next.points[player] as f32 - next.points[1 - player] as f32;
+            let lead = (lead / 5.0).clamp(-1.0, 1.0);
+            Distribution::with_probabilities((1.0 + lead) / 2.0, (1.0 - lead) / 2.0)
+        }
+    }
+
+    impl DiceRace {
+        fn advance(&self, points: u8) -> Self {
+            let mut next = *self;
+            next.points[self.to_move.index()] += points;
+            next.to_move = self.to_move.other_player();
+            next.flipping = false;
+            next
+        }
+    }
+
+    impl GameState for DiceRace {
+        type Move = RaceMove;
+
+        fn new() -> Self {
+            Self {
+                points: [0, 0],
+                to_move: Player::One,
+                flipping: false,
+            }
+        }
+
+        fn legal_moves(&self) -> Vec<RaceMove> {
+            if self.flipping || self.game_result().is_determined() {
+                Vec::new()
+            } else {
+                vec![RaceMove::Step, RaceMove::Gamble]
+            }
+        }
+
+        fn apply_move(&mut self, mov: &RaceMove) {
+            *self = match mov {
+                RaceMove::Step => self.advance(1),
+                RaceMove::Gamble => Self {
+                    flipping: true,
+                    ..*self
+                },
+            };
+        }
+
+        fn next_state(&self, mov: &RaceMove) -> Self {
+            let mut next = *self;
+            next.apply_move(mov);
+            next
+        }
+
+        fn validate_move(&self, _mov: &RaceMove) -> Result<(), MoveError> {
+            if self.game_result().is_determined() {
+                Err(MoveError::GameOver)
+            } else if self.flipping {
+                Err(MoveError::Illegal)
+            } else {
+                Ok(())
+            }
+        }
+
+        fn game_result(&self) -> GameResult {
+            match self.points {
+                [one, _] if one >= 5 => GameResult::Win(Player::One),
+                [_, two] if two >= 5 => GameResult::Win(Player::Two),
+                _ => GameResult::Undetermined,
+            }
+        }
+
+        fn current_player(&self) -> Player {
+            self.to_move
+        }
+    }
+
+    impl UndoMove for DiceRace {
+        type Undo = Self;
+
+        fn do_move(&mut self, mov: &RaceMove) -> Self {
+            let previous = *self;
+            self.apply_move(mov);
+            previous
+        }
+
+        fn undo_move(&mut self, undo: Self) {
+            *self = undo;
+        }
+    }
+
+    impl StochasticGame for DiceRace {
+        fn is_chance_node(&self) -> bool {
+            self.flipping
+        }
+
+        fn chance_outcomes(&self) -> Vec<(Self, Probability)> {
+            if self.flipping {
+                vec![(self.advance(2), 0.5), (self.advance(0), 0.5)]
+            } else {
+                Vec::new()
+            }
+        }
+    }
+
+    /// A race to 10 points in which each turn starts with a roll of a three-sided die, after which
+    /// the player moves forward by up to the number rolled. Moves are listed from the longest,
+    /// which is always the best, so Star2's probes search the best move first.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct RollRace {
+        points: [u8; 2],
+        to_move: Player,
+        rolled: Option<u8>,
+    }
+
+    /// Values a move by how far ahead it leaves the mover.
+    struct RollRaceEvaluator;
+
+    impl GameState for RollRace {
+        type Move = u8;
+
+        fn new() -> Self {
+            Self {
+                points: [0, 0],
+                to_move: Player::One,
+                rolled: None,
+            }
+        }
+
+        fn legal_moves(&self) -> Vec<u8> {
+            match self.rolled {
+                Some(roll) if !self.game_result().is_determined() => (0..=roll).rev().collect(),
+                _ => Vec::new(),
+            }
+        }
+
+        fn apply_move(&mut self, mov: &u8) {
+            self.points[self.to_move.index()] += mov;
+            self.to_move = self.to_move.other_player();
+            self.rolled = None;
+        }
+
+        fn next_state(&self, mov: &u8) -> Self {
+            let mut next = *self;
+            next.apply_move(mov);
+            next
+        }
+
+        fn validate_move(&self, mov: &u8) -> Result<(), MoveError> {
+            match self.rolled {
+                _ if self.game_result().is_determined() => Err(MoveError::GameOver),
+                Some(roll) if *mov <= roll => Ok(()),
+                Some(_) => Err(MoveError::OutOfRange),
+                None => Err(MoveError::Illegal),
+            }
+        }
+
+        fn game_result(&self) -> GameResult {
+            match self.points {
+                [one, _] if one >= 10 => GameResult::Win(Player::One),
+                [_, two] if two >= 10 => GameResult::Win(Player::Two),
+                _ => GameResult::Undetermined,
+            }
+        }
+
+        fn current_player(&self) -> Player {
+            self.to_move
+        }
+    }
+
+    impl StochasticGame for RollRace {
+        fn is_chance_node(&self) -> bool {
+            self.rolled.is_none() && !self.game_result().is_determined()
+        }
+
+        fn chance_outcomes(&self) -> Vec<(Self, Probability)> {
+            if !self.is_chance_node() {
+                return Vec::new();
+            }
+            (1..=3)
+                .map(|roll| {
+                    let outcome = Self {
+                        rolled: Some(roll),
+                        ..*self
+                    };
+                    (outcome, 1.0 / 3.0)
+                })
+                .collect()
+        }
+    }
+
+    impl Evaluator<RollRace> for RollRaceEvaluator {
+        type Evaluation = Distribution;
+
+        fn new() -> Self {
+            Self
+        }
+
+        fn evaluate(&self, state: &RollRace, mov: &u8) -> Distribution {
+            let next = state.next_state(mov);
+            let player = state.to_move.index();
+            let lead = next.points[player] as f32 - next.points[1 - player] as f32;
+            let lead = (lead / 10.0).clamp(-1.0, 1.0);
+            Distribution::with_probabilities((1.0 + lead) / 2.0, (1.0 - lead) / 2.0)
+        }
+    }
+
+    #[test]
+    fn test_chance_searches_agree() {
+        let mut states = vec![DiceRace::new()];
+        for points in [[3, 3], [4, 2], [2, 4], [0, 3], [4, 4]] {
+            states.push(DiceRace {
+                points,
+                ..DiceRace::new()
+            });
+        }
+        for state in states {
+            let search = |algorithm| {
+                ExpectimaxStrategy::with_depth(12, algorithm).search(
+                    &state,
+                    &RaceEvaluator,
+                    &mut |_| {},
+                )
+            };
+            let expectimax = search(ChanceSearch::Expectimax);
+            let Some(Score::Value(value)) = expectimax.score else {
+                panic!("no value for {:?}", state);
+            };
+            for algorithm in [ChanceSearch::Star1, ChanceSearch::Star2] {
+                let info = search(algorithm);
+                let Some(Score::Value(other)) = info.score else {
+                    panic!("no value for {:?}", state);
+                };
+                assert!((value - other).abs() < 1e-9, "{:?} {:?}", state, algorithm);
+                // Star2's probes only pay for themselves when the first move is usually a good one.
+                if algorithm == ChanceSearch::Star1 {
+                    assert!(info.nodes <= expectimax.nodes, "{:?}", state);
+                }
+            }
+        }
+
+        // One point short of winning, stepping is certain and gambling isn't.
+        let state = DiceRace {
+            points: [4, 4],
+            ..DiceRace::new()
+        };
+        let strategy = ExpectimaxStrategy::with_depth(4, ChanceSearch::Star2);
+        let info = strategy.search(&state, &RaceEvaluator, &mut |_| {});
+        assert_eq!(info.best_move(), Some(&RaceMove::Step));
+        assert_eq!(info.score, Some(Score::Value(1.0)));
+
+        let outcome = state.next_state(&RaceMove::Gamble);
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let sampled = outcome.sample_outcome(&mut rng);
+            assert!(sampled.points == [6, 4] || sampled.points == [4, 4]);
+        }
+    }
+
+    #[test]
+    fn test_star2_with_good_move_ordering() {
+        // Summed over many positions, since any one of them can go either way.
+        let mut nodes = [0; 3];
+        let algorithms = [
+            ChanceSearch::Expectimax,
+            ChanceSearch::Star1,
+            ChanceSearch::Star2,
+        ];
+        for mine in (0..10).step_by(2) {
+            for theirs in (0..10).step_by(2) {
+                for roll in 1..=3 {
+                    let state = RollRace {
+                        points: [mine, theirs],
+                        to_move: Player::One,
+                        rolled: Some(roll),
+                    };
+                    let mut values = Vec::new();
+                    for (index, algorithm) in algorithms.into_iter().enumerate() {
+                        let info = ExpectimaxStrategy::with_depth(4, algorithm).search(
+                            &state,
+                            &RollRaceEvaluator,
+                            &mut |_| {},
+                        );
+                        nodes[index] += info.nodes;
+                        let Some(Score::Value(value)) = info.score else {
+                            panic!("no value for {:?}", state);
+                        };
+                        values.push(value);
+                    }
+                    for pair in values.windows(2) {
+                        assert!((pair[0] - pair[1]).abs() < 1e-9, "{:?}", state);
+                    }
+                }
+            }
+        }
+        assert!(nodes[2] < nodes[1] && nodes[1] < nodes[0], "{:?}", nodes);
+    }
+}
@@ -0,0 +1,350 @@
+//! Chess, using the board representation and move generator of the chess crate. Player One is
+//! white and Player Two is black.
+//!
+//! Only checkmate and stalemate end the game: a Board carries no history, so draws by repetition
+//! or the fifty-move rule are not detected.
+use crate::{evaluate::*, game::*};
+use ::chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece, ALL_PIECES};
+use std::str::FromStr;
+
+/// Rates a move by the material balance after it, counting pawns 1, knights and bishops 3, rooks
+/// 5 and queens 9. A checkmate is a win and a stalemate a draw. Cheap, and only good for searches
+/// that look several moves ahead.
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialEvaluator;
+
+impl GameState for Board {
+    type Move = ChessMove;
+
+    fn new() -> Self {
+        Board::default()
+    }
+
+    fn legal_moves(&self) -> Vec<Self::Move> {
+        MoveGen::new_legal(self).collect()
+    }
+
+    fn legal_moves_into(&self, moves: &mut Vec<Self::Move>) {
+        moves.extend(MoveGen::new_legal(self));
+    }
+
+    fn apply_move(&mut self, mov: &Self::Move) {
+        *self = self.make_move_new(*mov);
+    }
+
+    fn next_state(&self, mov: &Self::Move) -> Self {
+        self.make_move_new(*mov)
+    }
+
+    fn validate_move(&self, mov: &Self::Move) -> Result<(), MoveError> {
+        let mover = self.side_to_move();
+        if self.status() != BoardStatus::Ongoing {
+            Err(MoveError::GameOver)
+        } else if self.color_on(mov.get_source()) == Some(!mover) {
+            Err(MoveError::WrongPlayer)
+        } else if self.color_on(mov.get_dest()) == Some(mover) {
+            Err(MoveError::Occupied)
+        } else if !self.legal(*mov) {
+            Err(MoveError::Illegal)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn game_result(&self) -> GameResult {
+        match self.status() {
+            BoardStatus::Ongoing => GameResult::Undetermined,
+            BoardStatus::Stalemate => GameResult::Draw,
+            BoardStatus::Checkmate => GameResult::Win(player(!self.side_to_move())),
+        }
+    }
+
+    fn current_player(&self) -> Player {
+        player(self.side_to_move())
+    }
+}
+
+impl UndoMove for Board {
+    /// A Board is small and Copy, so the previous board is the cheapest way to undo a move.
+    type Undo = Board;
+
+    fn do_move(&mut self, mov: &ChessMove) -> Board {
+        let previous = *self;
+        previous.make_move(*mov, self);
+        previous
+    }
+
+    fn undo_move(&mut self, undo: Board) {
+        *self = undo;
+    }
+}
+
+impl MoveNotation for Board {
+    /// Accepts both UCI long algebraic notation like "e2e4" or "e7e8q" and SAN like "Nf3".
+    fn parse_move(&self, s: &str) -> Result<ChessMove, ParseMoveError> {
+        let mov = ChessMove::from_str(s)
+            .or_else(|_| ChessMove::from_san(self, s))
+            .map_err(|_| ParseMoveError::Invalid(s.to_string()))?;
+        if self.legal(mov) {
+            Ok(mov)
+        } else {
+            Err(ParseMoveError::Illegal(s.to_string()))
+        }
+    }
+
+    /// Writes the move in SAN.
+    fn format_move(&self, mov: &ChessMove) -> String {
+        san(self, *mov)
+    }
+}
+
+impl PositionNotation for Board {
+    type Err = ::chess::Error;
+
+    /// Writes the position as FEN.
+    fn notation(&self) -> String {
+        self.to_string()
+    }
+
+    fn from_notation(s: &str) -> Result<Self, ::chess::Error> {
+        Board::from_str(s)
+    }
+}
+
+impl MaterialEvaluator {
+    /// Returns the material of the given side less that of the other, in pawns.
+    pub fn balance(board: &Board, color: Color) -> i32 {
+        ALL_PIECES
+            .iter()
+            .map(|&piece| {
+                let pieces = board.pieces(piece);
+                let own = (pieces & board.color_combined(color)).popcnt() as i32;
+                let other = (pieces & board.color_combined(!color)).popcnt() as i32;
+                piece_value(piece) * (own - other)
+            })
+            .sum()
+    }
+}
+
+impl Evaluator<Board> for MaterialEvaluator {
+    type Evaluation = Distribution;
+
+    fn new() -> Self {
+        Self
+    }
+
+    fn evaluate(&self, state: &Board, mov: &ChessMove) -> Distribution {
+        let next = state.make_move_new(*mov);
+        match next.status() {
+            BoardStatus::Checkmate => Distribution::win(),
+            BoardStatus::Stalemate => Distribution::draw(),
+            BoardStatus::Ongoing => {
+                let balance = Self::balance(&next, state.side_to_move());
+                let value = (balance as f32 / 8.0).tanh();
+                Distribution::with_probabilities((1.0 + value) / 2.0, (1.0 - value) / 2.0)
+            }
+        }
+    }
+}
+
+fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight | Piece::Bishop => 3,
+        Piece::Rook => 5,
+        Piece::Queen => 9,
+        Piece::King => 0,
+    }
+}
+
+/// Writes the move in UCI long algebraic notation e.g. "e2e4" or "e7e8q".
+pub fn uci(mov: &ChessMove) -> String {
+    mov.to_string()
+}
+
+/// Writes the given legal move in standard algebraic notation, including the check and mate
+/// suffixes.
+pub fn san(board: &Board, mov: ChessMove) -> String {
+    let (source, dest) = (mov.get_source(), mov.get_dest());
+    let piece = board
+        .piece_on(source)
+        .expect("a legal move starts on an occupied square");
+    let file_distance = source
+        .get_file()
+        .to_index()
+        .abs_diff(dest.get_file().to_index());
+
+    let mut san = String::new();
+    if piece == Piece::King && file_distance == 2 {
+        san.push_str(if dest.get_file() > source.get_file() {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        // Pawns that change file always capture, which also covers en passant.
+        let is_capture =
+            board.piece_on(dest).is_some() || (piece == Piece::Pawn && file_distance == 1);
+        if piece == Piece::Pawn {
+            if is_capture {
+                san.push(file_char(source.get_file().to_index()));
+            }
+        } else {
+            san.push_str(&piece.to_string(Color::White));
+            san.push_str(&disambiguation(board, mov, piece));
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&dest.to_string());
+        if let Some(promotion) = mov.get_promotion() {
+            san.push('=');
+            san.push_str(&promotion.to_string(Color::White));
+        }
+    }
+
+    let next = board.make_move_new(mov);
+    if next.status() == BoardStatus::Checkmate {
+        san.push('#');
+    } else if next.checkers().popcnt() > 0 {
+        san.push('+');
+    }
+    san
+}
+
+/// Returns the file, rank or square of the moving piece if another piece of the same kind could
+/// also move to the destination.
+fn disambiguation(board: &Board, mov: ChessMove, piece: Piece) -> String {
+    let source = mov.get_source();
+    let rivals: Vec<ChessMove> = MoveGen::new_legal(board)
+        .filter(|other| {
+            other.get_dest() == mov.get_dest()
+                && other.get_source() != source
+                && board.piece_on(other.get_source()) == Some(piece)
+        })
+        .collect();
+    if rivals.is_empty() {
+        return String::new();
+    }
+
+    let file = source.get_file();
+    let rank = source.get_rank();
+    if rivals
+        .iter()
+        .all(|other| other.get_source().get_file() != file)
+    {
+        file_char(file.to_index()).to_string()
+    } else if rivals
+        .iter()
+        .all(|other| other.get_source().get_rank() != rank)
+    {
+        rank_char(rank.to_index()).to_string()
+    } else {
+        source.to_string()
+    }
+}
+
+fn file_char(file: usize) -> char {
+    (b'a' + file as u8) as char
+}
+
+fn rank_char(rank: usize) -> char {
+    (b'1' + rank as u8) as char
+}
+
+fn player(color: Color) -> Player {
+    match color {
+        Color::White => Player::One,
+        Color::Black => Player::Two,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MaterialEvaluator;
+    use crate::evaluate::{Distribution, Evaluator, ResultDistribution};
+    use crate::game::{GameResult, GameState, MoveError, MoveNotation, ParseMoveError, Player};
+    use ::chess::{Board, ChessMove, Color};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_move_notation() {
+        let mut board = Board::default();
+        let e4 = board.parse_move("e4").unwrap();
+        assert_eq!(board.parse_move("e2e4"), Ok(e4));
+        assert_eq!(board.format_move(&e4), "e4");
+        assert_eq!(super::uci(&e4), "e2e4");
+        assert_eq!(
+            board.parse_move("e5"),
+            Err(ParseMoveError::Invalid("e5".to_string()))
+        );
+        assert_eq!(
+            board.parse_move("e2e5"),
+            Err(ParseMoveError::Illegal("e2e5".to_string()))
+        );
+        board.apply_move(&e4);
+
+        for (text, san) in [("e5", "e5"), ("Qh5", "Qh5"), ("Nc6", "Nc6"), ("Bc4", "Bc4")] {
+            let mov = board.parse_move(text).unwrap();
+            assert_eq!(board.format_move(&mov), san);
+            board.apply_move(&mov);
+        }
+        let mov = board.parse_move("Nf6").unwrap();
+        board.apply_move(&mov);
+        let mate = board.parse_move("h5f7").unwrap();
+        assert_eq!(board.format_move(&mate), "Qxf7#");
+        board.apply_move(&mate);
+        assert_eq!(board.game_result(), GameResult::Win(Player::One));
+    }
+
+    #[test]
+    fn test_material_evaluator() {
+        // White can take the undefended queen on d5 or push a pawn.
+        let board = Board::from_str("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(MaterialEvaluator::balance(&board, Color::White), -8);
+        let capture = board.parse_move("exd5").unwrap();
+        let push = board.parse_move("e5").unwrap();
+        let evaluator = MaterialEvaluator;
+        assert!(evaluator.evaluate(&board, &capture).expected_result() > 0.0);
+        assert!(evaluator.evaluate(&board, &push).expected_result() < -0.5);
+
+        let board = Board::from_str("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let mate = board.parse_move("Ra8").unwrap();
+        assert_eq!(evaluator.evaluate(&board, &mate), Distribution::win());
+    }
+
+    #[test]
+    fn test_san_disambiguation() {
+        let board = Board::from_str("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let castle = board.parse_move("e1g1").unwrap();
+        assert_eq!(board.format_move(&castle), "O-O");
+
+        let board = Board::from_str("4k3/8/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        let rook = board.parse_move("a1d1").unwrap();
+        assert_eq!(board.format_move(&rook), "Rad1");
+        let check = board.parse_move("a1a8").unwrap();
+        assert_eq!(board.format_move(&check), "Ra8+");
+    }
+
+    #[test]
+    fn test_try_apply_move() {
+        let mut board = Board::default();
+        let black_pawn = ChessMove::from_str("e7e5").unwrap();
+        assert_eq!(
+            board.try_apply_move(&black_pawn),
+            Err(MoveError::WrongPlayer)
+        );
+        let onto_pawn = ChessMove::from_str("g1e2").unwrap();
+        assert_eq!(board.try_apply_move(&onto_pawn), Err(MoveError::Occupied));
+        let too_far = ChessMove::from_str("e2e5").unwrap();
+        assert_eq!(board.try_apply_move(&too_far), Err(MoveError::Illegal));
+        assert_eq!(board, Board::default());
+
+        for text in ["f3", "e5", "g4", "Qh4"] {
+            let mov = board.parse_move(text).unwrap();
+            assert_eq!(board.try_apply_move(&mov), Ok(()));
+        }
+        let mov = ChessMove::from_str("a2a3").unwrap();
+        assert_eq!(board.try_next_state(&mov), Err(MoveError::GameOver));
+    }
+}
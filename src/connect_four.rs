@@ -0,0 +1,338 @@
+use crate::{evaluate::*, game::*};
+use std::fmt::Display;
+
+/// Connect Four: two players take turns dropping a stone into one of seven columns, where it falls
+/// to the lowest empty row of six. Whoever first gets four stones in a row horizontally,
+/// vertically or diagonally wins, and if the board fills up first the game is a draw. Player One
+/// drops X stones and moves first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectFour {
+    /// One bit per square for each player's stones, indexed by Player::index(). Each column takes
+    /// seven bits from the bottom row up, the seventh always empty so rows can't run from the top
+    /// of one column into the bottom of the next.
+    stones: [u64; 2],
+    /// The number of stones in each column.
+    heights: [u8; COLUMNS],
+    to_move: Player,
+}
+
+/// Drops a stone into the column with the given number, counting from 0 on the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectFourMove(pub u8);
+
+/// Rates a move by the rows of four through the square it fills, like mnk::LineEvaluator: every
+/// row still open to the player moving counts the cube of their stones in it, and every row the
+/// move blocks counts the cube of the opponent's. A winning move is a win.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectFourEvaluator;
+
+const COLUMNS: usize = 7;
+const ROWS: usize = 6;
+
+/// The bits between one square and the next along a column, a row and the two diagonals.
+const SHIFTS: [usize; 4] = [1, ROWS + 1, ROWS + 2, ROWS];
+
+/// The directions a row can run in, as column and row steps.
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+/// Columns from the centre outwards. Central columns are part of more rows, so searches that try
+/// moves in order find good ones sooner.
+const CENTRE_FIRST: [u8; COLUMNS] = [3, 2, 4, 1, 5, 0, 6];
+
+impl ConnectFour {
+    /// Returns the player whose stone is in the given column and row, counting rows from 0 at
+    /// the bottom.
+    pub fn stone_at(&self, column: usize, row: usize) -> Option<Player> {
+        let bit = 1 << Self::bit(column, row);
+        if self.stones[0] & bit != 0 {
+            Some(Player::One)
+        } else if self.stones[1] & bit != 0 {
+            Some(Player::Two)
+        } else {
+            None
+        }
+    }
+
+    fn bit(column: usize, row: usize) -> usize {
+        column * (ROWS + 1) + row
+    }
+
+    fn has_four(stones: u64) -> bool {
+        SHIFTS.iter().any(|&shift| {
+            let pairs = stones & (stones >> shift);
+            pairs & (pairs >> (2 * shift)) != 0
+        })
+    }
+
+    /// Returns the winner, who can only be whoever moved last.
+    fn winner(&self) -> Option<Player> {
+        let last = self.to_move.other_player();
+        Self::has_four(self.stones[last.index()]).then_some(last)
+    }
+
+    fn is_full(&self) -> bool {
+        self.heights.iter().all(|&height| height as usize == ROWS)
+    }
+}
+
+impl GameState for ConnectFour {
+    type Move = ConnectFourMove;
+
+    /// Returns an empty board with Player One to move.
+    fn new() -> Self {
+        Self {
+            stones: [0; 2],
+            heights: [0; COLUMNS],
+            to_move: Player::One,
+        }
+    }
+
+    /// Every column with room in it, from the centre outwards, or nothing once the game is over.
+    fn legal_moves(&self) -> Vec<ConnectFourMove> {
+        let mut moves = Vec::new();
+        self.legal_moves_into(&mut moves);
+        moves
+    }
+
+    fn legal_moves_into(&self, moves: &mut Vec<ConnectFourMove>) {
+        if self.winner().is_some() {
+            return;
+        }
+        moves.extend(
+            CENTRE_FIRST
+                .iter()
+                .filter(|&&column| (self.heights[column as usize] as usize) < ROWS)
+                .map(|&column| ConnectFourMove(column)),
+        );
+    }
+
+    fn apply_move(&mut self, mov: &ConnectFourMove) {
+        let column = mov.0 as usize;
+        let bit = Self::bit(column, self.heights[column] as usize);
+        self.stones[self.to_move.index()] |= 1 << bit;
+        self.heights[column] += 1;
+        self.to_move.flip_player();
+    }
+
+    fn next_state(&self, mov: &ConnectFourMove) -> Self {
+        let mut next = *self;
+        next.apply_move(mov);
+        next
+    }
+
+    /// A full column is Illegal.
+    fn validate_move(&self, mov: &ConnectFourMove) -> Result<(), MoveError> {
+        if self.game_result().is_determined() {
+            Err(MoveError::GameOver)
+        } else if mov.0 as usize >= COLUMNS {
+            Err(MoveError::OutOfRange)
+        } else if self.heights[mov.0 as usize] as usize == ROWS {
+            Err(MoveError::Illegal)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn game_result(&self) -> GameResult {
+        if let Some(winner) = self.winner() {
+            GameResult::Win(winner)
+        } else if self.is_full() {
+            GameResult::Draw
+        } else {
+            GameResult::Undetermined
+        }
+    }
+
+    fn current_player(&self) -> Player {
+        self.to_move
+    }
+}
+
+impl UndoMove for ConnectFour {
+    type Undo = ConnectFourMove;
+
+    fn do_move(&mut self, mov: &ConnectFourMove) -> ConnectFourMove {
+        self.apply_move(mov);
+        *mov
+    }
+
+    fn undo_move(&mut self, undo: ConnectFourMove) {
+        let column = undo.0 as usize;
+        self.heights[column] -= 1;
+        self.to_move.flip_player();
+        let bit = Self::bit(column, self.heights[column] as usize);
+        self.stones[self.to_move.index()] &= !(1 << bit);
+    }
+}
+
+impl MoveNotation for ConnectFour {
+    /// Parses the column number, from 1 on the left.
+    fn parse_move(&self, s: &str) -> Result<ConnectFourMove, ParseMoveError> {
+        let column = match s.as_bytes() {
+            [digit @ b'1'..=b'7'] => digit - b'1',
+            _ => return Err(ParseMoveError::Invalid(s.to_string())),
+        };
+        let mov = ConnectFourMove(column);
+        match self.validate_move(&mov) {
+            Ok(()) => Ok(mov),
+            Err(_) => Err(ParseMoveError::Illegal(s.to_string())),
+        }
+    }
+
+    fn format_move(&self, mov: &ConnectFourMove) -> String {
+        (mov.0 + 1).to_string()
+    }
+}
+
+impl Display for ConnectFour {
+    /// Draws the board from the top row down, with X for Player One, O for Player Two and . for
+    /// empty squares.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in (0..ROWS).rev() {
+            for column in 0..COLUMNS {
+                let square = match self.stone_at(column, row) {
+                    Some(Player::One) => 'X',
+                    Some(Player::Two) => 'O',
+                    None => '.',
+                };
+                write!(f, "{}", square)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Evaluator<ConnectFour> for ConnectFourEvaluator {
+    type Evaluation = Distribution;
+
+    fn new() -> Self {
+        Self
+    }
+
+    fn evaluate(&self, state: &ConnectFour, mov: &ConnectFourMove) -> Distribution {
+        let next = state.next_state(mov);
+        if next.winner().is_some() {
+            return Distribution::win();
+        }
+        let player = state.current_player().index();
+        let (own, other) = (next.stones[player], next.stones[1 - player]);
+        let column = mov.0 as isize;
+        let row = state.heights[mov.0 as usize] as isize;
+        let on_board = |x: isize, y: isize| {
+            (0..COLUMNS as isize).contains(&x) && (0..ROWS as isize).contains(&y)
+        };
+        let mut total = 0;
+        for (dx, dy) in DIRECTIONS {
+            // Each row through the square starts up to three steps back from it.
+            for back in 0..4 {
+                let (x, y) = (column - dx * back, row - dy * back);
+                if !on_board(x, y) || !on_board(x + dx * 3, y + dy * 3) {
+                    continue;
+                }
+                let (mut mine, mut theirs) = (0, 0);
+                for step in 0..4 {
+                    let bit =
+                        1 << ConnectFour::bit((x + dx * step) as usize, (y + dy * step) as usize);
+                    mine += (own & bit != 0) as u32;
+                    theirs += (other & bit != 0) as u32;
+                }
+                if theirs == 0 {
+                    total += mine.pow(3);
+                } else if mine == 1 {
+                    total += theirs.pow(3);
+                }
+            }
+        }
+        let value = (f64::from(total) / 64.0).tanh() as f32;
+        Distribution::with_probabilities((1.0 + value) / 2.0, (1.0 - value) / 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConnectFour, ConnectFourEvaluator, ConnectFourMove};
+    use crate::evaluate::{Distribution, Evaluator, ResultDistribution};
+    use crate::game::{GameResult, GameState, MoveError, MoveNotation, Player, UndoMove};
+
+    fn play(moves: &[&str]) -> ConnectFour {
+        let mut game = ConnectFour::new();
+        for mov in moves {
+            assert_eq!(game.game_result(), GameResult::Undetermined, "{}", game);
+            game.apply_move(&game.parse_move(mov).unwrap());
+        }
+        game
+    }
+
+    #[test]
+    fn test_connect_four_rules() {
+        // Stones fall to the bottom of their column.
+        let game = play(&["4", "4", "3"]);
+        assert_eq!(game.stone_at(3, 0), Some(Player::One));
+        assert_eq!(game.stone_at(3, 1), Some(Player::Two));
+        assert_eq!(game.stone_at(2, 0), Some(Player::One));
+        assert_eq!(game.to_string().lines().last(), Some("..XX..."));
+        assert_eq!(game.legal_moves()[0], ConnectFourMove(3));
+
+        let full = play(&["1"; 6]);
+        assert_eq!(full.legal_moves().len(), 6);
+        assert_eq!(
+            full.validate_move(&ConnectFourMove(0)),
+            Err(MoveError::Illegal)
+        );
+        assert_eq!(
+            full.validate_move(&ConnectFourMove(7)),
+            Err(MoveError::OutOfRange)
+        );
+        assert!(full.parse_move("1").is_err());
+        assert!(full.parse_move("0").is_err());
+
+        // Four in a row in every direction.
+        for moves in [
+            vec!["1", "2", "1", "2", "1", "2", "1"],
+            vec!["1", "1", "2", "2", "3", "3", "4"],
+            vec!["1", "2", "2", "3", "3", "4", "3", "4", "4", "7", "4"],
+            vec!["7", "6", "6", "5", "5", "4", "5", "4", "4", "1", "4"],
+        ] {
+            let won = play(&moves);
+            assert_eq!(won.game_result(), GameResult::Win(Player::One), "{}", won);
+            assert!(won.legal_moves().is_empty());
+        }
+
+        // Columns of alternating stones, with the middle one upside down, leave no four.
+        let mut moves = vec!["1"; 6];
+        moves.extend(["2"; 6]);
+        moves.extend(["3"; 6]);
+        moves.extend(["5", "4", "4", "5", "5", "4", "4", "5", "5", "4", "4", "5"]);
+        moves.extend(["6"; 6]);
+        moves.extend(["7"; 6]);
+        let drawn = play(&moves);
+        assert_eq!(drawn.game_result(), GameResult::Draw, "{}", drawn);
+        assert_eq!(
+            drawn.validate_move(&ConnectFourMove(0)),
+            Err(MoveError::GameOver)
+        );
+
+        let mut undone = game;
+        let undo = undone.do_move(&ConnectFourMove(2));
+        assert_eq!(undone.stone_at(2, 1), Some(Player::Two));
+        undone.undo_move(undo);
+        assert_eq!(undone, game);
+    }
+
+    #[test]
+    fn test_connect_four_evaluator() {
+        let value = |game: &ConnectFour, column| {
+            ConnectFourEvaluator
+                .evaluate(game, &ConnectFourMove(column))
+                .expected_result()
+        };
+        let empty = ConnectFour::new();
+        assert!(value(&empty, 3) > value(&empty, 0));
+        let game = play(&["4", "1", "4", "1", "4", "2"]);
+        assert_eq!(
+            ConnectFourEvaluator.evaluate(&game, &ConnectFourMove(3)),
+            Distribution::win()
+        );
+    }
+}
@@ -58,8 +58,17 @@ where
     fn evaluate(&self, state: &G, mov: &G::Move) -> Self::Evaluation;
 }
 
+/// Evaluations that can be turned into a single number, so that a search can score the positions
+/// it doesn't search any further by the evaluator's best move there.
+pub trait EvaluationValue {
+    /// Returns the value of the evaluated move for the given player, who is the one making it, from
+    /// -1 for a certain loss to 1 for a certain win.
+    fn value(&self, player: Player) -> f64;
+}
+
 /// Typical example of a struct that implements ResultDistribution.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Distribution {
     /// Probabilities of win/loss, respectively.
     probs: [Probability; 2],
@@ -81,6 +90,13 @@ impl Distribution {
     pub fn draw() -> Self {
         Self { probs: [0.0, 0.0] }
     }
+
+    /// Returns the distribution with the given probabilities of a win and a loss, which must not
+    /// add up to more than 1. Whatever is left over is the probability of a draw.
+    pub fn with_probabilities(win: Probability, loss: Probability) -> Self {
+        debug_assert!(win >= 0.0 && loss >= 0.0 && win + loss <= 1.0 + 1e-6);
+        Self { probs: [win, loss] }
+    }
 }
 
 impl ResultDistribution for Distribution {
@@ -103,11 +119,81 @@ impl ResultDistribution for Distribution {
     }
 }
 
+impl EvaluationValue for Distribution {
+    /// The expected result, since distributions are from the point of view of the player moving.
+    fn value(&self, _player: Player) -> f64 {
+        f64::from(self.expected_result())
+    }
+}
+
+impl EvaluationValue for GameResult {
+    fn value(&self, player: Player) -> f64 {
+        f64::from(self.score(player))
+    }
+}
+
+/// Evaluates moves by searching all the way to the end of the game, so the evaluation is the
+/// exact result of the game under perfect play. Only feasible for small games like tic-tac-toe.
 pub struct TerminalStateEvaluator;
 
+impl TerminalStateEvaluator {
+    /// Returns the result of the game under perfect play from both sides. The state is walked with
+    /// do_move()/undo_move() and is back where it started when this returns.
+    pub fn solve<G>(state: &mut G) -> GameResult
+    where
+        G: UndoMove,
+    {
+        let player = state.current_player();
+        match Self::negamax(state, -1, 1, &mut Vec::new()) {
+            1 => GameResult::Win(player),
+            -1 => GameResult::Win(player.other_player()),
+            _ => GameResult::Draw,
+        }
+    }
+
+    /// Alpha-beta search over the final results of the game. Returns 1, 0 or -1 if the player to
+    /// move wins, draws or loses, respectively. A move after which the same player moves again,
+    /// like an extra turn in Kalah, keeps the score and window as they are. The moves of every
+    /// node on the current path are kept in the one buffer, so the search doesn't allocate once
+    /// the buffer has grown.
+    fn negamax<G>(state: &mut G, mut alpha: i8, beta: i8, moves: &mut Vec<G::Move>) -> i8
+    where
+        G: UndoMove,
+    {
+        let result = state.game_result();
+        if result.is_determined() {
+            return result.score(state.current_player());
+        }
+
+        let start = moves.len();
+        state.legal_moves_into(moves);
+        if moves.len() == start {
+            return 0;
+        }
+        let mut best = -1;
+        for index in start..moves.len() {
+            let player = state.current_player();
+            let undo = state.do_move(&moves[index]);
+            let score = if state.current_player() == player {
+                Self::negamax(state, alpha, beta, moves)
+            } else {
+                -Self::negamax(state, -beta, -alpha, moves)
+            };
+            state.undo_move(undo);
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        moves.truncate(start);
+        best
+    }
+}
+
 impl<G> Evaluator<G> for TerminalStateEvaluator
 where
-    G: GameState,
+    G: UndoMove,
 {
     type Evaluation = GameResult;
 
@@ -115,17 +201,231 @@ where
         Self
     }
 
+    /// Returns the result the game will have if mov is played and both sides play perfectly
+    /// afterwards.
     fn evaluate(&self, state: &G, mov: &<G as GameState>::Move) -> Self::Evaluation {
-        let current_player = state.current_player();
-        let other_player = current_player.other_player();
-        let next_state = state.next_state(mov);
-        let game_result = next_state.game_result();
+        let mut next_state = state.next_state(mov);
+        Self::solve(&mut next_state)
+    }
+}
+
+/// The final score of the game under perfect play, as Player One's margin like
+/// GameState::final_score(). Unlike a GameResult it says by how much, and the result follows from
+/// it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FinalScore(pub i32);
 
-        if game_result.is_determined() {
-            return game_result;
+impl FinalScore {
+    pub fn result(&self) -> GameResult {
+        GameResult::from_final_score(self.0)
+    }
+
+    /// Returns the margin from the given player's point of view.
+    pub fn margin(&self, player: Player) -> i32 {
+        match player {
+            Player::One => self.0,
+            Player::Two => -self.0,
         }
-        //if let Some(opponents_move)
+    }
+}
+
+impl EvaluationValue for FinalScore {
+    /// 1 for any win and -1 for any loss, however big the margin.
+    fn value(&self, player: Player) -> f64 {
+        self.result().value(player)
+    }
+}
 
-        todo!()
+/// TerminalStateEvaluator for games with a final score: both sides play to maximise their margin
+/// rather than just to win. Since a positive margin is a win, they still win whenever they can.
+/// Only feasible for small games.
+pub struct MarginEvaluator;
+
+impl MarginEvaluator {
+    /// Returns the final score of the game when both sides maximise their margin. The state is
+    /// walked with do_move()/undo_move() and is back where it started when this returns.
+    pub fn solve<G>(state: &mut G) -> FinalScore
+    where
+        G: UndoMove,
+        G::Move: Clone,
+    {
+        Self::solve_line(state).0
+    }
+
+    /// Like solve(), but also returns the line of play that reaches the final score, in which
+    /// ties go to the earliest move, and the number of positions searched.
+    pub fn solve_line<G>(state: &mut G) -> (FinalScore, Vec<G::Move>, u64)
+    where
+        G: UndoMove,
+        G::Move: Clone,
+    {
+        let player = state.current_player();
+        let mut line = Vec::new();
+        let mut nodes = 0;
+        let margin = Self::negamax(
+            state,
+            -i32::MAX,
+            i32::MAX,
+            &mut Vec::new(),
+            &mut line,
+            &mut nodes,
+        );
+        let score = FinalScore(match player {
+            Player::One => margin,
+            Player::Two => -margin,
+        });
+        (score, line, nodes)
+    }
+
+    /// Alpha-beta search over the final scores of the game. Returns the margin of the player to
+    /// move and fills in the principal variation, sharing one move buffer and handling extra turns
+    /// like TerminalStateEvaluator::negamax(). Every position searched adds one to nodes.
+    fn negamax<G>(
+        state: &mut G,
+        mut alpha: i32,
+        beta: i32,
+        moves: &mut Vec<G::Move>,
+        principal_variation: &mut Vec<G::Move>,
+        nodes: &mut u64,
+    ) -> i32
+    where
+        G: UndoMove,
+        G::Move: Clone,
+    {
+        *nodes += 1;
+        principal_variation.clear();
+        if let Some(score) = state.final_score() {
+            return FinalScore(score).margin(state.current_player());
+        }
+
+        let start = moves.len();
+        state.legal_moves_into(moves);
+        if moves.len() == start {
+            return 0;
+        }
+        let mut best = -i32::MAX;
+        let mut line = Vec::new();
+        for index in start..moves.len() {
+            let player = state.current_player();
+            let undo = state.do_move(&moves[index]);
+            let score = if state.current_player() == player {
+                Self::negamax(state, alpha, beta, moves, &mut line, nodes)
+            } else {
+                -Self::negamax(state, -beta, -alpha, moves, &mut line, nodes)
+            };
+            state.undo_move(undo);
+            if score > best {
+                best = score;
+                principal_variation.clear();
+                principal_variation.push(moves[index].clone());
+                principal_variation.append(&mut line);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        moves.truncate(start);
+        best
+    }
+}
+
+impl<G> Evaluator<G> for MarginEvaluator
+where
+    G: UndoMove,
+    G::Move: Clone,
+{
+    type Evaluation = FinalScore;
+
+    fn new() -> Self {
+        Self
+    }
+
+    /// Returns the final score the game will have if mov is played and both sides maximise their
+    /// margin afterwards.
+    fn evaluate(&self, state: &G, mov: &<G as GameState>::Move) -> Self::Evaluation {
+        let mut next_state = state.next_state(mov);
+        Self::solve(&mut next_state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FinalScore, MarginEvaluator, TerminalStateEvaluator};
+    use crate::game::{GameResult, GameState, Player};
+    use crate::mancala::{Kalah, KalahMove};
+    use crate::strategy::{AlphaBetaStrategy, MarginStrategy, Score, Strategy};
+    use crate::tic_tac_toe::{BoardState, PerfectPlayTable};
+
+    #[test]
+    fn test_final_score() {
+        let table = PerfectPlayTable::new();
+        for (board, result) in table.iter() {
+            let mut solved = *board;
+            let score = MarginEvaluator::solve(&mut solved);
+            assert_eq!(solved, *board);
+            assert_eq!(score.result(), *result, "{}", board);
+            match board.final_score() {
+                Some(final_score) => {
+                    assert_eq!(
+                        GameResult::from_final_score(final_score),
+                        board.game_result()
+                    );
+                    assert_eq!(score, FinalScore(final_score));
+                }
+                None => assert!(!board.game_result().is_determined()),
+            }
+        }
+        assert_eq!(
+            MarginEvaluator::solve(&mut BoardState::new()),
+            FinalScore(0)
+        );
+
+        // In Kalah the margin is the difference between the stores, and with perfect play Player
+        // One wins this small board by four seeds.
+        let mut kalah = Kalah::<3>::with_seeds(2);
+        let info = MarginStrategy.search(&kalah, &MarginEvaluator, &mut |_| {});
+        let solved = MarginEvaluator::solve(&mut kalah);
+        assert_eq!(solved, FinalScore(4));
+        assert_eq!(info.score, Some(Score::Margin(solved.0)));
+        assert!(info.nodes > 0);
+        let mut end = kalah;
+        for mov in &info.principal_variation {
+            end.apply_move(mov);
+        }
+        assert_eq!(end.final_score(), Some(solved.0));
+        assert_eq!(FinalScore(solved.0).result(), end.game_result());
+
+        // Sowing into the store gives an extra turn, which neither solver negates.
+        assert_eq!(
+            kalah.next_state(&KalahMove(1)).current_player(),
+            Player::One
+        );
+        assert_eq!(
+            TerminalStateEvaluator::solve(&mut kalah),
+            GameResult::Win(Player::One)
+        );
+
+        // Alpha-beta scores finished games by their final score too, so it wins by as much.
+        let mut positions = vec![kalah];
+        positions.extend(kalah.legal_moves().iter().map(|mov| kalah.next_state(mov)));
+        for position in positions {
+            let alpha_beta = AlphaBetaStrategy::with_depth(usize::MAX).search(
+                &position,
+                &MarginEvaluator,
+                &mut |_| {},
+            );
+            let mut end = position;
+            for mov in &alpha_beta.principal_variation {
+                end.apply_move(mov);
+            }
+            assert_eq!(
+                end.final_score(),
+                Some(MarginEvaluator::solve(&mut position.clone()).0),
+                "{}",
+                position
+            );
+        }
     }
 }
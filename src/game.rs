@@ -1,16 +1,22 @@
-use crate::{evaluate::*, strategy::*};
-use std::fmt::Display;
+use crate::{evaluate::*, record::*, strategy::*};
+use std::{fmt::Display, time::Instant};
 
 /// Used to represent which player is going.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Player {
     #[default]
     One,
     Two,
 }
 
+/// Which player is which in a game for any number of players, counting from 0. In two-player
+/// games Player::One is 0 and Player::Two is 1.
+pub type PlayerIndex = usize;
+
 /// Represents the current outcome of the game. Undetermined denotes a non-terminal state.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GameResult {
     Win(Player),
     Draw,
@@ -33,6 +39,9 @@ where
 /// The state of the game. This should include both the current board position and any other
 /// necessary information e.g. in chess, we want this to include some kind of history so we
 /// can determine things like the 3-move repetition rule.
+///
+/// GameState is for two-player games. Games for more players implement
+/// multiplayer::MultiplayerGame instead, which every GameState is as well.
 pub trait GameState: Sized {
     /// This type should describe the moves of the game.
     type Move;
@@ -43,18 +52,57 @@ pub trait GameState: Sized {
     /// Returns a Vec of all the legal moves based on the current game state.
     fn legal_moves(&self) -> Vec<Self::Move>;
 
-    /// Applies the given move to advance the GameState.
+    /// Appends the legal moves to the given buffer rather than allocating a new Vec. A search can
+    /// reuse a single buffer for the whole tree by truncating it back to its old length when it
+    /// is done with a node. The default just extends the buffer with legal_moves(), so games
+    /// should override it to get any benefit.
+    fn legal_moves_into(&self, moves: &mut Vec<Self::Move>) {
+        moves.extend(self.legal_moves());
+    }
+
+    /// Applies the given move to advance the GameState. This does not need to check that the move
+    /// is legal; use try_apply_move() for untrusted moves.
     fn apply_move(&mut self, mov: &Self::Move);
 
-    /// Returns what the next game state would be if the move were applied.
+    /// Returns what the next game state would be if the move were applied. Like apply_move(), this
+    /// does not need to check that the move is legal.
     fn next_state(&self, mov: &Self::Move) -> Self;
 
+    /// Returns Ok if the given move can be played in the current state, and otherwise the reason
+    /// it can't.
+    fn validate_move(&self, mov: &Self::Move) -> Result<(), MoveError>;
+
+    /// Applies the given move if it is legal, leaving the state untouched otherwise.
+    fn try_apply_move(&mut self, mov: &Self::Move) -> Result<(), MoveError> {
+        self.validate_move(mov)?;
+        self.apply_move(mov);
+        Ok(())
+    }
+
+    /// Returns the next game state if the given move is legal.
+    fn try_next_state(&self, mov: &Self::Move) -> Result<Self, MoveError> {
+        self.validate_move(mov)?;
+        Ok(self.next_state(mov))
+    }
+
     /// Returns the current result of the game from the perspective of the player whose turn it is.
     fn game_result(&self) -> GameResult;
 
     /// Returns the current player i.e. the player whose turn it is.
     fn current_player(&self) -> Player;
 
+    /// Returns the final score once the game is over, as Player One's margin over Player Two, e.g.
+    /// the disc difference in Othello. Positive is a win for Player One, negative a win for Player
+    /// Two and 0 a draw, so that GameResult::from_final_score() agrees with game_result(). Games
+    /// that only have a winner can keep the default of 1, -1 or 0.
+    fn final_score(&self) -> Option<i32> {
+        match self.game_result() {
+            GameResult::Win(winner) => Some(if winner == Player::One { 1 } else { -1 }),
+            GameResult::Draw => Some(0),
+            GameResult::Undetermined => None,
+        }
+    }
+
     /// Returns a vector of game states reachable from the current state in one move.
     fn reachable_states(&self) -> Vec<Self> {
         self.legal_moves()
@@ -73,6 +121,70 @@ pub trait GameState: Sized {
     }
 }
 
+/// Converts moves to and from text, for human input, game records, logs and protocols. Moves are
+/// parsed relative to a state since many notations (e.g. SAN in chess) can't be understood
+/// without the position they are played from.
+pub trait MoveNotation: GameState {
+    /// Parses the given text as a move from the current state. The move must also be legal.
+    fn parse_move(&self, s: &str) -> Result<Self::Move, ParseMoveError>;
+
+    /// Writes the given move, played from the current state, in the game's preferred notation.
+    fn format_move(&self, mov: &Self::Move) -> String;
+}
+
+/// Converts whole positions, including whose turn it is, to and from one line of text, like FEN
+/// in chess. Games that start from somewhere other than new() can then still be written down and
+/// read back.
+pub trait PositionNotation: GameState {
+    type Err;
+
+    /// Writes the position in the game's notation.
+    fn notation(&self) -> String;
+
+    /// Parses a position written by notation().
+    fn from_notation(s: &str) -> Result<Self, Self::Err>;
+}
+
+/// Returned when text can't be turned into a move. Both variants hold the offending text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseMoveError {
+    /// The text isn't a move in any notation the game understands.
+    Invalid(String),
+    /// The text describes a move, but not one that is legal in the current state.
+    Illegal(String),
+}
+
+/// The reasons a move can be rejected by GameState::try_apply_move().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// The square being moved to is already occupied.
+    Occupied,
+    /// The move refers to squares that aren't on the board, or is otherwise malformed.
+    OutOfRange,
+    /// The game has already ended, so no more moves can be made.
+    GameOver,
+    /// The move belongs to the player whose turn it isn't e.g. it moves one of their pieces.
+    WrongPlayer,
+    /// The move breaks some other rule of the game e.g. it leaves the king in check.
+    Illegal,
+}
+
+/// Games that can take back a move in place. Searches can then walk the game tree by doing and
+/// undoing moves on a single state, rather than cloning a new state at every node with
+/// next_state(). Games without a cheaper way of undoing a move can simply use a copy of the
+/// previous state as the undo token.
+pub trait UndoMove: GameState {
+    /// Whatever is needed to restore the state from before a move.
+    type Undo;
+
+    /// Applies the given move like apply_move(), returning a token that takes it back.
+    fn do_move(&mut self, mov: &Self::Move) -> Self::Undo;
+
+    /// Takes back the move that returned the given token. Tokens must be undone in the reverse
+    /// order to the one they were made in.
+    fn undo_move(&mut self, undo: Self::Undo);
+}
+
 impl Player {
     /// Returns the other Player enum variant
     pub fn other_player(&self) -> Player {
@@ -89,6 +201,23 @@ impl Player {
             Player::Two => *self = Player::One,
         };
     }
+
+    /// Returns the player's index among any number of players: 0 for One and 1 for Two.
+    pub fn index(&self) -> usize {
+        match self {
+            Player::One => 0,
+            Player::Two => 1,
+        }
+    }
+
+    /// Returns the player with the given index, if it is 0 or 1.
+    pub fn from_index(index: usize) -> Option<Player> {
+        match index {
+            0 => Some(Player::One),
+            1 => Some(Player::Two),
+            _ => None,
+        }
+    }
 }
 
 impl Display for Player {
@@ -106,6 +235,26 @@ impl GameResult {
         *self != GameResult::Undetermined
     }
 
+    /// Scores the result from the given player's perspective: 1 for a win, -1 for a loss and 0
+    /// for a draw or undetermined result.
+    pub fn score(&self, player: Player) -> i8 {
+        match *self {
+            GameResult::Win(winner) if winner == player => 1,
+            GameResult::Win(_) => -1,
+            GameResult::Draw | GameResult::Undetermined => 0,
+        }
+    }
+
+    /// Returns the result of a finished game with the given final score, as from
+    /// GameState::final_score().
+    pub fn from_final_score(score: i32) -> GameResult {
+        match score.signum() {
+            1 => GameResult::Win(Player::One),
+            -1 => GameResult::Win(Player::Two),
+            _ => GameResult::Draw,
+        }
+    }
+
     pub fn other_result(&self) -> GameResult {
         match *self {
             GameResult::Win(player) => GameResult::Win(player.other_player()),
@@ -114,6 +263,32 @@ impl GameResult {
     }
 }
 
+impl Display for ParseMoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseMoveError::Invalid(s) => write!(f, "'{}' is not a valid move", s),
+            ParseMoveError::Illegal(s) => write!(f, "'{}' is not a legal move here", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseMoveError {}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            MoveError::Occupied => "the square is already occupied",
+            MoveError::OutOfRange => "the move is not on the board",
+            MoveError::GameOver => "the game is already over",
+            MoveError::WrongPlayer => "it is the other player's turn",
+            MoveError::Illegal => "the move is not legal",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl std::error::Error for MoveError {}
+
 impl From<Player> for GameResult {
     /// Turns the given player into Win(player).
     fn from(value: Player) -> Self {
@@ -124,6 +299,17 @@ impl From<Player> for GameResult {
     }
 }
 
+impl<G, E, S> Default for GamePlayer<G, E, S>
+where
+    G: GameState + Display,
+    E: Evaluator<G, Evaluation = S::Evaluation>,
+    S: Strategy<G, E>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<G, E, S> GamePlayer<G, E, S>
 where
     G: GameState + Display,
@@ -146,6 +332,34 @@ where
         }
     }
 
+    /// Plays the game to the end without printing anything, recording every move along with how
+    /// long the strategy took to choose it and what its search found.
+    pub fn play_recorded(&mut self) -> GameRecord<G>
+    where
+        G: Clone,
+        G::Move: Clone,
+    {
+        let mut record = GameRecord::new(self.state.clone());
+        let name = type_name::<S>();
+        record.players = [name.clone(), name];
+        while !self.state.game_result().is_determined() {
+            let start = Instant::now();
+            let info = self
+                .strategy
+                .search(&self.state, &self.evaluator, &mut |_| {});
+            match info.principal_variation.first().cloned() {
+                Some(mov) => {
+                    self.strategy.notify_move(&self.state, &mov);
+                    self.state.apply_move(&mov);
+                    record.push(mov, Some(start.elapsed()), Some(info));
+                }
+                None => break,
+            }
+        }
+        record.result = self.state.game_result();
+        record
+    }
+
     pub fn play(&mut self) -> GameResult {
         loop {
             print!("{}", &self.state);
@@ -154,6 +368,7 @@ where
                     if let Some(move_candidate) =
                         self.strategy.choose_move(&self.state, &self.evaluator)
                     {
+                        self.strategy.notify_move(&self.state, &move_candidate);
                         self.state.apply_move(&move_candidate);
                         std::thread::sleep(std::time::Duration::from_secs(1))
                     }
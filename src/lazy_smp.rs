@@ -0,0 +1,722 @@
+use crate::{evaluate::*, game::*, search::StopFlag, search_tree::SearchObserver, strategy::*};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A transposition table that threads can share without locks. Each slot stores the position's
+/// hash XORed with the entry alongside the entry itself, so a slot torn by two threads writing at
+/// once fails its hash check and is ignored rather than misread.
+///
+/// Entries stay valid from one search to the next, so a table can be kept for a whole game. Each
+/// entry is tagged with the search that stored it, and entries left by earlier searches give way
+/// to new ones even if they were searched deeper, so stale positions don't fill up the table.
+#[derive(Debug)]
+pub struct TranspositionTable {
+    slots: Vec<(AtomicU64, AtomicU64)>,
+    /// The number of searches started, modulo 16, which new entries are tagged with.
+    generation: AtomicU8,
+}
+
+/// Alpha-beta search with iterative deepening spread over several threads in the Lazy SMP style:
+/// every thread searches the same root, sharing what it finds through a transposition table, and
+/// the helpers search one ply deeper on every other thread so they fill the table ahead of the
+/// main thread. Only the main thread's results are reported. Like AlphaBetaStrategy, a position at
+/// the depth limit is worth the value of the evaluator's best move there.
+///
+/// The transposition table is allocated by the first search and kept for the ones after it, so
+/// each move of a game starts from what the searches of the earlier moves found. Clones share it,
+/// and can search at the same time, e.g. one pondering while the other plays.
+#[derive(Clone)]
+pub struct LazySmpStrategy {
+    /// The number of threads, including the main one.
+    pub threads: usize,
+    pub max_depth: usize,
+    /// The number of transposition table slots, rounded up to a power of two.
+    pub table_size: usize,
+    table: Arc<Mutex<Option<Arc<TranspositionTable>>>>,
+}
+
+/// Whether a stored score is exact or only a bound from a cutoff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TableEntry {
+    /// Relative to the position, so wins count their distance from it rather than from the root.
+    score: i32,
+    depth: u16,
+    bound: Bound,
+    /// True if the search below the position saw the end of every line.
+    complete: bool,
+    /// The index of the best move in legal_moves().
+    best_move: Option<u8>,
+    /// The generation of the table when the entry was stored.
+    generation: u8,
+}
+
+/// The state of one thread's search.
+struct Worker<'a, 'o, G, E> {
+    table: &'a TranspositionTable,
+    evaluator: &'a E,
+    nodes: u64,
+    /// Cleared if any line was cut short by the depth limit.
+    complete: bool,
+    stop: &'a StopFlag,
+    /// Set once the main thread is done, to stop the helpers.
+    done: &'a StopFlag,
+    /// Only the main thread's worker has one.
+    observer: Option<&'a mut (dyn SearchObserver<G> + 'o)>,
+}
+
+/// Marks a slot as used, so an empty slot never matches a position.
+const OCCUPIED: u64 = 1 << 55;
+
+fn hash<G: Hash>(state: &G) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl TableEntry {
+    fn pack(&self) -> u64 {
+        let bound = match self.bound {
+            Bound::Exact => 0,
+            Bound::Lower => 1,
+            Bound::Upper => 2,
+        };
+        self.score as u32 as u64
+            | (self.depth as u64) << 32
+            | bound << 48
+            | (self.complete as u64) << 50
+            | ((self.generation & 0xf) as u64) << 51
+            | OCCUPIED
+            | (self.best_move.unwrap_or(u8::MAX) as u64) << 56
+    }
+
+    fn unpack(data: u64) -> Self {
+        let bound = match (data >> 48) & 3 {
+            0 => Bound::Exact,
+            1 => Bound::Lower,
+            _ => Bound::Upper,
+        };
+        let best_move = (data >> 56) as u8;
+        Self {
+            score: data as u32 as i32,
+            depth: (data >> 32) as u16,
+            bound,
+            complete: (data >> 50) & 1 == 1,
+            generation: ((data >> 51) & 0xf) as u8,
+            best_move: (best_move != u8::MAX).then_some(best_move),
+        }
+    }
+}
+
+impl TranspositionTable {
+    /// Returns an empty table with at least the given number of slots.
+    pub fn new(size: usize) -> Self {
+        let slots = (0..size.max(1).next_power_of_two())
+            .map(|_| (AtomicU64::new(0), AtomicU64::new(0)))
+            .collect();
+        Self {
+            slots,
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn clear(&self) {
+        for (check, data) in &self.slots {
+            check.store(0, Ordering::Relaxed);
+            data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Moves on to a new search, whose entries replace any stored by the searches before it.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed) & 0xf
+    }
+
+    fn slot(&self, hash: u64) -> &(AtomicU64, AtomicU64) {
+        &self.slots[hash as usize & (self.slots.len() - 1)]
+    }
+
+    fn probe(&self, hash: u64) -> Option<TableEntry> {
+        let (check, data) = self.slot(hash);
+        let data = data.load(Ordering::Relaxed);
+        (data & OCCUPIED != 0 && check.load(Ordering::Relaxed) ^ data == hash)
+            .then(|| TableEntry::unpack(data))
+    }
+
+    /// Stores the entry, replacing whatever was in its slot unless that was a deeper search of the
+    /// same position by the current search.
+    fn store(&self, hash: u64, entry: TableEntry) {
+        let generation = self.generation();
+        if self.probe(hash).is_some_and(|existing| {
+            existing.generation == generation && existing.depth > entry.depth
+        }) {
+            return;
+        }
+        let (check, data) = self.slot(hash);
+        let packed = TableEntry {
+            generation,
+            ..entry
+        }
+        .pack();
+        check.store(hash ^ packed, Ordering::Relaxed);
+        data.store(packed, Ordering::Relaxed);
+    }
+}
+
+/// Converts a win or loss score from distance to the root to distance to the position at ply.
+fn to_table(score: i32, ply: i32) -> i32 {
+    if score > WIN_THRESHOLD {
+        score + ply
+    } else if score < -WIN_THRESHOLD {
+        score - ply
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: i32) -> i32 {
+    if score > WIN_THRESHOLD {
+        score - ply
+    } else if score < -WIN_THRESHOLD {
+        score + ply
+    } else {
+        score
+    }
+}
+
+/// Returns the index of the best move first and the others in order.
+fn move_order(count: usize, best_move: Option<u8>) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..count).collect();
+    if let Some(best) = best_move.map(usize::from).filter(|&best| best < count) {
+        order[..=best].rotate_right(1);
+    }
+    order
+}
+
+impl<G, E> Worker<'_, '_, G, E>
+where
+    G: UndoMove + Hash,
+    E: Evaluator<G>,
+    E::Evaluation: EvaluationValue,
+{
+    fn is_stopped(&self) -> bool {
+        self.stop.is_stopped() || self.done.is_stopped()
+    }
+
+    /// Searches every root move depth plies deep and returns the best score and move index, trying
+    /// the previous best move first.
+    fn search_root(&mut self, state: &mut G, depth: usize, previous: usize) -> (i32, usize) {
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.enter(state, None, Some((-1.0, 1.0)));
+        }
+        let moves = state.legal_moves();
+        let player = state.current_player();
+        let mut alpha = -WIN;
+        let mut best = (-WIN, previous);
+        for index in move_order(moves.len(), u8::try_from(previous).ok()) {
+            let undo = state.do_move(&moves[index]);
+            let mov = Some(&moves[index]);
+            let score = if state.current_player() == player {
+                self.negamax(state, mov, depth - 1, 1, alpha, WIN)
+            } else {
+                -self.negamax(state, mov, depth - 1, 1, -WIN, -alpha)
+            };
+            state.undo_move(undo);
+            if score > best.0 {
+                best = (score, index);
+            }
+            alpha = alpha.max(score);
+        }
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.leave((!self.stop.is_stopped()).then(|| observed_value(best.0)));
+        }
+        best
+    }
+
+    /// Like AlphaBetaSearch::negamax(), but looks positions up in the shared table first and
+    /// stores what it finds there. Once stopped every node returns straight away, so the caller
+    /// has to throw the result away.
+    fn negamax(
+        &mut self,
+        state: &mut G,
+        mov: Option<&G::Move>,
+        depth: usize,
+        ply: i32,
+        alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if let Some(observer) = self.observer.as_deref_mut() {
+            let bounds = (observed_value(alpha), observed_value(beta));
+            observer.enter(state, mov, Some(bounds));
+        }
+        let score = self.search_node(state, depth, ply, alpha, beta);
+        let stopped = self.is_stopped();
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.leave((!stopped).then(|| observed_value(score)));
+        }
+        score
+    }
+
+    fn search_node(
+        &mut self,
+        state: &mut G,
+        depth: usize,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.is_stopped() {
+            return 0;
+        }
+        self.nodes += 1;
+        if let Some(score) = terminal_score(state, ply) {
+            return score;
+        }
+        if depth == 0 {
+            self.complete = false;
+            return leaf_score(state, self.evaluator);
+        }
+
+        let key = hash(state);
+        let entry = self.table.probe(key);
+        if let Some(entry) = entry.filter(|entry| entry.complete || entry.depth as usize >= depth) {
+            let score = from_table(entry.score, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if usable {
+                self.complete &= entry.complete;
+                return score;
+            }
+        }
+
+        let moves = state.legal_moves();
+        if moves.is_empty() {
+            return 0;
+        }
+        let outer_complete = std::mem::replace(&mut self.complete, true);
+        let original_alpha = alpha;
+        let player = state.current_player();
+        let mut best = (-WIN, 0);
+        let mut order =
+            move_order(moves.len(), entry.and_then(|entry| entry.best_move)).into_iter();
+        for index in order.by_ref() {
+            let undo = state.do_move(&moves[index]);
+            let mov = Some(&moves[index]);
+            let score = if state.current_player() == player {
+                self.negamax(state, mov, depth - 1, ply + 1, alpha, beta)
+            } else {
+                -self.negamax(state, mov, depth - 1, ply + 1, -beta, -alpha)
+            };
+            state.undo_move(undo);
+            if score > best.0 {
+                best = (score, index);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        if let Some(observer) = self.observer.as_deref_mut() {
+            for index in order {
+                observer.prune(state, &moves[index]);
+            }
+        }
+
+        let complete = self.complete;
+        self.complete = outer_complete && complete;
+        if !self.is_stopped() {
+            let bound = if best.0 <= original_alpha {
+                Bound::Upper
+            } else if best.0 >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.table.store(
+                key,
+                TableEntry {
+                    score: to_table(best.0, ply),
+                    depth: depth.min(u16::MAX as usize) as u16,
+                    bound,
+                    complete,
+                    best_move: u8::try_from(best.1).ok().filter(|&index| index != u8::MAX),
+                    generation: 0,
+                },
+            );
+        }
+        best.0
+    }
+}
+
+impl LazySmpStrategy {
+    pub fn with_threads(threads: usize) -> Self {
+        Self {
+            threads,
+            max_depth: usize::MAX,
+            table_size: 1 << 20,
+            table: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Searches like search(), telling the observer about every position the main thread
+    /// searches. Each of its iterations starts with begin_iteration(), so a TreeRecorder ends up
+    /// with the tree of the deepest one. Positions answered from the table have no children.
+    pub fn search_observed<G, E>(
+        &self,
+        state: &G,
+        evaluator: &E,
+        observer: &mut dyn SearchObserver<G>,
+    ) -> SearchInfo<G::Move>
+    where
+        G: UndoMove + Clone + Hash + Send + Sync,
+        E: Evaluator<G> + Sync,
+        E::Evaluation: EvaluationValue,
+    {
+        self.run(
+            state,
+            evaluator,
+            &StopFlag::new(),
+            &mut |_| {},
+            Some(observer),
+        )
+    }
+
+    /// Empties the kept transposition table, e.g. before a new game. Aging already keeps one
+    /// game's moves from crowding out the next, so this is only needed for repeatable searches.
+    pub fn clear_table(&self) {
+        if let Some(table) = self.table.lock().unwrap().as_ref() {
+            table.clear();
+        }
+    }
+
+    /// Returns the kept transposition table, replacing it with an empty one if table_size has
+    /// changed. The lock is only held while the table is fetched, not for the search.
+    fn shared_table(&self) -> Arc<TranspositionTable> {
+        let size = self.table_size.max(1).next_power_of_two();
+        let mut kept = self.table.lock().unwrap();
+        match kept.as_ref() {
+            Some(table) if table.len() == size => Arc::clone(table),
+            _ => Arc::clone(kept.insert(Arc::new(TranspositionTable::new(size)))),
+        }
+    }
+
+    fn run<G, E>(
+        &self,
+        state: &G,
+        evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+        mut observer: Option<&mut dyn SearchObserver<G>>,
+    ) -> SearchInfo<G::Move>
+    where
+        G: UndoMove + Clone + Hash + Send + Sync,
+        E: Evaluator<G> + Sync,
+        E::Evaluation: EvaluationValue,
+    {
+        let start = Instant::now();
+        let player = state.current_player();
+        let mut info = SearchInfo {
+            principal_variation: Vec::new(),
+            score: Some(Score::Result(state.game_result())),
+            depth: 0,
+            nodes: 0,
+            elapsed: Duration::ZERO,
+        };
+        if state.game_result().is_determined() || state.legal_moves().is_empty() {
+            if let Some(observer) = observer {
+                let value = state.game_result().score(player);
+                observer.begin_iteration();
+                observer.enter(state, None, None);
+                observer.leave(Some(value as f64));
+            }
+            report(&info);
+            return info;
+        }
+
+        let table = self.shared_table();
+        table.new_search();
+        let table = &*table;
+        let done = StopFlag::new();
+        let helper_nodes = AtomicU64::new(0);
+        let max_depth = self.max_depth.max(1);
+        thread::scope(|scope| {
+            for thread in 1..self.threads {
+                let (done, helper_nodes) = (&done, &helper_nodes);
+                let mut state = state.clone();
+                scope.spawn(move || {
+                    let mut previous = 0;
+                    for depth in 1..=max_depth {
+                        let mut worker = Worker {
+                            table,
+                            evaluator,
+                            nodes: 0,
+                            complete: true,
+                            stop,
+                            done,
+                            observer: None,
+                        };
+                        let depth = (depth + thread % 2).min(max_depth);
+                        let (score, best) = worker.search_root(&mut state, depth, previous);
+                        helper_nodes.fetch_add(worker.nodes, Ordering::Relaxed);
+                        if worker.is_stopped() || worker.complete || score.abs() > WIN_THRESHOLD {
+                            break;
+                        }
+                        previous = best;
+                    }
+                });
+            }
+
+            let mut state = state.clone();
+            let mut nodes = 0;
+            let mut previous = 0;
+            for depth in 1..=max_depth {
+                if let Some(observer) = observer.as_deref_mut() {
+                    observer.begin_iteration();
+                }
+                let mut worker = Worker {
+                    table,
+                    evaluator,
+                    nodes: 0,
+                    complete: true,
+                    stop,
+                    done: &done,
+                    observer: observer.as_deref_mut(),
+                };
+                let (score, best) = worker.search_root(&mut state, depth, previous);
+                nodes += worker.nodes;
+                info.nodes = nodes + helper_nodes.load(Ordering::Relaxed);
+                info.elapsed = start.elapsed();
+                if stop.is_stopped() {
+                    if info.principal_variation.is_empty() {
+                        info.principal_variation =
+                            state.legal_moves().into_iter().take(1).collect();
+                        info.score = None;
+                    }
+                    break;
+                }
+
+                let mov = state.legal_moves().swap_remove(best);
+                info.principal_variation = Self::principal_variation(&state, mov, table, depth);
+                info.depth = depth;
+                info.score = Some(alpha_beta_score(score, player, worker.complete));
+                report(&info);
+                if worker.complete || score.abs() > WIN_THRESHOLD {
+                    break;
+                }
+                previous = best;
+            }
+            done.stop();
+        });
+        info
+    }
+
+    /// Returns the line starting with the given move and continuing with the best moves stored in
+    /// the table.
+    fn principal_variation<G>(
+        state: &G,
+        first: G::Move,
+        table: &TranspositionTable,
+        depth: usize,
+    ) -> Vec<G::Move>
+    where
+        G: GameState + Hash,
+    {
+        let mut current = state.next_state(&first);
+        let mut line = vec![first];
+        while line.len() < depth && !current.game_result().is_determined() {
+            let Some(best) = table
+                .probe(hash(&current))
+                .and_then(|entry| entry.best_move)
+            else {
+                break;
+            };
+            let Some(mov) = current.legal_moves().into_iter().nth(best as usize) else {
+                break;
+            };
+            current.apply_move(&mov);
+            line.push(mov);
+        }
+        line
+    }
+}
+
+impl Debug for LazySmpStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazySmpStrategy")
+            .field("threads", &self.threads)
+            .field("max_depth", &self.max_depth)
+            .field("table_size", &self.table_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<G, E> Strategy<G, E> for LazySmpStrategy
+where
+    G: UndoMove + Clone + Hash + Send + Sync,
+    E: Evaluator<G> + Sync,
+    E::Evaluation: EvaluationValue,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Uses one thread per core and searches until the result is known.
+    fn new() -> Self {
+        Self::with_threads(thread::available_parallelism().map_or(1, |threads| threads.get()))
+    }
+
+    fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    /// Reports after every iteration of the main thread.
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search_until(state, evaluator, &StopFlag::new(), report)
+    }
+
+    /// When stopped, the unfinished iteration is thrown away and the last one is returned. If not
+    /// even the first iteration finished, the first legal move is returned without a score.
+    fn search_until(
+        &self,
+        state: &G,
+        evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.run(state, evaluator, stop, report, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LazySmpStrategy;
+    use crate::evaluate::TerminalStateEvaluator;
+    use crate::game::{GameState, MoveNotation};
+    use crate::mnk::{LineEvaluator, MnkGame};
+    use crate::search::StopFlag;
+    use crate::strategy::{AlphaBetaStrategy, Score, Strategy};
+    use crate::tic_tac_toe::{perfect_moves, BoardState, PerfectPlayTable};
+    use std::thread;
+
+    #[test]
+    fn test_lazy_smp() {
+        // One strategy for every position, so later searches start from the table the earlier
+        // ones left behind.
+        let strategy = LazySmpStrategy {
+            table_size: 1 << 12,
+            ..LazySmpStrategy::with_threads(4)
+        };
+        let table = PerfectPlayTable::new();
+        for (board, result) in table.iter() {
+            if board.game_result().is_determined() {
+                continue;
+            }
+            let info = strategy.search(board, &TerminalStateEvaluator, &mut |_| {});
+            assert_eq!(info.score, Some(Score::Result(*result)), "{}", board);
+            assert!(perfect_moves(board).contains(info.best_move().unwrap()));
+            let mut end = *board;
+            for mov in &info.principal_variation {
+                end.try_apply_move(mov).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_table_kept_between_searches() {
+        let strategy = LazySmpStrategy {
+            table_size: 1 << 16,
+            ..LazySmpStrategy::with_threads(1)
+        };
+        let board = BoardState::new();
+        let first = strategy.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        let second = strategy.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        assert_eq!(second.score, first.score);
+        assert!(second.nodes < first.nodes / 10);
+
+        // A clone shares the table, and clearing it starts over.
+        let clone = strategy.clone();
+        clone.clear_table();
+        let third = strategy.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        assert_eq!(third.nodes, first.nodes);
+    }
+
+    #[test]
+    fn test_depth_limit_uses_evaluator() {
+        // With one thread and nothing in the table, the search agrees with plain alpha-beta.
+        let mut game = MnkGame::<5, 5, 4>::new();
+        for mov in ["c3", "b2", "c4"] {
+            game.apply_move(&game.parse_move(mov).unwrap());
+            let strategy = LazySmpStrategy {
+                max_depth: 2,
+                ..LazySmpStrategy::with_threads(1)
+            };
+            let info = strategy.search(&game, &LineEvaluator, &mut |_| {});
+            let expected =
+                AlphaBetaStrategy::with_depth(2).search(&game, &LineEvaluator, &mut |_| {});
+            assert_eq!(info.score, expected.score, "\n{}", game);
+            assert!(matches!(info.score, Some(Score::Value(value)) if value != 0.0));
+        }
+    }
+
+    #[test]
+    fn test_clones_search_at_once() {
+        // A clone searching in the background, as when pondering, doesn't hold the table, so a
+        // search on the game thread finishes while it runs.
+        let strategy = LazySmpStrategy {
+            table_size: 1 << 12,
+            ..LazySmpStrategy::with_threads(1)
+        };
+        let game = MnkGame::<5, 5, 4>::new();
+        let stop = StopFlag::new();
+        thread::scope(|scope| {
+            let pondering = strategy.clone();
+            let (game, stop) = (&game, &stop);
+            let background = scope
+                .spawn(move || pondering.search_until(game, &LineEvaluator, stop, &mut |_| {}));
+            let quick = LazySmpStrategy {
+                max_depth: 2,
+                ..strategy.clone()
+            };
+            let info = quick.search(game, &LineEvaluator, &mut |_| {});
+            assert_eq!(info.depth, 2);
+            stop.stop();
+            assert!(background.join().unwrap().best_move().is_some());
+        });
+    }
+}
@@ -1,4 +1,22 @@
+pub mod book;
+pub mod chance;
+pub mod chess;
+pub mod connect_four;
 pub mod evaluate;
 pub mod game;
+pub mod lazy_smp;
+pub mod mancala;
+pub mod mcts;
+pub mod mnk;
+pub mod multiplayer;
+pub mod nim;
+pub mod pig;
+pub mod proof_number;
+pub mod record;
+pub mod retrograde;
+pub mod search;
+pub mod search_tree;
 pub mod strategy;
+pub mod symmetry;
 pub mod tic_tac_toe;
+pub mod uci;
@@ -1,3 +1,4 @@
+#![allow(unused_imports, unused_variables)]
 use minimax_game::{game::GamePlayer, tic_tac_toe::BoardState};
 fn main() {
     let board = BoardState::new();
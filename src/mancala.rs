@@ -0,0 +1,269 @@
+use crate::game::*;
+use std::fmt::Display;
+
+/// Kalah, the most common mancala game. Each player has a row of PITS pits and a store to the
+/// right of it. A move takes every seed from one of the mover's pits and sows them one by one
+/// into the following pits counterclockwise, through the mover's own store but skipping the
+/// opponent's. If the last seed lands in the mover's store they move again. If it lands in one of
+/// their own pits that was empty and the opposite pit has seeds, both pits are captured into
+/// their store. Once either row is empty the game is over, and each player adds the seeds left in
+/// their row to their store.
+///
+/// The final score is the difference between the stores, so the game has a real margin of
+/// victory for MarginEvaluator to maximise. Small boards with few seeds can be solved outright.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Kalah<const PITS: usize> {
+    /// The seeds in each player's pits from their left, indexed by Player::index(). Pit i of one
+    /// player is opposite pit PITS - 1 - i of the other.
+    pits: [[u8; PITS]; 2],
+    stores: [u8; 2],
+    to_move: Player,
+}
+
+/// Sows the seeds from the pit with the given number, counting from 0 at the mover's left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KalahMove(pub u8);
+
+impl<const PITS: usize> Kalah<PITS> {
+    /// The usual number of seeds per pit.
+    pub const DEFAULT_SEEDS: u8 = 4;
+
+    /// Returns a new game with the given number of seeds in every pit and Player One to move.
+    /// Panics if there are no pits or more than 255 seeds in all.
+    pub fn with_seeds(seeds: u8) -> Self {
+        assert!(PITS > 0, "there has to be at least one pit");
+        assert!(
+            2 * PITS * seeds as usize <= u8::MAX as usize,
+            "there can be at most 255 seeds"
+        );
+        Self {
+            pits: [[seeds; PITS]; 2],
+            stores: [0; 2],
+            to_move: Player::One,
+        }
+    }
+
+    /// Returns the seeds in the given player's pit, counting from their left.
+    pub fn seeds(&self, player: Player, pit: usize) -> u8 {
+        self.pits[player.index()][pit]
+    }
+
+    /// Returns the seeds in the given player's store.
+    pub fn store(&self, player: Player) -> u8 {
+        self.stores[player.index()]
+    }
+
+    fn is_over(&self) -> bool {
+        self.pits
+            .iter()
+            .all(|row| row.iter().all(|&seeds| seeds == 0))
+    }
+}
+
+impl<const PITS: usize> Default for Kalah<PITS> {
+    fn default() -> Self {
+        Self::with_seeds(Self::DEFAULT_SEEDS)
+    }
+}
+
+impl<const PITS: usize> GameState for Kalah<PITS> {
+    type Move = KalahMove;
+
+    /// Returns a game with four seeds in every pit.
+    fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every one of the mover's pits that has seeds in it.
+    fn legal_moves(&self) -> Vec<KalahMove> {
+        (0..PITS)
+            .filter(|&pit| self.pits[self.to_move.index()][pit] > 0)
+            .map(|pit| KalahMove(pit as u8))
+            .collect()
+    }
+
+    fn apply_move(&mut self, mov: &KalahMove) {
+        let mover = self.to_move.index();
+        let mut seeds = std::mem::take(&mut self.pits[mover][mov.0 as usize]);
+        // Positions around the board from the mover's point of view: their pits, their store,
+        // then the opponent's pits. The opponent's store is skipped.
+        let mut position = mov.0 as usize;
+        while seeds > 0 {
+            position = (position + 1) % (2 * PITS + 1);
+            if position < PITS {
+                self.pits[mover][position] += 1;
+            } else if position == PITS {
+                self.stores[mover] += 1;
+            } else {
+                self.pits[1 - mover][position - PITS - 1] += 1;
+            }
+            seeds -= 1;
+        }
+
+        if position < PITS && self.pits[mover][position] == 1 {
+            let opposite = PITS - 1 - position;
+            let captured = self.pits[1 - mover][opposite];
+            if captured > 0 {
+                self.stores[mover] += captured + 1;
+                self.pits[mover][position] = 0;
+                self.pits[1 - mover][opposite] = 0;
+            }
+        }
+        if self
+            .pits
+            .iter()
+            .any(|row| row.iter().all(|&seeds| seeds == 0))
+        {
+            for player in 0..2 {
+                self.stores[player] += self.pits[player].iter().sum::<u8>();
+                self.pits[player] = [0; PITS];
+            }
+        }
+        if position != PITS {
+            self.to_move.flip_player();
+        }
+    }
+
+    fn next_state(&self, mov: &KalahMove) -> Self {
+        let mut next = *self;
+        next.apply_move(mov);
+        next
+    }
+
+    fn validate_move(&self, mov: &KalahMove) -> Result<(), MoveError> {
+        if self.is_over() {
+            Err(MoveError::GameOver)
+        } else if mov.0 as usize >= PITS {
+            Err(MoveError::OutOfRange)
+        } else if self.pits[self.to_move.index()][mov.0 as usize] == 0 {
+            Err(MoveError::Illegal)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn game_result(&self) -> GameResult {
+        match self.final_score() {
+            Some(score) => GameResult::from_final_score(score),
+            None => GameResult::Undetermined,
+        }
+    }
+
+    fn current_player(&self) -> Player {
+        self.to_move
+    }
+
+    /// Player One's store minus Player Two's.
+    fn final_score(&self) -> Option<i32> {
+        self.is_over()
+            .then(|| i32::from(self.stores[0]) - i32::from(self.stores[1]))
+    }
+}
+
+impl<const PITS: usize> UndoMove for Kalah<PITS> {
+    type Undo = Self;
+
+    fn do_move(&mut self, mov: &KalahMove) -> Self {
+        let previous = *self;
+        self.apply_move(mov);
+        previous
+    }
+
+    fn undo_move(&mut self, undo: Self) {
+        *self = undo;
+    }
+}
+
+impl<const PITS: usize> MoveNotation for Kalah<PITS> {
+    /// Parses the pit number, from 1 at the mover's left.
+    fn parse_move(&self, s: &str) -> Result<KalahMove, ParseMoveError> {
+        let pit: usize = s
+            .parse()
+            .ok()
+            .filter(|pit| (1..=PITS).contains(pit))
+            .ok_or_else(|| ParseMoveError::Invalid(s.to_string()))?;
+        let mov = KalahMove(pit as u8 - 1);
+        match self.validate_move(&mov) {
+            Ok(()) => Ok(mov),
+            Err(_) => Err(ParseMoveError::Illegal(s.to_string())),
+        }
+    }
+
+    fn format_move(&self, mov: &KalahMove) -> String {
+        (mov.0 + 1).to_string()
+    }
+}
+
+impl<const PITS: usize> Display for Kalah<PITS> {
+    /// Draws the board as Player One sees it: Player Two's pits along the top with their store on
+    /// the left, and Player One's pits along the bottom with their store on the right.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "   ")?;
+        for pit in self.pits[1].iter().rev() {
+            write!(f, "{:>3}", pit)?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>3}{}{:>3}",
+            self.stores[1],
+            " ".repeat(3 * PITS),
+            self.stores[0]
+        )?;
+        write!(f, "   ")?;
+        for pit in &self.pits[0] {
+            write!(f, "{:>3}", pit)?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kalah, KalahMove};
+    use crate::game::{GameResult, GameState, MoveError, MoveNotation, Player};
+
+    #[test]
+    fn test_kalah_rules() {
+        let game = Kalah::<6>::new();
+        assert_eq!(game.legal_moves().len(), 6);
+
+        // Four seeds from the third pit end in the store, so Player One moves again.
+        let again = game.next_state(&game.parse_move("3").unwrap());
+        assert_eq!(again.current_player(), Player::One);
+        assert_eq!(again.store(Player::One), 1);
+        assert_eq!(again.seeds(Player::One, 2), 0);
+        assert_eq!(again.seeds(Player::One, 5), 5);
+        assert_eq!(again.validate_move(&KalahMove(2)), Err(MoveError::Illegal));
+
+        // Sowing past the store reaches the opponent's pits, and passes the turn.
+        let passed = again.next_state(&KalahMove(5));
+        assert_eq!(passed.current_player(), Player::Two);
+        assert_eq!(passed.store(Player::One), 2);
+        assert_eq!(passed.seeds(Player::Two, 3), 5);
+        assert_eq!(passed.seeds(Player::Two, 4), 4);
+
+        // Ending in an empty pit of one's own captures the seeds opposite.
+        let mut game = Kalah::<3>::with_seeds(1);
+        for mov in ["3", "2"] {
+            game.apply_move(&game.parse_move(mov).unwrap());
+        }
+        assert_eq!(game.store(Player::One), 3);
+        assert_eq!(game.seeds(Player::One, 2), 0);
+        assert_eq!(game.seeds(Player::Two, 0), 0);
+        assert_eq!(game.current_player(), Player::Two);
+
+        // Emptying a row ends the game, and whatever is left goes to its owner's store.
+        for mov in ["2", "1", "3"] {
+            assert_eq!(game.final_score(), None);
+            game.apply_move(&game.parse_move(mov).unwrap());
+        }
+        assert_eq!(game.legal_moves(), vec![]);
+        assert_eq!(game.store(Player::One), 5);
+        assert_eq!(game.store(Player::Two), 1);
+        assert_eq!(game.final_score(), Some(4));
+        assert_eq!(game.game_result(), GameResult::Win(Player::One));
+        assert_eq!(game.validate_move(&KalahMove(0)), Err(MoveError::GameOver));
+        assert!(Kalah::<3>::new().parse_move("4").is_err());
+    }
+}
@@ -0,0 +1,1623 @@
+use crate::{
+    chance::StochasticGame,
+    evaluate::*,
+    game::*,
+    search::StopFlag,
+    search_tree::{SearchTree, TreeNode},
+    strategy::*,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    thread,
+    time::Instant,
+};
+
+/// Monte Carlo tree search with the UCT selection rule and uniformly random playouts. It doesn't
+/// consult the evaluator, so it works for any game without a heuristic.
+#[derive(Debug, Clone, Copy)]
+pub struct MctsStrategy {
+    pub iterations: usize,
+    /// The weight of the exploration term of UCT. The square root of two is the usual choice.
+    pub exploration: f64,
+    /// The seed of the random number generator, so that searches can be repeated.
+    pub seed: u64,
+    /// Whether to blend in all-moves-as-first statistics, and how quickly to fade them out.
+    pub rave: Option<RaveSchedule>,
+    /// Turns on MCTS-Solver: wins and losses that are proven, starting from finished games, are
+    /// backed up the tree like in minimax. Proven losses are never selected again, a proven win
+    /// is always chosen over better averages, and the search ends once the root is solved.
+    pub solver: bool,
+}
+
+/// How much weight RAVE gives the all-moves-as-first (AMAF) value of a move over its own mean
+/// reward. AMAF credits a move with the result of every playout in which its player made it later
+/// on, so it has many more samples early on but is biased; the weight, beta, falls towards 0 as
+/// the move's own visits grow. This only helps in games where a move means much the same thing
+/// whenever it is played, like placing a stone on a given point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RaveSchedule {
+    /// beta = sqrt(k / (3n + k)) for n visits, so the two values count equally after k visits.
+    Equivalence(f64),
+    /// beta = m / (n + m + 4b²nm) for n visits and m AMAF visits, which minimises the mean squared
+    /// error if the AMAF values are off by a bias of b.
+    MinimumError(f64),
+}
+
+/// MctsStrategy that keeps its tree between moves. It follows the game through notify_move(), so
+/// once its own move and the opponent's reply have been played, the search continues from the
+/// matching grandchild with all of its statistics instead of starting from scratch.
+///
+/// Clones share the kept tree. Handing a clone to Ponder::start() makes the ponder search grow the
+/// same tree, so on a ponder hit nothing it found is lost.
+#[derive(Clone)]
+pub struct ReusingMctsStrategy<G>
+where
+    G: GameState,
+{
+    /// The iterations run per search, on top of whatever the kept tree already has.
+    pub settings: MctsStrategy,
+    tree: Arc<Mutex<Option<MctsTree<G>>>>,
+}
+
+/// How ParallelMctsStrategy splits the work between threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parallelism {
+    /// Every thread grows its own tree and the visit counts at the root are added up at the end.
+    Root,
+    /// The threads grow one shared tree. Each thread adds a virtual loss to the nodes on its path
+    /// while its playout runs, which steers the other threads towards different paths.
+    Tree,
+}
+
+/// MCTS spread over several threads, with the same iteration budget as MctsStrategy shared
+/// between them.
+#[derive(Debug, Clone, Copy)]
+pub struct ParallelMctsStrategy {
+    pub threads: usize,
+    pub parallelism: Parallelism,
+    /// The total number of iterations over all threads.
+    pub iterations: usize,
+    pub exploration: f64,
+    /// Thread i seeds its random number generator with seed + i.
+    pub seed: u64,
+    /// Makes tree parallelisation repeatable for tests by running the threads' iterations in
+    /// lockstep on the calling thread: every thread selects a path, then every thread plays out
+    /// and backs up its result, so the virtual losses still take effect. Root parallelisation is
+    /// repeatable anyway.
+    pub deterministic: bool,
+}
+
+/// MCTS for games with chance events. The tree only branches on the players' moves: every
+/// iteration replays them from the root with freshly sampled chance outcomes, so the statistics of
+/// a node average over everything chance could have done on the way there ("open loop"). Which
+/// moves are legal, and who makes them, can differ from one iteration to the next, so children
+/// are only considered when their move is legal in the state the iteration reached.
+#[derive(Debug, Clone, Copy)]
+pub struct OpenLoopMctsStrategy {
+    pub iterations: usize,
+    pub exploration: f64,
+    pub seed: u64,
+}
+
+/// A search tree for MCTS, stored as a flat list of nodes with the root first.
+#[derive(Debug, Clone)]
+pub struct MctsTree<G>
+where
+    G: GameState,
+{
+    root: G,
+    nodes: Vec<MctsNode<G::Move>>,
+}
+
+#[derive(Debug, Clone)]
+struct MctsNode<M> {
+    /// The move that led here, None for the root.
+    mov: Option<M>,
+    parent: Option<usize>,
+    /// The player who made the move, whose point of view the rewards are from.
+    player: Player,
+    children: Vec<usize>,
+    /// Moves that haven't been given a child yet.
+    untried: Vec<M>,
+    visits: u64,
+    reward: f64,
+    /// AMAF statistics of the move that led here, for the player who made it.
+    amaf_visits: u64,
+    amaf_reward: f64,
+    /// The winner, if the position is proven to be won by one of the players. Finished games are
+    /// always marked; only the solver proves the positions above them.
+    proven: Option<Player>,
+}
+
+/// A node of a tree shared between threads. Children are created all at once by whichever thread
+/// first reaches the node, and the statistics are atomic, so no locks are needed.
+struct SharedNode<M> {
+    mov: Option<M>,
+    player: Player,
+    children: OnceLock<Vec<SharedNode<M>>>,
+    visits: AtomicU64,
+    /// Half points: 2 for a win, 1 for a draw and 0 for a loss.
+    half_points: AtomicU64,
+    /// The number of threads whose playout through this node hasn't been backed up yet.
+    virtual_losses: AtomicU64,
+}
+
+/// A node of an open-loop tree. The rewards are for whoever made the move in each iteration.
+#[derive(Debug, Clone)]
+struct OpenLoopNode<M> {
+    mov: Option<M>,
+    children: Vec<usize>,
+    visits: u64,
+    reward: f64,
+}
+
+/// The visits and total reward of one root move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveStatistics<M> {
+    pub mov: M,
+    pub visits: u64,
+    /// The sum of the rewards for the player making the move: 1 for a win, 0.5 for a draw and 0
+    /// for a loss.
+    pub reward: f64,
+}
+
+/// Returns the reward for the given player: 1 for a win, 0.5 for a draw and 0 for a loss.
+fn reward(result: GameResult, player: Player) -> f64 {
+    (result.score(player) as f64 + 1.0) / 2.0
+}
+
+/// The UCT value of a child. Unvisited children come first.
+fn uct(reward: f64, visits: u64, parent_visits: u64, exploration: f64) -> f64 {
+    if visits == 0 {
+        return f64::INFINITY;
+    }
+    let visits = visits as f64;
+    reward / visits + exploration * ((parent_visits.max(1) as f64).ln() / visits).sqrt()
+}
+
+/// Plays uniformly random moves until the game ends and returns the result. The moves are added
+/// to the trace along with the players who made them.
+fn playout<G, R>(mut state: G, rng: &mut R, trace: &mut Vec<(Player, G::Move)>) -> GameResult
+where
+    G: GameState,
+    G::Move: Clone,
+    R: Rng + ?Sized,
+{
+    let mut moves = Vec::new();
+    loop {
+        let result = state.game_result();
+        if result.is_determined() {
+            return result;
+        }
+        moves.clear();
+        state.legal_moves_into(&mut moves);
+        let Some(mov) = moves.choose(rng) else {
+            return GameResult::Draw;
+        };
+        trace.push((state.current_player(), mov.clone()));
+        state.apply_move(mov);
+    }
+}
+
+/// Like playout(), but samples an outcome at every chance node.
+fn stochastic_playout<G, R>(mut state: G, rng: &mut R) -> GameResult
+where
+    G: StochasticGame,
+    R: Rng + ?Sized,
+{
+    loop {
+        let result = state.game_result();
+        if result.is_determined() {
+            return result;
+        }
+        if state.is_chance_node() {
+            state = state.sample_outcome(rng);
+            continue;
+        }
+        let moves = state.legal_moves();
+        let Some(mov) = moves.choose(rng) else {
+            return GameResult::Draw;
+        };
+        state = state.next_state(mov);
+    }
+}
+
+impl RaveSchedule {
+    /// Returns the weight of the AMAF value of a move with the given visits and AMAF visits.
+    pub fn beta(&self, visits: u64, amaf_visits: u64) -> f64 {
+        let (n, m) = (visits as f64, amaf_visits as f64);
+        match *self {
+            RaveSchedule::Equivalence(k) => (k / (3.0 * n + k)).sqrt(),
+            RaveSchedule::MinimumError(bias) => {
+                if m == 0.0 {
+                    0.0
+                } else {
+                    m / (n + m + 4.0 * bias * bias * n * m)
+                }
+            }
+        }
+    }
+}
+
+/// Turns the root moves' statistics into a report: the most visited move is chosen and its mean
+/// reward, rescaled to between -1 and 1, is the score.
+fn statistics_info<M>(
+    statistics: &[MoveStatistics<M>],
+    principal_variation: Vec<M>,
+    nodes: u64,
+    start: Instant,
+) -> SearchInfo<M> {
+    let score = statistics
+        .iter()
+        .max_by_key(|statistics| statistics.visits)
+        .filter(|statistics| statistics.visits > 0)
+        .map(|best| Score::Value(2.0 * best.reward / best.visits as f64 - 1.0));
+    SearchInfo {
+        depth: principal_variation.len(),
+        principal_variation,
+        score,
+        nodes,
+        elapsed: start.elapsed(),
+    }
+}
+
+impl<G> MctsTree<G>
+where
+    G: GameState + Clone,
+    G::Move: Clone,
+{
+    /// Returns a tree containing only the root.
+    pub fn new(root: G) -> Self {
+        let untried = if root.game_result().is_determined() {
+            Vec::new()
+        } else {
+            root.legal_moves()
+        };
+        let player = root.current_player().other_player();
+        let proven = match root.game_result() {
+            GameResult::Win(winner) => Some(winner),
+            _ => None,
+        };
+        Self {
+            root,
+            nodes: vec![MctsNode {
+                mov: None,
+                parent: None,
+                player,
+                children: Vec::new(),
+                untried,
+                visits: 0,
+                reward: 0.0,
+                amaf_visits: 0,
+                amaf_reward: 0.0,
+                proven,
+            }],
+        }
+    }
+
+    pub fn root(&self) -> &G {
+        &self.root
+    }
+
+    /// Returns the number of nodes in the tree.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the number of iterations that have passed through the root.
+    pub fn visits(&self) -> u64 {
+        self.nodes[0].visits
+    }
+
+    /// Returns the selection value of a node: its UCT value, with its mean reward blended with
+    /// its AMAF value if RAVE is on.
+    fn selection_value(&self, index: usize, parent_visits: u64, settings: &MctsStrategy) -> f64 {
+        let node = &self.nodes[index];
+        let value = uct(
+            node.reward,
+            node.visits,
+            parent_visits,
+            settings.exploration,
+        );
+        match settings.rave {
+            Some(schedule) if node.visits > 0 && node.amaf_visits > 0 => {
+                let beta = schedule.beta(node.visits, node.amaf_visits);
+                let mean = node.reward / node.visits as f64;
+                let amaf = node.amaf_reward / node.amaf_visits as f64;
+                value + beta * (amaf - mean)
+            }
+            _ => value,
+        }
+    }
+
+    /// Returns the result of the game if the root has been proven a win for either player.
+    pub fn proven_result(&self) -> Option<GameResult> {
+        self.nodes[0].proven.map(GameResult::Win)
+    }
+
+    /// Runs one iteration: selects a path with UCT, adds one new node at its end, plays out a
+    /// random game from there and backs up the result along the path. With RAVE, every node on
+    /// the path also credits its children whose moves were made later on by the same player. With
+    /// the solver, a solved root is left alone.
+    pub fn iterate<R: Rng + ?Sized>(&mut self, settings: &MctsStrategy, rng: &mut R)
+    where
+        G::Move: PartialEq,
+    {
+        if settings.solver && self.nodes[0].proven.is_some() {
+            return;
+        }
+        let mut path = vec![0];
+        let mut trace = Vec::new();
+        let mut index = 0;
+        let mut state = self.root.clone();
+        while self.nodes[index].untried.is_empty() && !self.nodes[index].children.is_empty() {
+            let parent_visits = self.nodes[index].visits;
+            let mover = state.current_player();
+            index = *self.nodes[index]
+                .children
+                .iter()
+                .filter(|&&child| {
+                    !settings.solver
+                        || self.nodes[child]
+                            .proven
+                            .is_none_or(|winner| winner == mover)
+                })
+                .max_by(|&&a, &&b| {
+                    self.selection_value(a, parent_visits, settings)
+                        .total_cmp(&self.selection_value(b, parent_visits, settings))
+                })
+                .expect("a node whose moves all lose is solved");
+            let node = &self.nodes[index];
+            let mov = node.mov.as_ref().unwrap();
+            trace.push((node.player, mov.clone()));
+            state.apply_move(mov);
+            path.push(index);
+        }
+
+        if !self.nodes[index].untried.is_empty() {
+            let untried = &mut self.nodes[index].untried;
+            let mov = untried.swap_remove(rng.gen_range(0..untried.len()));
+            let player = state.current_player();
+            state.apply_move(&mov);
+            let result = state.game_result();
+            let untried = if result.is_determined() {
+                Vec::new()
+            } else {
+                state.legal_moves()
+            };
+            trace.push((player, mov.clone()));
+            self.nodes.push(MctsNode {
+                mov: Some(mov),
+                parent: Some(index),
+                player,
+                children: Vec::new(),
+                untried,
+                visits: 0,
+                reward: 0.0,
+                amaf_visits: 0,
+                amaf_reward: 0.0,
+                proven: match result {
+                    GameResult::Win(winner) => Some(winner),
+                    _ => None,
+                },
+            });
+            let child = self.nodes.len() - 1;
+            self.nodes[index].children.push(child);
+            path.push(child);
+        }
+
+        let result = playout(state, rng, &mut trace);
+        for (depth, &index) in path.iter().enumerate() {
+            let node = &mut self.nodes[index];
+            node.visits += 1;
+            node.reward += reward(result, node.player);
+            if settings.rave.is_none() {
+                continue;
+            }
+            // The moves made after this node are the ones from trace[depth] on.
+            let later = &trace[depth..];
+            for child in self.nodes[index].children.clone() {
+                let child = &mut self.nodes[child];
+                let mov = child.mov.as_ref().unwrap();
+                if later
+                    .iter()
+                    .any(|(player, played)| *player == child.player && played == mov)
+                {
+                    child.amaf_visits += 1;
+                    child.amaf_reward += reward(result, child.player);
+                }
+            }
+        }
+
+        if settings.solver {
+            for &index in path.iter().rev() {
+                if !self.prove(index) {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Tries to prove the node from its children: it is won by the player to move if one of their
+    /// moves is, and lost if every move has been tried and all of them are. Returns true if the
+    /// node is proven.
+    fn prove(&mut self, index: usize) -> bool {
+        let node = &self.nodes[index];
+        if node.proven.is_some() {
+            return true;
+        }
+        let Some(&first) = node.children.first() else {
+            return false;
+        };
+        let mover = self.nodes[first].player;
+        let winners = node.children.iter().map(|&child| self.nodes[child].proven);
+        let proven = if winners.clone().any(|winner| winner == Some(mover)) {
+            Some(mover)
+        } else if node.untried.is_empty()
+            && winners
+                .clone()
+                .all(|winner| winner.is_some_and(|w| w != mover))
+        {
+            Some(mover.other_player())
+        } else {
+            None
+        };
+        self.nodes[index].proven = proven;
+        proven.is_some()
+    }
+
+    /// Returns the child to play from the node: a proven win if there is one, otherwise the most
+    /// visited child that isn't a proven loss, or the most visited one if they all are.
+    fn best_child(&self, index: usize) -> Option<usize> {
+        let children = &self.nodes[index].children;
+        let mover = self.nodes[*children.first()?].player;
+        let most_visited = |losing: bool| {
+            children
+                .iter()
+                .copied()
+                .filter(|&child| {
+                    losing
+                        || self.nodes[child]
+                            .proven
+                            .is_none_or(|winner| winner == mover)
+                })
+                .max_by_key(|&child| self.nodes[child].visits)
+        };
+        children
+            .iter()
+            .copied()
+            .find(|&child| self.nodes[child].proven == Some(mover))
+            .or_else(|| most_visited(false))
+            .or_else(|| most_visited(true))
+    }
+
+    /// Moves the root to the position after the given move, keeping the subtree below it and
+    /// dropping the rest. Returns false if the move had no node yet, in which case the tree starts
+    /// again from the new position.
+    pub fn advance(&mut self, mov: &G::Move) -> bool
+    where
+        G::Move: PartialEq,
+    {
+        let root = self.root.next_state(mov);
+        let Some(&child) = self.nodes[0]
+            .children
+            .iter()
+            .find(|&&child| self.nodes[child].mov.as_ref() == Some(mov))
+        else {
+            *self = Self::new(root);
+            return false;
+        };
+
+        // Copy the subtree breadth first, so every parent is copied before its children.
+        let mut nodes = Vec::new();
+        let mut queue = VecDeque::from([(child, None)]);
+        while let Some((index, parent)) = queue.pop_front() {
+            let mut node = self.nodes[index].clone();
+            let children = std::mem::take(&mut node.children);
+            node.parent = parent;
+            if parent.is_none() {
+                node.mov = None;
+            }
+            let new_index = nodes.len();
+            if let Some(parent) = parent {
+                let parent: &mut MctsNode<G::Move> = &mut nodes[parent];
+                parent.children.push(new_index);
+            }
+            nodes.push(node);
+            queue.extend(children.into_iter().map(|child| (child, Some(new_index))));
+        }
+        self.root = root;
+        self.nodes = nodes;
+        true
+    }
+
+    /// Returns the visits and reward of every root move that has been tried.
+    pub fn root_statistics(&self) -> Vec<MoveStatistics<G::Move>> {
+        self.nodes[0]
+            .children
+            .iter()
+            .map(|&child| {
+                let child = &self.nodes[child];
+                MoveStatistics {
+                    mov: child.mov.clone().unwrap(),
+                    visits: child.visits,
+                    reward: child.reward,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the root move of best_child(), i.e. the most visited one unless moves are proven.
+    pub fn best_move(&self) -> Option<G::Move> {
+        self.principal_variation().into_iter().next()
+    }
+
+    /// Returns the line found by following the most visited child from the root.
+    pub fn principal_variation(&self) -> Vec<G::Move> {
+        let mut line = Vec::new();
+        let mut index = 0;
+        while let Some(child) = self.best_child(index) {
+            line.push(self.nodes[child].mov.clone().unwrap());
+            index = child;
+        }
+        line
+    }
+}
+
+impl<G> MctsTree<G>
+where
+    G: GameState + Clone + Display,
+    G::Move: Clone + Display,
+{
+    /// Returns the tree as a SearchTree for exporting. Each node gets its visits and a value
+    /// from the perspective of the player to move: its mean reward stretched to run from -1 for
+    /// a loss to 1 for a win, or the proven result. Unvisited nodes have no value.
+    pub fn to_search_tree(&self) -> SearchTree {
+        let mut tree = SearchTree::new();
+        let mut states: Vec<G> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let (state, depth) = match (node.parent, &node.mov) {
+                (Some(parent), Some(mov)) => {
+                    (states[parent].next_state(mov), tree.node(parent).depth + 1)
+                }
+                _ => (self.root.clone(), 0),
+            };
+            let to_move = state.current_player();
+            let mut tree_node = TreeNode::new(&state, node.mov.as_ref(), node.parent, depth);
+            tree_node.visits = Some(node.visits);
+            tree_node.value = match node.proven {
+                Some(winner) => Some(if winner == to_move { 1.0 } else { -1.0 }),
+                None if node.visits == 0 => None,
+                None => {
+                    let value = 2.0 * node.reward / node.visits as f64 - 1.0;
+                    Some(if node.player == to_move {
+                        value
+                    } else {
+                        -value
+                    })
+                }
+            };
+            tree.add_node(tree_node);
+            states.push(state);
+        }
+        tree
+    }
+}
+
+impl<M> SharedNode<M> {
+    fn new(mov: Option<M>, player: Player) -> Self {
+        Self {
+            mov,
+            player,
+            children: OnceLock::new(),
+            visits: AtomicU64::new(0),
+            half_points: AtomicU64::new(0),
+            virtual_losses: AtomicU64::new(0),
+        }
+    }
+
+    /// The UCT value, with every virtual loss counted as a visit without reward.
+    fn uct(&self, parent_visits: u64, exploration: f64) -> f64 {
+        let visits =
+            self.visits.load(Ordering::Relaxed) + self.virtual_losses.load(Ordering::Relaxed);
+        let reward = self.half_points.load(Ordering::Relaxed) as f64 / 2.0;
+        uct(reward, visits, parent_visits, exploration)
+    }
+
+    /// Walks down the tree by UCT, expanding the first node reached without children, and returns
+    /// the path with a virtual loss added to every node on it, along with the state at its end.
+    fn select<'a, G, R>(
+        &'a self,
+        root: &G,
+        exploration: f64,
+        rng: &mut R,
+    ) -> (Vec<&'a SharedNode<M>>, G)
+    where
+        G: GameState<Move = M> + Clone,
+        M: Clone,
+        R: Rng + ?Sized,
+    {
+        let mut state = root.clone();
+        let mut path = vec![self];
+        self.virtual_losses.fetch_add(1, Ordering::Relaxed);
+        loop {
+            let node = *path.last().unwrap();
+            if state.game_result().is_determined() {
+                break;
+            }
+            let expanded = node.children.get().is_some();
+            let player = state.current_player();
+            let children = node.children.get_or_init(|| {
+                state
+                    .legal_moves()
+                    .into_iter()
+                    .map(|mov| SharedNode::new(Some(mov), player))
+                    .collect()
+            });
+            if children.is_empty() {
+                break;
+            }
+            let parent_visits =
+                node.visits.load(Ordering::Relaxed) + node.virtual_losses.load(Ordering::Relaxed);
+            // The values are read once, since other threads keep changing them. Ties, which are
+            // common among unvisited children, are broken at random.
+            let values: Vec<f64> = children
+                .iter()
+                .map(|child| child.uct(parent_visits, exploration))
+                .collect();
+            let best = values.iter().copied().max_by(f64::total_cmp).unwrap();
+            let candidates: Vec<&SharedNode<M>> = children
+                .iter()
+                .zip(&values)
+                .filter(|(_, value)| value.total_cmp(&best).is_eq())
+                .map(|(child, _)| child)
+                .collect();
+            let child = *candidates.choose(rng).unwrap();
+            child.virtual_losses.fetch_add(1, Ordering::Relaxed);
+            state.apply_move(child.mov.as_ref().unwrap());
+            path.push(child);
+            if !expanded {
+                break;
+            }
+        }
+        (path, state)
+    }
+
+    /// Records the result for every node on the path and takes back its virtual losses.
+    fn backpropagate(path: &[&SharedNode<M>], result: GameResult) {
+        for node in path {
+            let half_points = (result.score(node.player) + 1) as u64;
+            node.half_points.fetch_add(half_points, Ordering::Relaxed);
+            node.visits.fetch_add(1, Ordering::Relaxed);
+            node.virtual_losses.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    fn root_statistics(&self) -> Vec<MoveStatistics<M>>
+    where
+        M: Clone,
+    {
+        self.children
+            .get()
+            .map(|children| {
+                children
+                    .iter()
+                    .map(|child| MoveStatistics {
+                        mov: child.mov.clone().unwrap(),
+                        visits: child.visits.load(Ordering::Relaxed),
+                        reward: child.half_points.load(Ordering::Relaxed) as f64 / 2.0,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn principal_variation(&self) -> Vec<M>
+    where
+        M: Clone,
+    {
+        let mut line = Vec::new();
+        let mut node = self;
+        while let Some(child) = node.children.get().and_then(|children| {
+            children
+                .iter()
+                .filter(|child| child.visits.load(Ordering::Relaxed) > 0)
+                .max_by_key(|child| child.visits.load(Ordering::Relaxed))
+        }) {
+            line.push(child.mov.clone().unwrap());
+            node = child;
+        }
+        line
+    }
+}
+
+impl MctsStrategy {
+    pub fn with_iterations(iterations: usize, seed: u64) -> Self {
+        Self {
+            iterations,
+            exploration: std::f64::consts::SQRT_2,
+            seed,
+            rave: None,
+            solver: false,
+        }
+    }
+
+    /// Plays a match against plain UCT with the same number of iterations and exploration
+    /// weight, alternating which side moves first, to see whether RAVE or the solver pays off in
+    /// a game. Searches with a fixed seed would replay the same two games over and over, so both
+    /// strategies are reseeded for every pair of games. The score is this strategy's.
+    pub fn compare_with_uct<G, E>(&self, evaluator: &E, games: usize) -> MatchScore
+    where
+        G: GameState + Clone,
+        G::Move: Clone + PartialEq,
+        E: Evaluator<G>,
+    {
+        let mut score = MatchScore::default();
+        for pair in 0..games.div_ceil(2) {
+            let this = Self {
+                seed: self.seed.wrapping_add(2 * pair as u64),
+                ..*self
+            };
+            let uct = Self {
+                seed: this.seed.wrapping_add(1),
+                rave: None,
+                solver: false,
+                ..this
+            };
+            let pair_score = play_match::<G, E, _, _>(
+                &this,
+                &uct,
+                evaluator,
+                (games - 2 * pair).min(2),
+                &mut |_| {},
+            );
+            score.wins += pair_score.wins;
+            score.draws += pair_score.draws;
+            score.losses += pair_score.losses;
+        }
+        score
+    }
+
+    /// Runs the search and returns its tree rather than a move, e.g. to export it with
+    /// MctsTree::to_search_tree().
+    pub fn build_tree<G>(&self, state: &G) -> MctsTree<G>
+    where
+        G: GameState + Clone,
+        G::Move: Clone + PartialEq,
+    {
+        self.grow_tree(state, &StopFlag::new())
+    }
+
+    fn grow_tree<G>(&self, state: &G, stop: &StopFlag) -> MctsTree<G>
+    where
+        G: GameState + Clone,
+        G::Move: Clone + PartialEq,
+    {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut tree = MctsTree::new(state.clone());
+        for _ in 0..self.iterations {
+            if stop.is_stopped() || (self.solver && tree.proven_result().is_some()) {
+                break;
+            }
+            tree.iterate(self, &mut rng);
+        }
+        tree
+    }
+}
+
+impl<G, E> Strategy<G, E> for MctsStrategy
+where
+    G: GameState + Clone,
+    G::Move: Clone + PartialEq,
+    E: Evaluator<G>,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Runs 10,000 iterations with a random seed.
+    fn new() -> Self {
+        Self::with_iterations(10_000, rand::random())
+    }
+
+    fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search_until(state, evaluator, &StopFlag::new(), report)
+    }
+
+    /// Reports once, after the last iteration. Nodes are iterations.
+    fn search_until(
+        &self,
+        state: &G,
+        _evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let tree = self.grow_tree(state, stop);
+        let mut info = statistics_info(
+            &tree.root_statistics(),
+            tree.principal_variation(),
+            tree.visits(),
+            start,
+        );
+        if let Some(result) = tree.proven_result() {
+            info.score = Some(Score::Result(result));
+        }
+        report(&info);
+        info
+    }
+}
+
+impl<G> ReusingMctsStrategy<G>
+where
+    G: GameState,
+{
+    pub fn from_settings(settings: MctsStrategy) -> Self {
+        Self {
+            settings,
+            tree: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Returns the number of iterations stored in the kept tree, or 0 if there is none.
+    pub fn kept_visits(&self) -> u64
+    where
+        G: Clone,
+        G::Move: Clone,
+    {
+        self.tree
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |tree| tree.visits())
+    }
+}
+
+impl<G, E> Strategy<G, E> for ReusingMctsStrategy<G>
+where
+    G: GameState + Clone + PartialEq,
+    G::Move: Clone + PartialEq,
+    E: Evaluator<G>,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Runs 10,000 iterations per move with a random seed.
+    fn new() -> Self {
+        Self::from_settings(MctsStrategy::with_iterations(10_000, rand::random()))
+    }
+
+    fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search_until(state, evaluator, &StopFlag::new(), report)
+    }
+
+    /// Reports once, after the last iteration. Nodes are the root's visits, including the ones
+    /// kept from earlier searches. If the position is one move on from the kept tree's root, as
+    /// for a ponder search, the search carries on from that child. A kept tree for any other
+    /// position is thrown away. While one clone is searching the tree is not kept, so another
+    /// clone searching at the same time starts a tree of its own, and whichever finishes last
+    /// keeps theirs.
+    fn search_until(
+        &self,
+        state: &G,
+        _evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        // The tree is taken out of the slot for the length of the search, so the lock is not held
+        // while iterating and notify_move() never has to wait for a search to finish.
+        let kept = self.tree.lock().unwrap().take();
+        let mut tree = match kept {
+            Some(tree) if tree.root() == state => tree,
+            Some(mut tree) => {
+                let mov = tree
+                    .root()
+                    .legal_moves()
+                    .into_iter()
+                    .find(|mov| tree.root().next_state(mov) == *state);
+                match mov {
+                    Some(mov) => {
+                        tree.advance(&mov);
+                        tree
+                    }
+                    None => MctsTree::new(state.clone()),
+                }
+            }
+            None => MctsTree::new(state.clone()),
+        };
+        let mut rng = StdRng::seed_from_u64(self.settings.seed.wrapping_add(tree.visits()));
+        for _ in 0..self.settings.iterations {
+            if stop.is_stopped() || (self.settings.solver && tree.proven_result().is_some()) {
+                break;
+            }
+            tree.iterate(&self.settings, &mut rng);
+        }
+        let mut info = statistics_info(
+            &tree.root_statistics(),
+            tree.principal_variation(),
+            tree.visits(),
+            start,
+        );
+        if let Some(result) = tree.proven_result() {
+            info.score = Some(Score::Result(result));
+        }
+        *self.tree.lock().unwrap() = Some(tree);
+        report(&info);
+        info
+    }
+
+    /// Moves the kept tree down to the position after the move. A tree that is already there,
+    /// because a ponder search went ahead of the game, is left as it is.
+    fn notify_move(&self, state: &G, mov: &G::Move) {
+        let mut kept = self.tree.lock().unwrap();
+        match kept.as_mut() {
+            Some(tree) if tree.root() == state => {
+                tree.advance(mov);
+            }
+            Some(tree) if *tree.root() == state.next_state(mov) => {}
+            _ => *kept = None,
+        }
+    }
+}
+
+impl ParallelMctsStrategy {
+    pub fn with_threads(threads: usize, parallelism: Parallelism, iterations: usize) -> Self {
+        Self {
+            threads,
+            parallelism,
+            iterations,
+            exploration: std::f64::consts::SQRT_2,
+            seed: rand::random(),
+            deterministic: false,
+        }
+    }
+
+    /// Returns the number of iterations the given thread runs, sharing the budget evenly.
+    fn thread_iterations(&self, thread: usize) -> usize {
+        let threads = self.threads.max(1);
+        self.iterations / threads + usize::from(thread < self.iterations % threads)
+    }
+
+    fn root_parallel<G>(&self, state: &G, stop: &StopFlag) -> (Vec<MoveStatistics<G::Move>>, u64)
+    where
+        G: GameState + Clone + Send + Sync,
+        G::Move: Clone + PartialEq + Send + Sync,
+    {
+        let settings = MctsStrategy {
+            iterations: self.iterations,
+            exploration: self.exploration,
+            seed: self.seed,
+            rave: None,
+            solver: false,
+        };
+        let trees: Vec<MctsTree<G>> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads.max(1))
+                .map(|thread| {
+                    scope.spawn(move || {
+                        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(thread as u64));
+                        let mut tree = MctsTree::new(state.clone());
+                        for _ in 0..self.thread_iterations(thread) {
+                            if stop.is_stopped() {
+                                break;
+                            }
+                            tree.iterate(&settings, &mut rng);
+                        }
+                        tree
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        let mut merged: Vec<MoveStatistics<G::Move>> = Vec::new();
+        for statistics in trees.iter().flat_map(|tree| tree.root_statistics()) {
+            match merged
+                .iter_mut()
+                .find(|merged| merged.mov == statistics.mov)
+            {
+                Some(merged) => {
+                    merged.visits += statistics.visits;
+                    merged.reward += statistics.reward;
+                }
+                None => merged.push(statistics),
+            }
+        }
+        let nodes = trees.iter().map(|tree| tree.visits()).sum();
+        (merged, nodes)
+    }
+
+    fn tree_parallel<G>(&self, state: &G, stop: &StopFlag) -> SharedNode<G::Move>
+    where
+        G: GameState + Clone + Send + Sync,
+        G::Move: Clone + Send + Sync,
+    {
+        let root = SharedNode::new(None, state.current_player().other_player());
+        let threads = self.threads.max(1);
+        let mut rngs: Vec<StdRng> = (0..threads)
+            .map(|thread| StdRng::seed_from_u64(self.seed.wrapping_add(thread as u64)))
+            .collect();
+
+        if self.deterministic {
+            for round in 0..self.thread_iterations(0) {
+                if stop.is_stopped() {
+                    break;
+                }
+                let selections: Vec<_> = rngs
+                    .iter_mut()
+                    .enumerate()
+                    .filter(|&(thread, _)| round < self.thread_iterations(thread))
+                    .map(|(_, rng)| root.select(state, self.exploration, rng))
+                    .collect();
+                for ((path, end), rng) in selections.into_iter().zip(&mut rngs) {
+                    SharedNode::backpropagate(&path, playout(end, rng, &mut Vec::new()));
+                }
+            }
+        } else {
+            thread::scope(|scope| {
+                for (thread, mut rng) in rngs.into_iter().enumerate() {
+                    let root = &root;
+                    scope.spawn(move || {
+                        for _ in 0..self.thread_iterations(thread) {
+                            if stop.is_stopped() {
+                                break;
+                            }
+                            let (path, end) = root.select(state, self.exploration, &mut rng);
+                            SharedNode::backpropagate(
+                                &path,
+                                playout(end, &mut rng, &mut Vec::new()),
+                            );
+                        }
+                    });
+                }
+            });
+        }
+        root
+    }
+
+    /// Plays a match against single-threaded UCT with the same total number of iterations and
+    /// exploration weight, alternating which side moves first. As in
+    /// MctsStrategy::compare_with_uct(), both strategies are reseeded for every pair of games, and
+    /// UCT never shares a seed with one of this strategy's threads. The score is this strategy's.
+    pub fn compare_with_uct<G, E>(&self, evaluator: &E, games: usize) -> MatchScore
+    where
+        G: GameState + Clone + Send + Sync,
+        G::Move: Clone + PartialEq + Send + Sync,
+        E: Evaluator<G>,
+    {
+        let mut score = MatchScore::default();
+        for pair in 0..games.div_ceil(2) {
+            let this = Self {
+                seed: self
+                    .seed
+                    .wrapping_add(pair as u64 * (self.threads as u64 + 1)),
+                ..*self
+            };
+            let uct = MctsStrategy {
+                iterations: self.iterations,
+                exploration: self.exploration,
+                seed: this.seed.wrapping_add(self.threads as u64),
+                rave: None,
+                solver: false,
+            };
+            let pair_score = play_match::<G, E, _, _>(
+                &this,
+                &uct,
+                evaluator,
+                (games - 2 * pair).min(2),
+                &mut |_| {},
+            );
+            score.wins += pair_score.wins;
+            score.draws += pair_score.draws;
+            score.losses += pair_score.losses;
+        }
+        score
+    }
+}
+
+impl<G, E> Strategy<G, E> for ParallelMctsStrategy
+where
+    G: GameState + Clone + Send + Sync,
+    G::Move: Clone + PartialEq + Send + Sync,
+    E: Evaluator<G>,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Uses tree parallelisation with one thread per core and 10,000 iterations.
+    fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        Self::with_threads(threads, Parallelism::Tree, 10_000)
+    }
+
+    fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search_until(state, evaluator, &StopFlag::new(), report)
+    }
+
+    /// Reports once, after the last iteration. Nodes are iterations. With root parallelisation
+    /// the principal variation is just the chosen move, since the trees aren't merged below the
+    /// root.
+    fn search_until(
+        &self,
+        state: &G,
+        _evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let info = match self.parallelism {
+            Parallelism::Root => {
+                let (statistics, nodes) = self.root_parallel(state, stop);
+                let best = statistics
+                    .iter()
+                    .max_by_key(|statistics| statistics.visits)
+                    .map(|best| best.mov.clone());
+                statistics_info(&statistics, best.into_iter().collect(), nodes, start)
+            }
+            Parallelism::Tree => {
+                let root = self.tree_parallel(state, stop);
+                statistics_info(
+                    &root.root_statistics(),
+                    root.principal_variation(),
+                    root.visits.load(Ordering::Relaxed),
+                    start,
+                )
+            }
+        };
+        report(&info);
+        info
+    }
+}
+
+impl OpenLoopMctsStrategy {
+    pub fn with_iterations(iterations: usize, seed: u64) -> Self {
+        Self {
+            iterations,
+            exploration: std::f64::consts::SQRT_2,
+            seed,
+        }
+    }
+
+    /// Runs one iteration: select and expand down the tree, sampling chance outcomes after every
+    /// move, then play out and back up the result.
+    fn iterate<G, R>(&self, nodes: &mut Vec<OpenLoopNode<G::Move>>, root: &G, rng: &mut R)
+    where
+        G: StochasticGame + Clone,
+        G::Move: Clone + PartialEq,
+        R: Rng + ?Sized,
+    {
+        let mut state = root.clone();
+        let mut path = vec![(0, state.current_player())];
+        let mut index = 0;
+        loop {
+            while state.is_chance_node() {
+                state = state.sample_outcome(rng);
+            }
+            if state.game_result().is_determined() {
+                break;
+            }
+            let moves = state.legal_moves();
+            let child_move = |child: usize| nodes[child].mov.as_ref().expect("children have moves");
+            let untried: Vec<&G::Move> = moves
+                .iter()
+                .filter(|mov| {
+                    !nodes[index]
+                        .children
+                        .iter()
+                        .any(|&child| child_move(child) == *mov)
+                })
+                .collect();
+            let expand = !untried.is_empty();
+            let child = if let Some(&mov) = untried.choose(rng) {
+                nodes.push(OpenLoopNode {
+                    mov: Some(mov.clone()),
+                    children: Vec::new(),
+                    visits: 0,
+                    reward: 0.0,
+                });
+                let child = nodes.len() - 1;
+                nodes[index].children.push(child);
+                child
+            } else {
+                let parent_visits = nodes[index].visits;
+                let value = |child: usize| {
+                    uct(
+                        nodes[child].reward,
+                        nodes[child].visits,
+                        parent_visits,
+                        self.exploration,
+                    )
+                };
+                let Some(child) = nodes[index]
+                    .children
+                    .iter()
+                    .copied()
+                    .filter(|&child| moves.contains(child_move(child)))
+                    .max_by(|&a, &b| value(a).total_cmp(&value(b)))
+                else {
+                    break;
+                };
+                child
+            };
+            path.push((child, state.current_player()));
+            state.apply_move(nodes[child].mov.as_ref().expect("children have moves"));
+            index = child;
+            if expand {
+                break;
+            }
+        }
+        let result = stochastic_playout(state, rng);
+        for (index, player) in path {
+            nodes[index].visits += 1;
+            nodes[index].reward += reward(result, player);
+        }
+    }
+}
+
+impl<G, E> Strategy<G, E> for OpenLoopMctsStrategy
+where
+    G: StochasticGame + Clone,
+    G::Move: Clone + PartialEq,
+    E: Evaluator<G>,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Runs 10,000 iterations with a random seed.
+    fn new() -> Self {
+        Self::with_iterations(10_000, rand::random())
+    }
+
+    fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search_until(state, evaluator, &StopFlag::new(), report)
+    }
+
+    /// Reports once, after the last iteration. Nodes are iterations. The principal variation is
+    /// only the chosen move, since what follows depends on chance.
+    fn search_until(
+        &self,
+        state: &G,
+        _evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut nodes = vec![OpenLoopNode {
+            mov: None,
+            children: Vec::new(),
+            visits: 0,
+            reward: 0.0,
+        }];
+        if !state.is_chance_node() && !state.legal_moves().is_empty() {
+            for _ in 0..self.iterations {
+                if stop.is_stopped() {
+                    break;
+                }
+                self.iterate(&mut nodes, state, &mut rng);
+            }
+        }
+        let statistics: Vec<_> = nodes[0]
+            .children
+            .iter()
+            .map(|&child| MoveStatistics {
+                mov: nodes[child].mov.clone().expect("children have moves"),
+                visits: nodes[child].visits,
+                reward: nodes[child].reward,
+            })
+            .collect();
+        let principal_variation = statistics
+            .iter()
+            .max_by_key(|statistics| statistics.visits)
+            .map(|best| best.mov.clone())
+            .into_iter()
+            .collect();
+        let info = statistics_info(&statistics, principal_variation, nodes[0].visits, start);
+        report(&info);
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MctsStrategy, MctsTree, ParallelMctsStrategy, Parallelism, RaveSchedule,
+        ReusingMctsStrategy,
+    };
+    use crate::evaluate::TerminalStateEvaluator;
+    use crate::game::{GamePlayer, GameResult};
+    use crate::mnk::FiveInARow;
+    use crate::search::Ponder;
+    use crate::strategy::{play_match, MatchScore, Score, Strategy, TerminalStateStrategy};
+    use crate::tic_tac_toe::{BoardState, Move, PerfectPlayTable};
+
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_mcts_ponder() {
+        let strategy = ReusingMctsStrategy::from_settings(MctsStrategy::with_iterations(500, 8));
+        let notify = |state: &BoardState, mov: &Move| {
+            Strategy::<BoardState, TerminalStateEvaluator>::notify_move(&strategy, state, mov)
+        };
+        let board = BoardState::new();
+        let info = strategy.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        let mov = *info.best_move().unwrap();
+        notify(&board, &mov);
+        let after = board.next_state(&mov);
+
+        // The ponder search grows the shared tree from what the first search found below the
+        // expected reply, and the game following it along keeps the tree.
+        let ponder = Ponder::start(after, &info, TerminalStateEvaluator, strategy.clone()).unwrap();
+        let reply = *ponder.expected_move();
+        let pondered = ponder.opponent_moved(&reply).unwrap().wait();
+        assert!(pondered.nodes > 500);
+        notify(&after, &reply);
+        assert_eq!(strategy.kept_visits(), pondered.nodes);
+        let info = strategy.search(
+            &after.next_state(&reply),
+            &TerminalStateEvaluator,
+            &mut |_| {},
+        );
+        assert_eq!(info.nodes, pondered.nodes + 500);
+
+        // On a miss, the tree is thrown away once the game moves elsewhere.
+        let board = after
+            .next_state(&reply)
+            .next_state(info.best_move().unwrap());
+        notify(&after.next_state(&reply), info.best_move().unwrap());
+        let ponder = Ponder::start(board, &info, TerminalStateEvaluator, strategy.clone()).unwrap();
+        let expected = *ponder.expected_move();
+        let other = board
+            .legal_moves()
+            .into_iter()
+            .find(|mov| *mov != expected)
+            .unwrap();
+        assert!(ponder.opponent_moved(&other).is_none());
+        notify(&board, &other);
+        assert_eq!(strategy.kept_visits(), 0);
+    }
+
+    #[test]
+    fn test_mcts() {
+        // X wins at c1 and O must block the diagonal, as in test_terminal_state_strategy().
+        let win: BoardState = "XO_/_X_/O__ x".parse().unwrap();
+        let block: BoardState = "X__/_X_/O__ o".parse().unwrap();
+
+        let uct = MctsStrategy::with_iterations(2000, 1);
+        let info = uct.search(&win, &TerminalStateEvaluator, &mut |_| {});
+        assert_eq!(info.best_move(), Some(&Move::from_square(8).unwrap()));
+        assert_eq!(info.nodes, 2000);
+        assert_eq!(
+            uct.choose_move(&block, &TerminalStateEvaluator),
+            Some(Move::from_square(8).unwrap())
+        );
+
+        for parallelism in [Parallelism::Root, Parallelism::Tree] {
+            let strategy = ParallelMctsStrategy {
+                seed: 1,
+                deterministic: true,
+                ..ParallelMctsStrategy::with_threads(4, parallelism, 2000)
+            };
+            let info = strategy.search(&win, &TerminalStateEvaluator, &mut |_| {});
+            assert_eq!(info.best_move(), Some(&Move::from_square(8).unwrap()));
+            assert_eq!(info.nodes, 2000);
+            let again = strategy.search(&win, &TerminalStateEvaluator, &mut |_| {});
+            assert_eq!(
+                (again.principal_variation, again.score),
+                (info.principal_variation, info.score)
+            );
+            assert_eq!(
+                strategy.choose_move(&block, &TerminalStateEvaluator),
+                Some(Move::from_square(8).unwrap())
+            );
+        }
+
+        let strategy = ParallelMctsStrategy {
+            seed: 2,
+            ..ParallelMctsStrategy::with_threads(2, Parallelism::Tree, 2000)
+        };
+        let info = strategy.search(&win, &TerminalStateEvaluator, &mut |_| {});
+        assert_eq!(info.best_move(), Some(&Move::from_square(8).unwrap()));
+        // An odd number of games ends with half a pair, from new seeds.
+        let score = strategy.compare_with_uct::<BoardState, _>(&TerminalStateEvaluator, 3);
+        assert_eq!(score.wins + score.draws + score.losses, 3);
+
+        // A perfect player never loses, and every move of the match is recorded with its search.
+        let mut records = Vec::new();
+        let score = play_match::<BoardState, _, _, _>(
+            &TerminalStateStrategy,
+            &uct,
+            &TerminalStateEvaluator,
+            2,
+            &mut |record| records.push(record),
+        );
+        assert_eq!(score.losses, 0);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].players[0], "TerminalStateStrategy");
+        assert_eq!(records[1].players[1], "TerminalStateStrategy");
+        assert!(records
+            .iter()
+            .flat_map(|record| &record.moves)
+            .all(|mov| mov.info.is_some()));
+        assert_ne!(score, MatchScore::default());
+    }
+
+    #[test]
+    fn test_mcts_tree_reuse() {
+        let board = BoardState::new();
+        let mut tree = MctsTree::new(board);
+        let mut rng = rand::rngs::StdRng::seed_from_u64(3);
+        for _ in 0..1000 {
+            tree.iterate(&MctsStrategy::with_iterations(1000, 3), &mut rng);
+        }
+        let best = tree.best_move().unwrap();
+        let statistics = tree.root_statistics();
+        let kept = statistics.iter().find(|s| s.mov == best).unwrap().visits;
+        assert!(tree.advance(&best));
+        assert_eq!(*tree.root(), board.next_state(&best));
+        assert_eq!(tree.visits(), kept);
+        assert!(tree.len() as u64 <= kept);
+
+        let strategy = ReusingMctsStrategy::from_settings(MctsStrategy::with_iterations(500, 4));
+        let mut player = GamePlayer::from(board, TerminalStateEvaluator, strategy);
+        let record = player.play_recorded();
+        assert!(record.result.is_determined());
+
+        // After our move and the reply, the search starts from what it already knew.
+        let strategy = ReusingMctsStrategy::from_settings(MctsStrategy::with_iterations(500, 5));
+        let mov = strategy
+            .choose_move(&board, &TerminalStateEvaluator)
+            .unwrap();
+        let notify = |state: &BoardState, mov: &Move| {
+            Strategy::<BoardState, TerminalStateEvaluator>::notify_move(&strategy, state, mov)
+        };
+        notify(&board, &mov);
+        let after = board.next_state(&mov);
+        let reply = strategy
+            .choose_move(&after, &TerminalStateEvaluator)
+            .unwrap();
+        notify(&after, &reply);
+        let kept = strategy.kept_visits();
+        assert!(kept > 0);
+        let info = strategy.search(
+            &after.next_state(&reply),
+            &TerminalStateEvaluator,
+            &mut |_| {},
+        );
+        assert_eq!(info.nodes, kept + 500);
+    }
+
+    #[test]
+    fn test_rave() {
+        assert_eq!(RaveSchedule::Equivalence(100.0).beta(0, 0), 1.0);
+        assert_eq!(RaveSchedule::Equivalence(100.0).beta(100, 50), 0.5);
+        assert_eq!(RaveSchedule::MinimumError(0.0).beta(10, 30), 0.75);
+        assert_eq!(RaveSchedule::MinimumError(0.1).beta(10, 0), 0.0);
+
+        let win: BoardState = "XO_/_X_/O__ x".parse().unwrap();
+        let block: BoardState = "X__/_X_/O__ o".parse().unwrap();
+        for schedule in [
+            RaveSchedule::Equivalence(300.0),
+            RaveSchedule::MinimumError(0.05),
+        ] {
+            let rave = MctsStrategy {
+                rave: Some(schedule),
+                ..MctsStrategy::with_iterations(2000, 6)
+            };
+            assert_eq!(
+                rave.choose_move(&win, &TerminalStateEvaluator),
+                Some(Move::from_square(8).unwrap())
+            );
+            assert_eq!(
+                rave.choose_move(&block, &TerminalStateEvaluator),
+                Some(Move::from_square(8).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_rave_strength() {
+        // In five in a row a stone means much the same whenever it is placed, so RAVE's extra
+        // samples make it far stronger than UCT with the same number of iterations.
+        let rave = MctsStrategy {
+            rave: Some(RaveSchedule::Equivalence(300.0)),
+            ..MctsStrategy::with_iterations(500, 0)
+        };
+        let score = rave.compare_with_uct::<FiveInARow, _>(&TerminalStateEvaluator, 8);
+        assert_eq!(score.wins + score.draws + score.losses, 8);
+        assert!(score.wins >= 3 * score.losses, "{:?}", score);
+    }
+
+    #[test]
+    fn test_mcts_solver() {
+        let solver = MctsStrategy {
+            solver: true,
+            ..MctsStrategy::with_iterations(20_000, 7)
+        };
+        let table = PerfectPlayTable::new();
+        for board in [
+            "XO_/_X_/O__ x",
+            "X__/___/__O x",
+            "X__/_O_/__X o",
+            "XX_/OO_/___ o",
+        ] {
+            let board: BoardState = board.parse().unwrap();
+            let info = solver.search(&board, &TerminalStateEvaluator, &mut |_| {});
+            let result = table.result(&board).unwrap();
+            if result.is_determined() && result != GameResult::Draw {
+                // Solving stops the search early and the proven move is played.
+                assert_eq!(info.score, Some(Score::Result(result)), "{}", board);
+                assert!(info.nodes < 20_000);
+            }
+            assert!(
+                table
+                    .perfect_moves(&board)
+                    .contains(info.best_move().unwrap()),
+                "{}",
+                board
+            );
+        }
+
+        // Without the solver, the same search never claims a result.
+        let uct = MctsStrategy::with_iterations(2000, 7);
+        let board: BoardState = "XO_/_X_/O__ x".parse().unwrap();
+        let info = uct.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        assert!(matches!(info.score, Some(Score::Value(_))));
+    }
+}
@@ -0,0 +1,726 @@
+use crate::{evaluate::*, game::*, multiplayer::MultiplayerGame, symmetry::*};
+use std::fmt::Display;
+
+/// An m,n,k-game: two players take turns placing a stone on an empty square of a board M columns
+/// wide and N rows high, and whoever first gets K stones in a row horizontally, vertically or
+/// diagonally wins. If the board fills up first the game is a draw. Tic-tac-toe is the 3,3,3-game
+/// and FiveInARow the 9,9,5-game. Boards can have up to 26 columns and 128 squares.
+///
+/// Player One places X stones and moves first. Squares are numbered along the rows from the top
+/// left, like in tic-tac-toe. Square boards have all eight symmetries of BoardSymmetry, and
+/// other boards the four of BoardSymmetry::RECTANGLE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MnkGame<const M: usize, const N: usize, const K: usize> {
+    /// One bit per square for each player's stones, indexed by Player::index().
+    stones: [u128; 2],
+    to_move: Player,
+    /// The square of the last stone placed, the only one that can have completed a row.
+    last: Option<u8>,
+}
+
+/// Five in a row on a 9×9 board. Big enough that searching it is hard, small enough for MCTS
+/// playouts to be cheap.
+pub type FiveInARow = MnkGame<9, 9, 5>;
+
+/// An m,n,k-game for any number of players, who take turns in order of their index. Whoever first
+/// gets K stones in a row gets a payoff of 1 and everyone else 0. If the board fills up first the
+/// payoff is shared evenly. Squares are numbered like in MnkGame, and boards can have up to 128
+/// squares.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MultiplayerMnk<const M: usize, const N: usize, const K: usize> {
+    /// One bit per square for each player's stones, by index.
+    stones: Vec<u128>,
+    to_move: PlayerIndex,
+    /// The square of the last stone placed, the only one that can have completed a row.
+    last: Option<u8>,
+}
+
+/// Places a stone on the square with the given number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MnkMove(pub u8);
+
+/// Returned by MnkGame::undo_move() to take a move back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MnkUndo {
+    square: u8,
+    last: Option<u8>,
+}
+
+/// Rates a move by the rows of K squares through it: every row still open to the player moving
+/// counts the cube of their stones in it, and every row the move blocks counts the cube of the
+/// opponent's. A winning move is a win. Cheap and far from perfect, but enough for searches
+/// that can't see to the end of the game.
+#[derive(Debug, Clone, Copy)]
+pub struct LineEvaluator;
+
+/// The directions a row can run in, as column and row steps.
+const DIRECTIONS: [(isize, isize); 4] = [(1, 0), (0, 1), (1, 1), (1, -1)];
+
+/// How MultiplayerMnk draws each player's stones, by index.
+const SYMBOLS: [char; 8] = ['X', 'O', 'A', 'B', 'C', 'D', 'E', 'F'];
+
+impl<const M: usize, const N: usize, const K: usize> MnkGame<M, N, K> {
+    /// Returns an empty board with Player One to move. Panics if the board has more than 26
+    /// columns, since they are lettered a to z, or more than 128 squares, or if no row of K fits
+    /// on it.
+    pub fn new() -> Self {
+        Self::check_size();
+        Self {
+            stones: [0; 2],
+            to_move: Player::One,
+            last: None,
+        }
+    }
+
+    /// Returns the player whose stone is on the given square, if any.
+    pub fn stone_at(&self, square: usize) -> Option<Player> {
+        let bit = 1 << square;
+        if self.stones[0] & bit != 0 {
+            Some(Player::One)
+        } else if self.stones[1] & bit != 0 {
+            Some(Player::Two)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of stones on the board.
+    pub fn stone_count(&self) -> u32 {
+        (self.stones[0] | self.stones[1]).count_ones()
+    }
+
+    fn occupied(&self) -> u128 {
+        self.stones[0] | self.stones[1]
+    }
+
+    /// Returns a mask with a bit set for every square of the board.
+    fn full() -> u128 {
+        if M * N == 128 {
+            u128::MAX
+        } else {
+            (1 << (M * N)) - 1
+        }
+    }
+
+    /// Panics if the board has more than 26 columns or 128 squares, or no row of K fits on it.
+    fn check_size() {
+        assert!(M <= 26, "boards can have at most 26 columns, a to z");
+        assert!(M * N <= 128, "boards can have at most 128 squares");
+        assert!(
+            (1..=M.max(N)).contains(&K),
+            "a row of {} doesn't fit on the board",
+            K
+        );
+    }
+
+    /// Returns the winner, who can only be whoever placed the last stone.
+    fn winner(&self) -> Option<Player> {
+        let last = self.last? as usize;
+        let player = self.stone_at(last)?;
+        Self::has_row(self.stones[player.index()], last).then_some(player)
+    }
+
+    /// Returns every row of K squares on the board, each as one bit per square.
+    fn rows() -> Vec<u128> {
+        let mut rows = Vec::new();
+        for (dx, dy) in DIRECTIONS {
+            for square in 0..M * N {
+                let (column, row) = ((square % M) as isize, (square / M) as isize);
+                let (end_x, end_y) = (column + dx * (K as isize - 1), row + dy * (K as isize - 1));
+                if (0..M as isize).contains(&end_x) && (0..N as isize).contains(&end_y) {
+                    rows.push((0..K as isize).fold(0, |mask, step| {
+                        let (x, y) = (column + dx * step, row + dy * step);
+                        mask | 1 << (y as usize * M + x as usize)
+                    }));
+                }
+            }
+        }
+        rows
+    }
+
+    /// Returns true if the given stones include a row of K through the given square.
+    fn has_row(stones: u128, square: usize) -> bool {
+        let (column, row) = ((square % M) as isize, (square / M) as isize);
+        let count_from = |dx: isize, dy: isize| {
+            (1..K as isize)
+                .map(|step| (column + dx * step, row + dy * step))
+                .take_while(|&(x, y)| {
+                    (0..M as isize).contains(&x)
+                        && (0..N as isize).contains(&y)
+                        && stones & (1 << (y as usize * M + x as usize)) != 0
+                })
+                .count()
+        };
+        DIRECTIONS
+            .iter()
+            .any(|&(dx, dy)| 1 + count_from(dx, dy) + count_from(-dx, -dy) >= K)
+    }
+
+    /// Parses coordinates like "e5": the column from a on the left, then the row from 1 at the
+    /// bottom, as on a chess board. The row is plain digits without a leading zero, so every
+    /// square has just one spelling.
+    fn square_from_coordinates(s: &str) -> Option<u8> {
+        let mut chars = s.chars();
+        let column = chars.next()?;
+        if !column.is_ascii_lowercase() {
+            return None;
+        }
+        let column = (column as u8 - b'a') as usize;
+        let row = chars.as_str();
+        if row.starts_with('0') || !row.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let row: usize = row.parse().ok()?;
+        if column >= M || !(1..=N).contains(&row) {
+            return None;
+        }
+        Some(((N - row) * M + column) as u8)
+    }
+
+    /// Returns the square the given one ends up on under the symmetry.
+    fn transform_square(square: u8, symmetry: BoardSymmetry) -> u8 {
+        let (row, column) = (square as usize / M, square as usize % M);
+        let (row, column) = symmetry.map_rectangle(row, column, N, M);
+        (row * M + column) as u8
+    }
+
+    /// Writes the square as coordinates, see square_from_coordinates().
+    fn coordinates(square: u8) -> String {
+        let (column, row) = (square as usize % M, square as usize / M);
+        format!("{}{}", (b'a' + column as u8) as char, N - row)
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> MultiplayerMnk<M, N, K> {
+    /// Returns an empty board with player 0 to move. Panics if there are fewer than two or more
+    /// than eight players, or if the board is too big or too small as in MnkGame::new().
+    pub fn new(players: usize) -> Self {
+        assert!(
+            (2..=SYMBOLS.len()).contains(&players),
+            "there can be 2 to {} players",
+            SYMBOLS.len()
+        );
+        MnkGame::<M, N, K>::check_size();
+        Self {
+            stones: vec![0; players],
+            to_move: 0,
+            last: None,
+        }
+    }
+
+    /// Returns the player whose stone is on the given square, if any.
+    pub fn stone_at(&self, square: usize) -> Option<PlayerIndex> {
+        self.stones
+            .iter()
+            .position(|stones| stones & (1 << square) != 0)
+    }
+
+    /// Returns the winner, who can only be whoever placed the last stone.
+    fn winner(&self) -> Option<PlayerIndex> {
+        let last = self.last? as usize;
+        let player = self.stone_at(last)?;
+        MnkGame::<M, N, K>::has_row(self.stones[player], last).then_some(player)
+    }
+
+    fn occupied(&self) -> u128 {
+        self.stones
+            .iter()
+            .fold(0, |occupied, stones| occupied | stones)
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> Evaluator<MnkGame<M, N, K>> for LineEvaluator {
+    type Evaluation = Distribution;
+
+    fn new() -> Self {
+        Self
+    }
+
+    fn evaluate(&self, state: &MnkGame<M, N, K>, mov: &MnkMove) -> Distribution {
+        let next = state.next_state(mov);
+        if next.winner().is_some() {
+            return Distribution::win();
+        }
+        let player = state.current_player().index();
+        let (own, other) = (next.stones[player], next.stones[1 - player]);
+        let square = mov.0 as usize;
+        let (column, row) = ((square % M) as isize, (square / M) as isize);
+        let mut total = 0;
+        for (dx, dy) in DIRECTIONS {
+            // Each row through the square starts up to K - 1 steps back from it.
+            for back in 0..K as isize {
+                let (x, y) = (column - dx * back, row - dy * back);
+                let (end_x, end_y) = (x + dx * (K as isize - 1), y + dy * (K as isize - 1));
+                let on_board = |x: isize, y: isize| {
+                    (0..M as isize).contains(&x) && (0..N as isize).contains(&y)
+                };
+                if !on_board(x, y) || !on_board(end_x, end_y) {
+                    continue;
+                }
+                let (mut mine, mut theirs) = (0, 0);
+                for step in 0..K as isize {
+                    let bit = 1 << ((y + dy * step) as usize * M + (x + dx * step) as usize);
+                    mine += (own & bit != 0) as u32;
+                    theirs += (other & bit != 0) as u32;
+                }
+                if theirs == 0 {
+                    total += mine.pow(3);
+                } else if mine == 1 {
+                    total += theirs.pow(3);
+                }
+            }
+        }
+        let value = (f64::from(total) / K.pow(3) as f64).tanh() as f32;
+        Distribution::with_probabilities((1.0 + value) / 2.0, (1.0 - value) / 2.0)
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> Default for MnkGame<M, N, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> GameState for MnkGame<M, N, K> {
+    type Move = MnkMove;
+
+    fn new() -> Self {
+        MnkGame::new()
+    }
+
+    /// Every empty square, or nothing once the game is over.
+    fn legal_moves(&self) -> Vec<MnkMove> {
+        let mut moves = Vec::new();
+        self.legal_moves_into(&mut moves);
+        moves
+    }
+
+    fn legal_moves_into(&self, moves: &mut Vec<MnkMove>) {
+        if self.winner().is_some() {
+            return;
+        }
+        let mut empty = !self.occupied() & Self::full();
+        while empty != 0 {
+            moves.push(MnkMove(empty.trailing_zeros() as u8));
+            empty &= empty - 1;
+        }
+    }
+
+    fn apply_move(&mut self, mov: &MnkMove) {
+        self.stones[self.to_move.index()] |= 1 << mov.0;
+        self.last = Some(mov.0);
+        self.to_move.flip_player();
+    }
+
+    fn next_state(&self, mov: &MnkMove) -> Self {
+        let mut next = *self;
+        next.apply_move(mov);
+        next
+    }
+
+    fn validate_move(&self, mov: &MnkMove) -> Result<(), MoveError> {
+        if self.game_result().is_determined() {
+            Err(MoveError::GameOver)
+        } else if mov.0 as usize >= M * N {
+            Err(MoveError::OutOfRange)
+        } else if self.occupied() & (1 << mov.0) != 0 {
+            Err(MoveError::Occupied)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn game_result(&self) -> GameResult {
+        if let Some(winner) = self.winner() {
+            GameResult::Win(winner)
+        } else if self.occupied() == Self::full() {
+            GameResult::Draw
+        } else {
+            GameResult::Undetermined
+        }
+    }
+
+    fn current_player(&self) -> Player {
+        self.to_move
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> UndoMove for MnkGame<M, N, K> {
+    type Undo = MnkUndo;
+
+    fn do_move(&mut self, mov: &MnkMove) -> MnkUndo {
+        let undo = MnkUndo {
+            square: mov.0,
+            last: self.last,
+        };
+        self.apply_move(mov);
+        undo
+    }
+
+    fn undo_move(&mut self, undo: MnkUndo) {
+        self.to_move.flip_player();
+        self.stones[self.to_move.index()] &= !(1 << undo.square);
+        self.last = undo.last;
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> MultiplayerGame for MultiplayerMnk<M, N, K> {
+    type Move = MnkMove;
+
+    fn player_count(&self) -> usize {
+        self.stones.len()
+    }
+
+    fn player_to_move(&self) -> PlayerIndex {
+        self.to_move
+    }
+
+    /// Every empty square, or nothing once the game is over.
+    fn available_moves(&self) -> Vec<MnkMove> {
+        if self.payoffs().is_some() {
+            return Vec::new();
+        }
+        let mut empty = !self.occupied() & MnkGame::<M, N, K>::full();
+        let mut moves = Vec::new();
+        while empty != 0 {
+            moves.push(MnkMove(empty.trailing_zeros() as u8));
+            empty &= empty - 1;
+        }
+        moves
+    }
+
+    fn after_move(&self, mov: &MnkMove) -> Self {
+        let mut next = self.clone();
+        next.stones[self.to_move] |= 1 << mov.0;
+        next.last = Some(mov.0);
+        next.to_move = (self.to_move + 1) % self.stones.len();
+        next
+    }
+
+    fn payoffs(&self) -> Option<Vec<f64>> {
+        let players = self.stones.len();
+        if let Some(winner) = self.winner() {
+            Some(
+                (0..players)
+                    .map(|player| f64::from(player == winner))
+                    .collect(),
+            )
+        } else if self.occupied() == MnkGame::<M, N, K>::full() {
+            Some(vec![1.0 / players as f64; players])
+        } else {
+            None
+        }
+    }
+
+    /// Shares out a payoff of 1 by the rows each player could still complete. A row that only
+    /// one player has stones in counts the square of their stones in it for them, and every
+    /// player starts from 1, so the empty board is an even split.
+    fn heuristic_payoffs(&self) -> Vec<f64> {
+        let mut weights = vec![1.0; self.stones.len()];
+        for row in MnkGame::<M, N, K>::rows() {
+            let mut owners = self
+                .stones
+                .iter()
+                .enumerate()
+                .filter(|(_, &stones)| stones & row != 0);
+            if let (Some((player, stones)), None) = (owners.next(), owners.next()) {
+                weights[player] += f64::from((stones & row).count_ones().pow(2));
+            }
+        }
+        let total: f64 = weights.iter().sum();
+        weights.iter().map(|weight| weight / total).collect()
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> Symmetric for MnkGame<M, N, K> {
+    type Symmetry = BoardSymmetry;
+
+    fn symmetries() -> &'static [BoardSymmetry] {
+        if M == N {
+            &BoardSymmetry::ALL
+        } else {
+            &BoardSymmetry::RECTANGLE
+        }
+    }
+
+    fn transform(&self, symmetry: BoardSymmetry) -> Self {
+        let transform_stones = |stones: u128| {
+            let mut transformed = 0;
+            let mut remaining = stones;
+            while remaining != 0 {
+                let square = remaining.trailing_zeros() as u8;
+                transformed |= 1 << Self::transform_square(square, symmetry);
+                remaining &= remaining - 1;
+            }
+            transformed
+        };
+        Self {
+            stones: self.stones.map(transform_stones),
+            last: self
+                .last
+                .map(|square| Self::transform_square(square, symmetry)),
+            ..*self
+        }
+    }
+
+    fn transform_move(mov: &MnkMove, symmetry: BoardSymmetry) -> MnkMove {
+        MnkMove(Self::transform_square(mov.0, symmetry))
+    }
+
+    fn inverse(symmetry: BoardSymmetry) -> BoardSymmetry {
+        symmetry.inverse()
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> MoveNotation for MnkGame<M, N, K> {
+    /// Parses coordinates like "e5", with column a on the left and row 1 at the bottom.
+    fn parse_move(&self, s: &str) -> Result<MnkMove, ParseMoveError> {
+        let square = Self::square_from_coordinates(s)
+            .ok_or_else(|| ParseMoveError::Invalid(s.to_string()))?;
+        let mov = MnkMove(square);
+        match self.validate_move(&mov) {
+            Ok(()) => Ok(mov),
+            Err(_) => Err(ParseMoveError::Illegal(s.to_string())),
+        }
+    }
+
+    fn format_move(&self, mov: &MnkMove) -> String {
+        Self::coordinates(mov.0)
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> Display for MnkGame<M, N, K> {
+    /// Draws the board with X for Player One, O for Player Two and . for empty squares.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in 0..N {
+            for column in 0..M {
+                let square = match self.stone_at(row * M + column) {
+                    Some(Player::One) => 'X',
+                    Some(Player::Two) => 'O',
+                    None => '.',
+                };
+                write!(f, "{}", square)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize> Display for MultiplayerMnk<M, N, K> {
+    /// Draws the board with X for player 0, O for player 1, then A, B and so on, and . for empty
+    /// squares.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in 0..N {
+            for column in 0..M {
+                let square = match self.stone_at(row * M + column) {
+                    Some(player) => SYMBOLS[player],
+                    None => '.',
+                };
+                write!(f, "{}", square)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FiveInARow, LineEvaluator, MnkGame, MnkMove, MultiplayerMnk};
+    use crate::evaluate::{Distribution, Evaluator, ResultDistribution, TerminalStateEvaluator};
+    use crate::game::{GameResult, GameState, MoveError, MoveNotation, Player, UndoMove};
+    use crate::multiplayer::{
+        play_multiplayer, MaxnStrategy, MultiplayerGame, MultiplayerStrategy,
+    };
+    use crate::symmetry::{BoardSymmetry, Symmetric};
+
+    fn play<const M: usize, const N: usize, const K: usize>(moves: &[&str]) -> MnkGame<M, N, K> {
+        let mut game = MnkGame::new();
+        for mov in moves {
+            let mov = game.parse_move(mov).unwrap();
+            game.apply_move(&mov);
+        }
+        game
+    }
+
+    #[test]
+    fn test_rows() {
+        // X builds each kind of row while O plays elsewhere.
+        let rows: [[&str; 5]; 4] = [
+            ["a1", "b1", "c1", "d1", "e1"],
+            ["a1", "a2", "a3", "a4", "a5"],
+            ["a1", "b2", "c3", "d4", "e5"],
+            ["a9", "b8", "c7", "d6", "e5"],
+        ];
+        let replies = ["i9", "i8", "i7", "i6"];
+        for row in rows {
+            let mut game = FiveInARow::new();
+            for (index, square) in row.iter().enumerate() {
+                assert_eq!(game.game_result(), GameResult::Undetermined);
+                game.apply_move(&game.parse_move(square).unwrap());
+                if index < 4 {
+                    game.apply_move(&game.parse_move(replies[index]).unwrap());
+                }
+            }
+            assert_eq!(
+                game.game_result(),
+                GameResult::Win(Player::One),
+                "\n{}",
+                game
+            );
+            assert!(game.legal_moves().is_empty());
+            assert_eq!(game.validate_move(&MnkMove(40)), Err(MoveError::GameOver));
+        }
+
+        // Four in a row broken by the edge of the board doesn't wrap around.
+        let game: FiveInARow = play(&["g5", "a1", "h5", "a2", "i5", "a3", "a4", "a9", "b4"]);
+        assert_eq!(game.game_result(), GameResult::Undetermined);
+    }
+
+    #[test]
+    fn test_moves_and_notation() {
+        let mut game = FiveInARow::new();
+        assert_eq!(game.legal_moves().len(), 81);
+        let centre = game.parse_move("e5").unwrap();
+        assert_eq!(centre, MnkMove(40));
+        assert_eq!(game.format_move(&MnkMove(0)), "a9");
+        assert_eq!(game.format_move(&MnkMove(80)), "i1");
+        assert!(game.parse_move("j1").is_err());
+        assert!(game.parse_move("a10").is_err());
+        for spelling in ["e05", "e+5", "e 5", "e"] {
+            assert!(game.parse_move(spelling).is_err(), "{}", spelling);
+        }
+
+        let undo = game.do_move(&centre);
+        assert_eq!(game.stone_at(40), Some(Player::One));
+        assert_eq!(game.validate_move(&centre), Err(MoveError::Occupied));
+        assert_eq!(game.validate_move(&MnkMove(81)), Err(MoveError::OutOfRange));
+        assert!(game.parse_move("e5").is_err());
+        game.undo_move(undo);
+        assert_eq!(game, FiveInARow::new());
+    }
+
+    #[test]
+    fn test_small_boards() {
+        // The 3,3,3-game is tic-tac-toe.
+        assert_eq!(
+            TerminalStateEvaluator::solve(&mut MnkGame::<3, 3, 3>::new()),
+            GameResult::Draw
+        );
+        let game: MnkGame<3, 3, 3> = play(&["b2", "a3", "c3", "a1", "a2", "c2", "b3", "b1", "c1"]);
+        assert_eq!(game.game_result(), GameResult::Draw);
+        assert_eq!(game.to_string(), "OXX\nXXO\nOOX\n");
+    }
+
+    #[test]
+    fn test_mnk_symmetry() {
+        fn check<const M: usize, const N: usize, const K: usize>(game: MnkGame<M, N, K>) {
+            for &symmetry in MnkGame::<M, N, K>::symmetries() {
+                let transformed = game.transform(symmetry);
+                assert_eq!(transformed.stone_count(), game.stone_count());
+                assert_eq!(transformed.transform(symmetry.inverse()), game);
+                assert_eq!(transformed.canonical().0, game.canonical().0);
+                for mov in game.legal_moves() {
+                    assert_eq!(
+                        game.next_state(&mov).transform(symmetry),
+                        transformed.next_state(&MnkGame::<M, N, K>::transform_move(&mov, symmetry))
+                    );
+                }
+            }
+            let (canonical, symmetry) = game.canonical();
+            assert_eq!(game.transform(symmetry), canonical);
+        }
+
+        let square: MnkGame<4, 4, 3> = play(&["a1", "b3", "d2"]);
+        assert_eq!(MnkGame::<4, 4, 3>::symmetries().len(), 8);
+        assert_eq!(
+            square.transform(BoardSymmetry::Rotate90).to_string(),
+            "X...\n..O.\n....\n.X..\n"
+        );
+        check(square);
+
+        // A board wider than it is high can only be turned half way round or mirrored.
+        let wide: MnkGame<5, 3, 3> = play(&["a1", "b3", "e2"]);
+        assert_eq!(MnkGame::<5, 3, 3>::symmetries(), BoardSymmetry::RECTANGLE);
+        assert_eq!(
+            wide.transform(BoardSymmetry::Rotate180).to_string(),
+            "....X\nX....\n...O.\n"
+        );
+        check(wide);
+    }
+
+    #[test]
+    #[should_panic(expected = "26 columns")]
+    fn test_too_many_columns() {
+        MnkGame::<27, 1, 1>::new();
+    }
+
+    #[test]
+    fn test_line_evaluator() {
+        // X has three in a row with both ends open: O has to block, and X wins by extending it.
+        let game: MnkGame<7, 6, 4> = play(&["c1", "a6", "d1", "b6", "e1"]);
+        let value = |mov: &str| {
+            LineEvaluator
+                .evaluate(&game, &game.parse_move(mov).unwrap())
+                .expected_result()
+        };
+        assert!(value("f1") > value("g6"));
+        assert!(value("b1") > value("d4"));
+        let mut extended = game;
+        extended.apply_move(&extended.parse_move("g6").unwrap());
+        let win = extended.parse_move("f1").unwrap();
+        assert_eq!(LineEvaluator.evaluate(&extended, &win), Distribution::win());
+    }
+
+    #[test]
+    fn test_multiplayer_mnk() {
+        let play = |moves: &[u8]| {
+            moves
+                .iter()
+                .fold(MultiplayerMnk::<4, 4, 3>::new(3), |game, &square| {
+                    game.after_move(&MnkMove(square))
+                })
+        };
+        let game = play(&[5, 0, 1]);
+        assert_eq!(game.player_count(), 3);
+        assert_eq!(game.player_to_move(), 0);
+        assert_eq!(game.stone_at(1), Some(2));
+        assert_eq!(game.available_moves().len(), 13);
+        assert_eq!(game.to_string(), "OA..\n.X..\n....\n....\n");
+
+        // The third player completes a diagonal: they win and everyone else loses.
+        let won = play(&[5, 0, 1, 12, 13, 6, 3, 7, 11]);
+        assert_eq!(won.stone_at(11), Some(2));
+        assert_eq!(won.payoffs(), Some(vec![0.0, 0.0, 1.0]));
+        assert!(won.available_moves().is_empty());
+
+        // One move earlier, the third player finds the win even a single ply deep.
+        let before = play(&[5, 0, 1, 12, 13, 6, 3, 7]);
+        assert_eq!(before.payoffs(), None);
+        let search = MaxnStrategy::with_depth(1).search_payoffs(&before);
+        assert_eq!(search.principal_variation, vec![MnkMove(11)]);
+        assert_eq!(search.payoffs, vec![0.0, 0.0, 1.0]);
+
+        // Three players filling a row of three with no room for a line share the payoff.
+        let full = [0, 1, 2]
+            .iter()
+            .fold(MultiplayerMnk::<3, 1, 3>::new(3), |game, &square| {
+                game.after_move(&MnkMove(square))
+            });
+        assert_eq!(full.payoffs(), Some(vec![1.0 / 3.0; 3]));
+
+        // Cut off a ply deep, an even split would leave every move tied and the corner first.
+        // The heuristic payoffs favour the squares with the most rows through them instead.
+        let empty = MultiplayerMnk::<4, 4, 3>::new(3);
+        assert_eq!(empty.heuristic_payoffs(), vec![1.0 / 3.0; 3]);
+        assert_eq!(empty.available_moves()[0], MnkMove(0));
+        let search = MaxnStrategy::with_depth(1).search_payoffs(&empty);
+        assert_eq!(search.principal_variation, vec![MnkMove(5)]);
+        assert!(search.payoffs[0] > 1.0 / 3.0);
+
+        let maxn = MaxnStrategy::with_depth(2);
+        let payoffs = play_multiplayer(MultiplayerMnk::<4, 4, 3>::new(3), &[&maxn, &maxn, &maxn]);
+        assert_eq!(payoffs.iter().sum::<f64>(), 1.0);
+    }
+}
@@ -0,0 +1,484 @@
+use crate::{evaluate::*, game::*, strategy::*};
+use std::time::Instant;
+
+/// Games for any number of players. Each player is after their own payoff, so unlike two-player
+/// games one player's gain needn't be everyone else's loss. Every two-player GameState is one,
+/// with Player::One as player 0 and Player::Two as player 1, a payoff of 1 for a win, 0 for a loss
+/// and a half each for a draw, so the strategies here play those too.
+pub trait MultiplayerGame: Sized {
+    type Move;
+
+    fn player_count(&self) -> usize;
+
+    /// Returns the index of the player whose turn it is.
+    fn player_to_move(&self) -> PlayerIndex;
+
+    /// Returns the legal moves of the player to move, or none once the game is over.
+    fn available_moves(&self) -> Vec<Self::Move>;
+
+    /// Returns the state after the player to move makes the given move.
+    fn after_move(&self, mov: &Self::Move) -> Self;
+
+    /// Returns every player's payoff, by index, once the game is over, and None until then.
+    fn payoffs(&self) -> Option<Vec<f64>>;
+
+    /// Returns a guess at every player's payoff, by index, for searches that stop before the game
+    /// is over, like an Evaluator does for two-player searches. By default every player gets an
+    /// even share of a payoff of 1.
+    fn heuristic_payoffs(&self) -> Vec<f64> {
+        vec![1.0 / self.player_count() as f64; self.player_count()]
+    }
+}
+
+/// Strategies for MultiplayerGame.
+pub trait MultiplayerStrategy<G>
+where
+    G: MultiplayerGame,
+{
+    /// Searches the state for the best move of the player to move and the payoffs it leads to.
+    fn search_payoffs(&self, state: &G) -> MultiplayerSearch<G::Move>;
+}
+
+/// What a MultiplayerStrategy expects a game to come to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiplayerSearch<M> {
+    /// The line of play the search expects, starting with the chosen move.
+    pub principal_variation: Vec<M>,
+    /// The payoffs the search expects each player to end up with, by index.
+    pub payoffs: Vec<f64>,
+    pub nodes: u64,
+}
+
+/// Max^n: every player maximises their own payoff, assuming the others do the same. Ties go to
+/// the earliest move, which decides what the other players get. With two players and a zero-sum
+/// payoff this is minimax. It prunes nothing. At the depth limit it goes by the game's
+/// heuristic_payoffs(). On two-player games it is also an ordinary Strategy, which doesn't consult
+/// the evaluator.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxnStrategy {
+    pub depth: usize,
+}
+
+/// The paranoid assumption: the other players are all out to minimise the payoff of the player to
+/// move at the root, which turns the game into a two-player one between that player and a
+/// coalition of the rest and allows alpha-beta pruning. It plays safe but can be far too
+/// pessimistic, e.g. when a player has no reason to help the others. Like MaxnStrategy it goes by
+/// heuristic_payoffs() at the depth limit and is also an ordinary Strategy on two-player games.
+#[derive(Debug, Clone, Copy)]
+pub struct ParanoidStrategy {
+    pub depth: usize,
+}
+
+impl<G> MultiplayerGame for G
+where
+    G: GameState,
+{
+    type Move = G::Move;
+
+    fn player_count(&self) -> usize {
+        2
+    }
+
+    fn player_to_move(&self) -> PlayerIndex {
+        self.current_player().index()
+    }
+
+    fn available_moves(&self) -> Vec<G::Move> {
+        self.legal_moves()
+    }
+
+    fn after_move(&self, mov: &G::Move) -> Self {
+        self.next_state(mov)
+    }
+
+    fn payoffs(&self) -> Option<Vec<f64>> {
+        match self.game_result() {
+            GameResult::Win(Player::One) => Some(vec![1.0, 0.0]),
+            GameResult::Win(Player::Two) => Some(vec![0.0, 1.0]),
+            GameResult::Draw => Some(vec![0.5, 0.5]),
+            GameResult::Undetermined => None,
+        }
+    }
+}
+
+/// Turns a search into a report, with the payoff of the player to move rescaled from between 0
+/// and 1 to between -1 and 1 like the values of two-player searches.
+fn search_info<G>(
+    state: &G,
+    search: MultiplayerSearch<G::Move>,
+    start: Instant,
+) -> SearchInfo<G::Move>
+where
+    G: GameState,
+{
+    let payoff = search.payoffs[state.player_to_move()];
+    SearchInfo {
+        depth: search.principal_variation.len(),
+        score: (!search.principal_variation.is_empty()).then_some(Score::Value(2.0 * payoff - 1.0)),
+        principal_variation: search.principal_variation,
+        nodes: search.nodes,
+        elapsed: start.elapsed(),
+    }
+}
+
+impl MaxnStrategy {
+    pub fn with_depth(depth: usize) -> Self {
+        Self { depth }
+    }
+
+    fn maxn<G>(&self, state: &G, depth: usize, nodes: &mut u64) -> (Vec<f64>, Vec<G::Move>)
+    where
+        G: MultiplayerGame,
+    {
+        *nodes += 1;
+        if let Some(payoffs) = state.payoffs() {
+            return (payoffs, Vec::new());
+        }
+        if depth == 0 {
+            return (state.heuristic_payoffs(), Vec::new());
+        }
+        let player = state.player_to_move();
+        let mut best: Option<(Vec<f64>, Vec<G::Move>)> = None;
+        for mov in state.available_moves() {
+            let (payoffs, mut line) = self.maxn(&state.after_move(&mov), depth - 1, nodes);
+            if best
+                .as_ref()
+                .is_none_or(|(best, _)| payoffs[player] > best[player])
+            {
+                line.insert(0, mov);
+                best = Some((payoffs, line));
+            }
+        }
+        best.unwrap_or_else(|| (state.heuristic_payoffs(), Vec::new()))
+    }
+}
+
+impl<G> MultiplayerStrategy<G> for MaxnStrategy
+where
+    G: MultiplayerGame,
+{
+    fn search_payoffs(&self, state: &G) -> MultiplayerSearch<G::Move> {
+        let mut nodes = 0;
+        let (payoffs, principal_variation) = self.maxn(state, self.depth, &mut nodes);
+        MultiplayerSearch {
+            principal_variation,
+            payoffs,
+            nodes,
+        }
+    }
+}
+
+impl ParanoidStrategy {
+    pub fn with_depth(depth: usize) -> Self {
+        Self { depth }
+    }
+
+    /// Returns the payoffs at the end of the line the root player and the coalition settle on,
+    /// which are exact for the root player if their payoff lies within (alpha, beta) and a bound
+    /// otherwise, like alpha-beta.
+    fn paranoid<G>(
+        &self,
+        state: &G,
+        root: PlayerIndex,
+        depth: usize,
+        mut alpha: f64,
+        mut beta: f64,
+        nodes: &mut u64,
+    ) -> (Vec<f64>, Vec<G::Move>)
+    where
+        G: MultiplayerGame,
+    {
+        *nodes += 1;
+        if let Some(payoffs) = state.payoffs() {
+            return (payoffs, Vec::new());
+        }
+        if depth == 0 {
+            return (state.heuristic_payoffs(), Vec::new());
+        }
+        let maximising = state.player_to_move() == root;
+        let mut best: Option<(Vec<f64>, Vec<G::Move>)> = None;
+        for mov in state.available_moves() {
+            let (payoffs, mut line) =
+                self.paranoid(&state.after_move(&mov), root, depth - 1, alpha, beta, nodes);
+            let value = payoffs[root];
+            let better = best.as_ref().is_none_or(|(best, _)| {
+                if maximising {
+                    value > best[root]
+                } else {
+                    value < best[root]
+                }
+            });
+            if better {
+                line.insert(0, mov);
+                best = Some((payoffs, line));
+            }
+            if maximising {
+                alpha = alpha.max(value);
+            } else {
+                beta = beta.min(value);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+        best.unwrap_or_else(|| (state.heuristic_payoffs(), Vec::new()))
+    }
+}
+
+impl<G> MultiplayerStrategy<G> for ParanoidStrategy
+where
+    G: MultiplayerGame,
+{
+    /// The payoffs are only exact for the player to move.
+    fn search_payoffs(&self, state: &G) -> MultiplayerSearch<G::Move> {
+        let mut nodes = 0;
+        let root = state.player_to_move();
+        let (payoffs, principal_variation) = self.paranoid(
+            state,
+            root,
+            self.depth,
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            &mut nodes,
+        );
+        MultiplayerSearch {
+            principal_variation,
+            payoffs,
+            nodes,
+        }
+    }
+}
+
+impl<G, E> Strategy<G, E> for MaxnStrategy
+where
+    G: GameState,
+    E: Evaluator<G>,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Searches to the end of the game.
+    fn new() -> Self {
+        Self::with_depth(usize::MAX)
+    }
+
+    fn choose_move(&self, state: &G, _evaluator: &E) -> Option<G::Move> {
+        self.search_payoffs(state)
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    /// Reports once, scoring the position by the payoff of the player to move.
+    fn search(
+        &self,
+        state: &G,
+        _evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let info = search_info(state, self.search_payoffs(state), start);
+        report(&info);
+        info
+    }
+}
+
+impl<G, E> Strategy<G, E> for ParanoidStrategy
+where
+    G: GameState,
+    E: Evaluator<G>,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Searches to the end of the game.
+    fn new() -> Self {
+        Self::with_depth(usize::MAX)
+    }
+
+    fn choose_move(&self, state: &G, _evaluator: &E) -> Option<G::Move> {
+        self.search_payoffs(state)
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    /// Reports once, scoring the position by the payoff of the player to move.
+    fn search(
+        &self,
+        state: &G,
+        _evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let info = search_info(state, self.search_payoffs(state), start);
+        report(&info);
+        info
+    }
+}
+
+/// play_match() for any number of players: plays a game from the given state to the end, with
+/// each player using the strategy at their index, and returns the payoffs. A player whose
+/// strategy returns no move while the game is on gets 0 and the others share a payoff of 1.
+pub fn play_multiplayer<G>(mut state: G, strategies: &[&dyn MultiplayerStrategy<G>]) -> Vec<f64>
+where
+    G: MultiplayerGame,
+{
+    assert_eq!(
+        strategies.len(),
+        state.player_count(),
+        "one strategy per player"
+    );
+    loop {
+        if let Some(payoffs) = state.payoffs() {
+            return payoffs;
+        }
+        let player = state.player_to_move();
+        let search = strategies[player].search_payoffs(&state);
+        match search.principal_variation.into_iter().next() {
+            Some(mov) => state = state.after_move(&mov),
+            None => {
+                let others = (state.player_count() - 1) as f64;
+                return (0..state.player_count())
+                    .map(|index| if index == player { 0.0 } else { 1.0 / others })
+                    .collect();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        play_multiplayer, MaxnStrategy, MultiplayerGame, MultiplayerStrategy, ParanoidStrategy,
+    };
+    use crate::evaluate::TerminalStateEvaluator;
+    use crate::game::{GameState, PlayerIndex};
+    use crate::strategy::{play_match, Score, Strategy};
+    use crate::tic_tac_toe::{BoardState, PerfectPlayTable};
+
+    /// Three players take turns taking one or two stones from a pile. Whoever takes the last
+    /// stone wins.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct LastStone {
+        stones: u32,
+        to_move: PlayerIndex,
+    }
+
+    impl MultiplayerGame for LastStone {
+        type Move = u32;
+
+        fn player_count(&self) -> usize {
+            3
+        }
+
+        fn player_to_move(&self) -> PlayerIndex {
+            self.to_move
+        }
+
+        fn available_moves(&self) -> Vec<u32> {
+            (1..=self.stones.min(2)).collect()
+        }
+
+        fn after_move(&self, mov: &u32) -> Self {
+            Self {
+                stones: self.stones - mov,
+                to_move: (self.to_move + 1) % 3,
+            }
+        }
+
+        fn payoffs(&self) -> Option<Vec<f64>> {
+            (self.stones == 0).then(|| {
+                let winner = (self.to_move + 2) % 3;
+                (0..3).map(|player| f64::from(player == winner)).collect()
+            })
+        }
+    }
+
+    #[test]
+    fn test_multiplayer_search() {
+        let pile = |stones| LastStone { stones, to_move: 0 };
+        let maxn = MaxnStrategy::with_depth(usize::MAX);
+        let paranoid = ParanoidStrategy::with_depth(usize::MAX);
+
+        // Whoever can take the whole pile does.
+        for stones in [1, 2] {
+            assert_eq!(
+                maxn.search_payoffs(&pile(stones)).payoffs,
+                vec![1.0, 0.0, 0.0]
+            );
+            assert_eq!(paranoid.search_payoffs(&pile(stones)).payoffs[0], 1.0);
+            assert_eq!(
+                maxn.search_payoffs(&pile(stones)).principal_variation,
+                vec![stones]
+            );
+        }
+        // With three stones every move leaves the next player a winning pile.
+        assert_eq!(maxn.search_payoffs(&pile(3)).payoffs[0], 0.0);
+
+        // From five stones, player 1 and then player 2 can't win whatever they do, and each
+        // takes one, leaving player 0 the last two. A coalition of the two would win instead.
+        let search = maxn.search_payoffs(&pile(5));
+        assert_eq!(search.payoffs, vec![1.0, 0.0, 0.0]);
+        assert_eq!(search.principal_variation, vec![1, 1, 1, 2]);
+        for stones in 3..10 {
+            assert_eq!(
+                paranoid.search_payoffs(&pile(stones)).payoffs[0],
+                0.0,
+                "{}",
+                stones
+            );
+        }
+        assert!(paranoid.search_payoffs(&pile(9)).nodes < maxn.search_payoffs(&pile(9)).nodes);
+
+        let payoffs = play_multiplayer(pile(5), &[&maxn, &maxn, &maxn]);
+        assert_eq!(payoffs, vec![1.0, 0.0, 0.0]);
+        let payoffs = play_multiplayer(pile(5), &[&maxn, &paranoid, &maxn]);
+        assert_eq!(payoffs.iter().sum::<f64>(), 1.0);
+
+        // Cut off before the end, a game without a heuristic shares the payoffs out evenly.
+        let shallow = MaxnStrategy::with_depth(1).search_payoffs(&pile(9));
+        assert_eq!(shallow.payoffs, vec![1.0 / 3.0; 3]);
+    }
+
+    #[test]
+    fn test_two_player_maxn_and_paranoid() {
+        // Two-player games are multiplayer games with a payoff of 1 for a win.
+        let board = BoardState::new();
+        assert_eq!(board.player_count(), 2);
+        assert_eq!(board.player_to_move(), 0);
+        assert_eq!(board.payoffs(), None);
+        let won: BoardState = "XXX/OO_/___ o".parse().unwrap();
+        assert_eq!(won.payoffs(), Some(vec![1.0, 0.0]));
+
+        // With two players and a win for one being a loss for the other, both are minimax, and the
+        // score is the payoff of the player to move rescaled to between -1 and 1.
+        let maxn = MaxnStrategy::with_depth(usize::MAX);
+        let paranoid = ParanoidStrategy::with_depth(usize::MAX);
+        let table = PerfectPlayTable::new();
+        for (board, result) in table.iter() {
+            if board.game_result().is_determined() {
+                continue;
+            }
+            let value = f64::from(result.score(board.current_player()));
+            let info = paranoid.search(board, &TerminalStateEvaluator, &mut |_| {});
+            assert_eq!(info.score, Some(Score::Value(value)), "{}", board);
+            assert!(table
+                .perfect_moves(board)
+                .contains(info.best_move().unwrap()));
+            let info = maxn.search(board, &TerminalStateEvaluator, &mut |_| {});
+            assert_eq!(info.score, Some(Score::Value(value)), "{}", board);
+            assert!(table
+                .perfect_moves(board)
+                .contains(info.best_move().unwrap()));
+        }
+
+        // They are ordinary strategies, so they can play matches against each other.
+        let score = play_match::<BoardState, _, _, _>(
+            &maxn,
+            &paranoid,
+            &TerminalStateEvaluator,
+            2,
+            &mut |_| {},
+        );
+        assert_eq!(score.draws, 2);
+    }
+}
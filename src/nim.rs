@@ -0,0 +1,228 @@
+use crate::{game::*, retrograde::*};
+use std::fmt::Display;
+
+/// Nim with HEAPS heaps of at most SIZE stones each. A move takes any number of stones from a
+/// single heap, and whoever takes the last stone wins. The player to move loses exactly when the
+/// bitwise xor of the heap sizes is 0, which makes it an easy check for solvers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Nim<const HEAPS: usize, const SIZE: usize> {
+    heaps: [u8; HEAPS],
+    to_move: Player,
+}
+
+/// Takes the given number of stones from the heap with the given index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NimMove {
+    pub heap: u8,
+    pub stones: u8,
+}
+
+impl<const HEAPS: usize, const SIZE: usize> Nim<HEAPS, SIZE> {
+    /// Returns a game with the given heaps and Player One to move. Panics if a heap holds more
+    /// than SIZE stones.
+    pub fn with_heaps(heaps: [u8; HEAPS]) -> Self {
+        Self::check_size();
+        assert!(
+            heaps.iter().all(|&heap| heap as usize <= SIZE),
+            "a heap can hold at most {} stones",
+            SIZE
+        );
+        Self {
+            heaps,
+            to_move: Player::One,
+        }
+    }
+
+    /// Returns the number of stones in the heap with the given index.
+    pub fn heap(&self, heap: usize) -> u8 {
+        self.heaps[heap]
+    }
+
+    fn check_size() {
+        assert!(HEAPS > 0, "there has to be at least one heap");
+        assert!(
+            SIZE <= u8::MAX as usize,
+            "a heap can hold at most 255 stones"
+        );
+    }
+}
+
+impl<const HEAPS: usize, const SIZE: usize> GameState for Nim<HEAPS, SIZE> {
+    type Move = NimMove;
+
+    /// Returns a game with SIZE stones in every heap.
+    fn new() -> Self {
+        Self::with_heaps([SIZE as u8; HEAPS])
+    }
+
+    fn legal_moves(&self) -> Vec<NimMove> {
+        (0..HEAPS)
+            .flat_map(|heap| {
+                (1..=self.heaps[heap]).map(move |stones| NimMove {
+                    heap: heap as u8,
+                    stones,
+                })
+            })
+            .collect()
+    }
+
+    fn apply_move(&mut self, mov: &NimMove) {
+        self.heaps[mov.heap as usize] -= mov.stones;
+        self.to_move.flip_player();
+    }
+
+    fn next_state(&self, mov: &NimMove) -> Self {
+        let mut next = *self;
+        next.apply_move(mov);
+        next
+    }
+
+    fn validate_move(&self, mov: &NimMove) -> Result<(), MoveError> {
+        if self.game_result().is_determined() {
+            Err(MoveError::GameOver)
+        } else if mov.heap as usize >= HEAPS || mov.stones == 0 {
+            Err(MoveError::OutOfRange)
+        } else if mov.stones > self.heaps[mov.heap as usize] {
+            Err(MoveError::Illegal)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Once every heap is empty, the player who took the last stone has won.
+    fn game_result(&self) -> GameResult {
+        if self.heaps.iter().all(|&heap| heap == 0) {
+            GameResult::Win(self.to_move.other_player())
+        } else {
+            GameResult::Undetermined
+        }
+    }
+
+    fn current_player(&self) -> Player {
+        self.to_move
+    }
+}
+
+/// Positions are numbered by reading the heaps as the digits of a base SIZE + 1 number, the first
+/// heap being the least significant, with the positions where Player Two is to move after all of
+/// those where Player One is. Every index is a position.
+impl<const HEAPS: usize, const SIZE: usize> Enumerable for Nim<HEAPS, SIZE> {
+    fn index_count() -> usize {
+        2 * (SIZE + 1).pow(HEAPS as u32)
+    }
+
+    fn index(&self) -> usize {
+        let heaps = self
+            .heaps
+            .iter()
+            .rev()
+            .fold(0, |index, &heap| index * (SIZE + 1) + heap as usize);
+        self.to_move.index() * (SIZE + 1).pow(HEAPS as u32) + heaps
+    }
+
+    fn from_index(index: usize) -> Option<Self> {
+        Self::check_size();
+        if index >= Self::index_count() {
+            return None;
+        }
+        let positions = (SIZE + 1).pow(HEAPS as u32);
+        let mut rest = index % positions;
+        let mut heaps = [0; HEAPS];
+        for heap in &mut heaps {
+            *heap = (rest % (SIZE + 1)) as u8;
+            rest /= SIZE + 1;
+        }
+        Some(Self {
+            heaps,
+            to_move: Player::from_index(index / positions)?,
+        })
+    }
+}
+
+impl<const HEAPS: usize, const SIZE: usize> Retractable for Nim<HEAPS, SIZE> {
+    /// Puts back every number of stones that still fits on each heap in turn.
+    fn predecessors(&self) -> Vec<Self> {
+        let mut predecessors = Vec::new();
+        for heap in 0..HEAPS {
+            for stones in 1..=SIZE as u8 - self.heaps[heap] {
+                let mut previous = *self;
+                previous.heaps[heap] += stones;
+                previous.to_move.flip_player();
+                predecessors.push(previous);
+            }
+        }
+        predecessors
+    }
+}
+
+impl<const HEAPS: usize, const SIZE: usize> Display for Nim<HEAPS, SIZE> {
+    /// Writes the heap sizes separated by spaces.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let heaps: Vec<String> = self.heaps.iter().map(|heap| heap.to_string()).collect();
+        write!(f, "{}", heaps.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Nim, NimMove};
+    use crate::evaluate::Evaluator;
+    use crate::game::{GameResult, GameState, MoveError, Player};
+    use crate::retrograde::{
+        DatabaseEvaluator, DatabaseValue, EndgameDatabase, Enumerable, Retractable,
+    };
+
+    type SmallNim = Nim<3, 4>;
+
+    #[test]
+    fn test_nim_database() {
+        let game = SmallNim::with_heaps([1, 2, 4]);
+        assert_eq!(game.legal_moves().len(), 7);
+        assert_eq!(
+            game.validate_move(&NimMove { heap: 0, stones: 2 }),
+            Err(MoveError::Illegal)
+        );
+        let over = SmallNim::with_heaps([0, 0, 1]).next_state(&NimMove { heap: 2, stones: 1 });
+        assert_eq!(over.game_result(), GameResult::Win(Player::One));
+
+        for index in 0..SmallNim::index_count() {
+            let game = SmallNim::from_index(index).unwrap();
+            assert_eq!(game.index(), index);
+            for previous in game.predecessors() {
+                assert!(previous.reachable_states().contains(&game));
+            }
+        }
+
+        // The player to move loses exactly when the heaps xor to 0.
+        let database = EndgameDatabase::<SmallNim>::build().unwrap();
+        for index in 0..SmallNim::index_count() {
+            let game = SmallNim::from_index(index).unwrap();
+            let nim_sum = (0..3).fold(0, |sum, heap| sum ^ game.heap(heap));
+            let value = database.probe(&game).unwrap();
+            assert_eq!(
+                matches!(value, DatabaseValue::Loss(_)),
+                nim_sum == 0,
+                "{}",
+                game
+            );
+        }
+        assert_eq!(
+            database.probe(&SmallNim::with_heaps([0, 0, 3])),
+            Some(DatabaseValue::Win(1))
+        );
+        assert_eq!(
+            database.probe(&SmallNim::with_heaps([1, 1, 0])),
+            Some(DatabaseValue::Loss(2))
+        );
+
+        // Evening out the heaps is the only winning move.
+        let evaluator = DatabaseEvaluator::from_database(database);
+        let game = SmallNim::with_heaps([0, 1, 3]);
+        let winning: Vec<NimMove> = game
+            .legal_moves()
+            .into_iter()
+            .filter(|mov| matches!(evaluator.evaluate(&game, mov), DatabaseValue::Win(_)))
+            .collect();
+        assert_eq!(winning, vec![NimMove { heap: 2, stones: 2 }]);
+    }
+}
@@ -0,0 +1,481 @@
+use crate::{chance::*, evaluate::*, game::*, strategy::*};
+use std::fmt;
+
+/// The dice game Pig. On their turn a player rolls a die as often as they like, adding each roll
+/// to their turn total, until they either hold, banking the turn total, or roll a 1 and lose it.
+/// They have to roll at least once. The first to bank the target score wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pig {
+    target: u32,
+    scores: [u32; 2],
+    turn_total: u32,
+    to_move: Player,
+    /// Whether the die has been thrown and is waiting to land, i.e. this is a chance node.
+    rolling: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PigMove {
+    Roll,
+    Hold,
+}
+
+/// Optimal play of Pig, found by value iteration as in Neller and Presser's "Optimal Play of the
+/// Dice Game Pig". It holds the probability that the player to move wins from every position
+/// at the start of a roll, assuming both players play optimally from then on. This is ground
+/// truth for testing strategies on games with chance.
+#[derive(Debug, Clone)]
+pub struct PigPolicy {
+    target: u32,
+    /// Indexed by the score of the player to move, their opponent's score and the turn total.
+    wins: Vec<f64>,
+}
+
+/// A heuristic for searches that can't see to the end of a game of Pig, like ExpectimaxStrategy
+/// at its depth limit. A move is worth the mover's lead after it, counting the turn total as if it
+/// were banked, as a fraction of the target: a lead of a quarter of the target is a 62.5% chance
+/// of winning and a 37.5% chance of losing.
+#[derive(Debug, Clone, Copy)]
+pub struct PigEvaluator;
+
+impl Pig {
+    /// The usual target score.
+    pub const DEFAULT_TARGET: u32 = 100;
+
+    /// Returns a new game played to the given score, which must be positive.
+    pub fn with_target(target: u32) -> Self {
+        assert!(target > 0, "the target score must be positive");
+        Self {
+            target,
+            scores: [0, 0],
+            turn_total: 0,
+            to_move: Player::One,
+            rolling: false,
+        }
+    }
+
+    /// Returns a game in progress before a roll, with the player to move having the given score
+    /// and turn total and their opponent the other score.
+    pub fn from_scores(target: u32, to_move: Player, scores: [u32; 2], turn_total: u32) -> Self {
+        Self {
+            scores,
+            turn_total,
+            to_move,
+            ..Self::with_target(target)
+        }
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Returns the banked score of the given player.
+    pub fn score(&self, player: Player) -> u32 {
+        self.scores[player.index()]
+    }
+
+    /// Returns what the player to move stands to bank if they hold.
+    pub fn turn_total(&self) -> u32 {
+        self.turn_total
+    }
+
+    /// Returns the state after the die lands on the given number.
+    fn land(&self, roll: u32) -> Self {
+        let mut next = Self {
+            rolling: false,
+            ..*self
+        };
+        if roll == 1 {
+            next.turn_total = 0;
+            next.to_move.flip_player();
+        } else {
+            next.turn_total += roll;
+        }
+        next
+    }
+}
+
+impl GameState for Pig {
+    type Move = PigMove;
+
+    /// Returns a game played to 100.
+    fn new() -> Self {
+        Self::with_target(Self::DEFAULT_TARGET)
+    }
+
+    /// Holding is only allowed after rolling at least once. There are no moves while the die is
+    /// rolling.
+    fn legal_moves(&self) -> Vec<PigMove> {
+        if self.rolling || self.game_result().is_determined() {
+            Vec::new()
+        } else if self.turn_total == 0 {
+            vec![PigMove::Roll]
+        } else {
+            vec![PigMove::Roll, PigMove::Hold]
+        }
+    }
+
+    fn apply_move(&mut self, mov: &PigMove) {
+        match mov {
+            PigMove::Roll => self.rolling = true,
+            PigMove::Hold => {
+                self.scores[self.to_move.index()] += self.turn_total;
+                self.turn_total = 0;
+                self.to_move.flip_player();
+            }
+        }
+    }
+
+    fn next_state(&self, mov: &PigMove) -> Self {
+        let mut next = *self;
+        next.apply_move(mov);
+        next
+    }
+
+    fn validate_move(&self, mov: &PigMove) -> Result<(), MoveError> {
+        if self.game_result().is_determined() {
+            Err(MoveError::GameOver)
+        } else if self.rolling || (*mov == PigMove::Hold && self.turn_total == 0) {
+            Err(MoveError::Illegal)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn game_result(&self) -> GameResult {
+        if self.scores[0] >= self.target {
+            GameResult::Win(Player::One)
+        } else if self.scores[1] >= self.target {
+            GameResult::Win(Player::Two)
+        } else {
+            GameResult::Undetermined
+        }
+    }
+
+    fn current_player(&self) -> Player {
+        self.to_move
+    }
+
+    /// Player One's banked score minus Player Two's.
+    fn final_score(&self) -> Option<i32> {
+        self.game_result()
+            .is_determined()
+            .then(|| self.scores[0] as i32 - self.scores[1] as i32)
+    }
+}
+
+impl UndoMove for Pig {
+    type Undo = Self;
+
+    fn do_move(&mut self, mov: &PigMove) -> Self {
+        let previous = *self;
+        self.apply_move(mov);
+        previous
+    }
+
+    fn undo_move(&mut self, undo: Self) {
+        *self = undo;
+    }
+}
+
+impl MoveNotation for Pig {
+    /// Accepts "roll" or "hold", or just their first letters.
+    fn parse_move(&self, s: &str) -> Result<PigMove, ParseMoveError> {
+        let mov = match s {
+            "roll" | "r" => PigMove::Roll,
+            "hold" | "h" => PigMove::Hold,
+            _ => return Err(ParseMoveError::Invalid(s.to_string())),
+        };
+        match self.validate_move(&mov) {
+            Ok(()) => Ok(mov),
+            Err(_) => Err(ParseMoveError::Illegal(s.to_string())),
+        }
+    }
+
+    fn format_move(&self, mov: &PigMove) -> String {
+        mov.to_string()
+    }
+}
+
+impl StochasticGame for Pig {
+    fn is_chance_node(&self) -> bool {
+        self.rolling
+    }
+
+    /// The six faces of the die, equally likely.
+    fn chance_outcomes(&self) -> Vec<(Self, Probability)> {
+        if self.rolling {
+            (1..=6).map(|roll| (self.land(roll), 1.0 / 6.0)).collect()
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+impl Evaluator<Pig> for PigEvaluator {
+    type Evaluation = Distribution;
+
+    fn new() -> Self {
+        Self
+    }
+
+    fn evaluate(&self, state: &Pig, mov: &PigMove) -> Distribution {
+        let next = state.next_state(mov);
+        let player = state.current_player();
+        match next.game_result() {
+            GameResult::Win(winner) if winner == player => return Distribution::win(),
+            GameResult::Win(_) => return Distribution::loss(),
+            _ => {}
+        }
+        let mut scores = next.scores;
+        scores[next.to_move.index()] += next.turn_total;
+        let lead = scores[player.index()] as f32 - scores[player.other_player().index()] as f32;
+        let lead = (lead / next.target as f32).clamp(-1.0, 1.0);
+        Distribution::with_probabilities((1.0 + lead) / 2.0, (1.0 - lead) / 2.0)
+    }
+}
+
+impl fmt::Display for PigMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PigMove::Roll => write!(f, "roll"),
+            PigMove::Hold => write!(f, "hold"),
+        }
+    }
+}
+
+impl PigPolicy {
+    /// How close successive sweeps have to be before value iteration stops.
+    const TOLERANCE: f64 = 1e-10;
+
+    /// Solves the game with the given target score. Every position depends on others, including
+    /// itself when both players keep rolling 1s, so the win probabilities are found by sweeping
+    /// over all of them, each time setting each to its value given the current estimates of the
+    /// rest, until they stop changing. Takes about a second for a target of 100 in a release
+    /// build.
+    pub fn solve(target: u32) -> Self {
+        assert!(target > 0, "the target score must be positive");
+        let size = target as usize;
+        let mut policy = Self {
+            target,
+            wins: vec![0.0; size * size * size],
+        };
+        loop {
+            let mut change: f64 = 0.0;
+            for mine in 0..target {
+                for theirs in 0..target {
+                    for turn_total in 0..target - mine {
+                        let (roll, hold) = policy.roll_and_hold(mine, theirs, turn_total);
+                        let value = roll.max(hold.unwrap_or(0.0));
+                        let index = policy.index(mine, theirs, turn_total);
+                        change = change.max((value - policy.wins[index]).abs());
+                        policy.wins[index] = value;
+                    }
+                }
+            }
+            if change < Self::TOLERANCE {
+                return policy;
+            }
+        }
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    fn index(&self, mine: u32, theirs: u32, turn_total: u32) -> usize {
+        let size = self.target as usize;
+        (mine as usize * size + theirs as usize) * size + turn_total as usize
+    }
+
+    /// The probability that the player to move wins with the given scores and turn total, before
+    /// they choose whether to roll.
+    fn wins(&self, mine: u32, theirs: u32, turn_total: u32) -> f64 {
+        if mine + turn_total >= self.target {
+            1.0
+        } else {
+            self.wins[self.index(mine, theirs, turn_total)]
+        }
+    }
+
+    /// The probabilities of winning by rolling and, if allowed, by holding.
+    fn roll_and_hold(&self, mine: u32, theirs: u32, turn_total: u32) -> (f64, Option<f64>) {
+        let pig_out = 1.0 - self.wins(theirs, mine, 0);
+        let roll = (pig_out
+            + (2..=6)
+                .map(|roll| self.wins(mine, theirs, turn_total + roll))
+                .sum::<f64>())
+            / 6.0;
+        let hold = (turn_total > 0).then(|| {
+            if mine + turn_total >= self.target {
+                1.0
+            } else {
+                1.0 - self.wins(theirs, mine + turn_total, 0)
+            }
+        });
+        (roll, hold)
+    }
+
+    /// Returns the probability that the given player wins from the given state with optimal play.
+    /// The state's target must be the policy's.
+    pub fn win_probability(&self, state: &Pig, player: Player) -> f64 {
+        assert_eq!(
+            state.target, self.target,
+            "the policy is for another target"
+        );
+        match state.game_result() {
+            GameResult::Undetermined if state.rolling => state
+                .chance_outcomes()
+                .iter()
+                .map(|(outcome, probability)| probability * self.win_probability(outcome, player))
+                .sum(),
+            GameResult::Undetermined => {
+                let to_move = state.to_move;
+                let wins = self.wins(
+                    state.score(to_move),
+                    state.score(to_move.other_player()),
+                    state.turn_total,
+                );
+                if player == to_move {
+                    wins
+                } else {
+                    1.0 - wins
+                }
+            }
+            result => (result.score(player) + 1) as f64 / 2.0,
+        }
+    }
+
+    /// Returns the probability that the player to move wins by making the given move, then playing
+    /// optimally.
+    pub fn move_value(&self, state: &Pig, mov: &PigMove) -> f64 {
+        self.win_probability(&state.next_state(mov), state.to_move)
+    }
+
+    /// Returns the optimal move, or None if the game is over or the die is rolling. Ties go to
+    /// rolling.
+    pub fn best_move(&self, state: &Pig) -> Option<PigMove> {
+        state.legal_moves().into_iter().reduce(|best, mov| {
+            if self.move_value(state, &mov) > self.move_value(state, &best) {
+                mov
+            } else {
+                best
+            }
+        })
+    }
+}
+
+impl<E> Strategy<Pig, E> for PigPolicy
+where
+    E: Evaluator<Pig>,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Solves the game to 100.
+    fn new() -> Self {
+        Self::solve(Pig::DEFAULT_TARGET)
+    }
+
+    /// Plays the optimal move. The state's target must be the policy's.
+    fn choose_move(&self, state: &Pig, _evaluator: &E) -> Option<PigMove> {
+        self.best_move(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pig, PigEvaluator, PigMove, PigPolicy};
+    use crate::chance::{play_chance_match, ChanceSearch, ExpectimaxStrategy, StochasticGame};
+    use crate::evaluate::{Evaluator, ResultDistribution};
+    use crate::game::{GameResult, GameState, MoveNotation, Player};
+    use crate::mcts::OpenLoopMctsStrategy;
+    use crate::strategy::Strategy;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_pig_rules() {
+        let state = Pig::with_target(20);
+        assert_eq!(state.legal_moves(), vec![PigMove::Roll]);
+        assert!(state.parse_move("hold").is_err());
+
+        let rolling = state.next_state(&PigMove::Roll);
+        assert!(rolling.is_chance_node());
+        assert!(rolling.legal_moves().is_empty());
+        let outcomes = rolling.chance_outcomes();
+        assert_eq!(outcomes.len(), 6);
+        assert!((outcomes.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-12);
+
+        // Rolling a 1 passes the turn with nothing banked.
+        let (pig_out, _) = outcomes[0];
+        assert_eq!(pig_out.current_player(), Player::Two);
+        assert_eq!(pig_out.turn_total(), 0);
+
+        let (five, _) = outcomes[4];
+        assert_eq!(five.turn_total(), 5);
+        assert_eq!(five.parse_move("h"), Ok(PigMove::Hold));
+        let held = five.next_state(&PigMove::Hold);
+        assert_eq!(held.score(Player::One), 5);
+        assert_eq!(held.current_player(), Player::Two);
+
+        let before_winning = Pig::from_scores(20, Player::Two, [3, 15], 6);
+        let won = before_winning.next_state(&PigMove::Hold);
+        assert_eq!(won.game_result(), GameResult::Win(Player::Two));
+        assert_eq!(before_winning.final_score(), None);
+        assert_eq!(won.final_score(), Some(-18));
+
+        // The evaluator values a move by the lead after it, as a fraction of the target.
+        let evaluator = PigEvaluator::new();
+        let hold = evaluator.evaluate(&five, &PigMove::Hold);
+        assert!((hold.expected_result() - 0.25).abs() < 1e-6);
+        let roll = evaluator.evaluate(&five, &PigMove::Roll);
+        assert!((roll.expected_result() - 0.25).abs() < 1e-6);
+        let winning = evaluator.evaluate(&before_winning, &PigMove::Hold);
+        assert_eq!(winning.win_prob(), 1.0);
+    }
+
+    #[test]
+    fn test_pig_policy() {
+        let policy = PigPolicy::solve(10);
+        let start = Pig::with_target(10);
+        let first = policy.win_probability(&start, Player::One);
+        assert!(first > 0.5 && first < 1.0);
+        assert!((first + policy.win_probability(&start, Player::Two) - 1.0).abs() < 1e-12);
+
+        // Holding wins outright once the turn total reaches the target.
+        let state = Pig::from_scores(10, Player::One, [6, 8], 4);
+        assert_eq!(policy.best_move(&state), Some(PigMove::Hold));
+        assert_eq!(policy.move_value(&state, &PigMove::Hold), 1.0);
+
+        // Expectimax and MCTS find the optimal move wherever it is clearly better.
+        let mut positions = 0;
+        for mine in 0..10 {
+            for theirs in 0..10 {
+                for turn_total in 1..10 - mine {
+                    let state = Pig::from_scores(10, Player::One, [mine, theirs], turn_total);
+                    let roll = policy.move_value(&state, &PigMove::Roll);
+                    let hold = policy.move_value(&state, &PigMove::Hold);
+                    if (roll - hold).abs() < 0.05 {
+                        continue;
+                    }
+                    positions += 1;
+                    let best = policy.best_move(&state);
+                    let star1 = ExpectimaxStrategy::with_depth(6, ChanceSearch::Star1);
+                    let mcts = OpenLoopMctsStrategy::with_iterations(2000, 1);
+                    for mov in [
+                        star1.choose_move(&state, &PigEvaluator),
+                        mcts.choose_move(&state, &PigEvaluator),
+                    ] {
+                        assert_eq!(mov, best, "{:?}", state);
+                    }
+                }
+            }
+        }
+        assert!(positions > 100);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let weak = OpenLoopMctsStrategy::with_iterations(10, 0);
+        let score = play_chance_match(&start, &policy, &weak, &PigEvaluator, 200, &mut rng);
+        assert_eq!(score.wins + score.losses, 200);
+        assert!(score.wins > score.losses, "{:?}", score);
+    }
+}
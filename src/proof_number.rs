@@ -0,0 +1,490 @@
+use crate::game::*;
+use std::{collections::HashMap, hash::Hash};
+
+/// Proof and disproof numbers. A proof number of 0 means the position is proven and one of INFINITY
+/// that it can't be; the same goes for disproof numbers.
+pub type ProofNumber = u64;
+
+pub const INFINITY: ProofNumber = ProofNumber::MAX;
+
+/// The outcome of a proof-number search.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofSearch<M> {
+    /// For prove_win(), Win(player) if the win was proven and Undetermined otherwise. For solve(),
+    /// the result of the game under perfect play, or Undetermined if the node limit ran out first.
+    pub result: GameResult,
+    /// The proof number of the root in the last search that was run.
+    pub proof_number: ProofNumber,
+    /// The disproof number of the root in the last search that was run.
+    pub disproof_number: ProofNumber,
+    /// The number of nodes expanded over all searches.
+    pub nodes: usize,
+    /// If a win was proven, a line of play in which the winner wins against any defence: the
+    /// winner's moves are proven wins and the loser's moves are the defence that lasts longest
+    /// among the ones the search happened to look at.
+    pub line: Vec<M>,
+}
+
+/// Searches that prove or disprove that a player can force a win. Proof-number searches grow the
+/// tree towards whichever part is cheapest to prove or disprove, so they can decide positions
+/// whose trees are far too big for the exhaustive TerminalStateEvaluator.
+pub trait Prover<G>
+where
+    G: GameState,
+{
+    /// Tries to prove that the given player can force a win from the state.
+    fn prove_win(&self, state: &G, player: Player) -> ProofSearch<G::Move>;
+
+    /// Proves the state a win, loss or draw for the player to move. Since each search only
+    /// separates wins from everything else, this first tries to prove a win for the player to
+    /// move and then one for their opponent; if both are disproven the game is a draw.
+    fn solve(&self, state: &G) -> ProofSearch<G::Move> {
+        let player = state.current_player();
+        let win = self.prove_win(state, player);
+        if win.proof_number == 0 || win.disproof_number != 0 {
+            return win;
+        }
+        let mut loss = self.prove_win(state, player.other_player());
+        loss.nodes += win.nodes;
+        if loss.disproof_number == 0 {
+            loss.result = GameResult::Draw;
+        }
+        loss
+    }
+}
+
+/// Classic best-first proof-number search, keeping the whole search tree in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct ProofNumberSearch {
+    /// The search gives up after expanding this many nodes.
+    pub max_nodes: usize,
+}
+
+/// Depth-first proof-number search. It reaches the same decisions as ProofNumberSearch but only
+/// keeps the current path on the stack, remembering proof numbers in a transposition table
+/// instead of an explicit tree.
+///
+/// The table is keyed by position alone, so DfPn is only for games in which a position can't
+/// repeat. If a line of play can come back to a position, the search can follow the cycle
+/// forever or take a result from one path for a position reached by another.
+#[derive(Debug, Clone, Copy)]
+pub struct DfPn {
+    /// The search gives up after expanding this many nodes.
+    pub max_nodes: usize,
+}
+
+struct Node<G>
+where
+    G: GameState,
+{
+    state: G,
+    mov: Option<G::Move>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    proof: ProofNumber,
+    disproof: ProofNumber,
+    /// The height of the subtree below the node, kept up to date by update_ancestors().
+    height: usize,
+}
+
+/// Returns the proof and disproof numbers of a position that hasn't been expanded yet.
+fn leaf_numbers<G>(state: &G, attacker: Player) -> (ProofNumber, ProofNumber)
+where
+    G: GameState,
+{
+    match state.game_result() {
+        GameResult::Undetermined => (1, 1),
+        GameResult::Win(winner) if winner == attacker => (0, INFINITY),
+        _ => (INFINITY, 0),
+    }
+}
+
+/// Proof numbers are summed over the children of AND nodes and disproof numbers over those of OR
+/// nodes, where infinity absorbs everything.
+fn sum(numbers: impl Iterator<Item = ProofNumber>) -> ProofNumber {
+    numbers.fold(0, ProofNumber::saturating_add)
+}
+
+impl ProofNumberSearch {
+    pub fn new(max_nodes: usize) -> Self {
+        Self { max_nodes }
+    }
+
+    /// Walks from the root to the leaf that would do most to decide the root: at the attacker's
+    /// nodes the child with the smallest proof number, at the defender's the child with the
+    /// smallest disproof number.
+    fn most_proving_node<G>(tree: &[Node<G>], attacker: Player) -> usize
+    where
+        G: GameState,
+    {
+        let mut index = 0;
+        while !tree[index].children.is_empty() {
+            let node = &tree[index];
+            index = if node.state.current_player() == attacker {
+                *node
+                    .children
+                    .iter()
+                    .min_by_key(|&&c| tree[c].proof)
+                    .unwrap()
+            } else {
+                *node
+                    .children
+                    .iter()
+                    .min_by_key(|&&c| tree[c].disproof)
+                    .unwrap()
+            };
+        }
+        index
+    }
+
+    /// Recomputes the numbers and height of the given node from its children, then does the same
+    /// for its ancestors.
+    fn update_ancestors<G>(tree: &mut [Node<G>], mut index: usize, attacker: Player)
+    where
+        G: GameState,
+    {
+        loop {
+            let node = &tree[index];
+            let proofs = node.children.iter().map(|&c| tree[c].proof);
+            let disproofs = node.children.iter().map(|&c| tree[c].disproof);
+            let (proof, disproof) = if node.children.is_empty() {
+                // An unfinished game without moves can't be won.
+                (INFINITY, 0)
+            } else if node.state.current_player() == attacker {
+                (proofs.min().unwrap(), sum(disproofs))
+            } else {
+                (sum(proofs), disproofs.min().unwrap())
+            };
+            let height = node.children.iter().map(|&c| tree[c].height + 1).max();
+            tree[index].proof = proof;
+            tree[index].disproof = disproof;
+            tree[index].height = height.unwrap_or(0);
+            match tree[index].parent {
+                Some(parent) => index = parent,
+                None => return,
+            }
+        }
+    }
+
+    /// Follows proven children from the root, choosing the longest defence for the defender.
+    fn winning_line<G>(tree: &[Node<G>], attacker: Player) -> Vec<G::Move>
+    where
+        G: GameState,
+        G::Move: Clone,
+    {
+        let mut line = Vec::new();
+        let mut index = 0;
+        while !tree[index].children.is_empty() {
+            let node = &tree[index];
+            let proven = node.children.iter().filter(|&&c| tree[c].proof == 0);
+            let next = if node.state.current_player() == attacker {
+                proven.min_by_key(|&&c| tree[c].height)
+            } else {
+                proven.max_by_key(|&&c| tree[c].height)
+            };
+            let Some(&next) = next else {
+                break;
+            };
+            line.push(tree[next].mov.clone().expect("only the root has no move"));
+            index = next;
+        }
+        line
+    }
+}
+
+impl<G> Prover<G> for ProofNumberSearch
+where
+    G: GameState + Clone,
+    G::Move: Clone,
+{
+    fn prove_win(&self, state: &G, player: Player) -> ProofSearch<G::Move> {
+        let (proof, disproof) = leaf_numbers(state, player);
+        let mut tree = vec![Node {
+            state: state.clone(),
+            mov: None,
+            parent: None,
+            children: Vec::new(),
+            proof,
+            disproof,
+            height: 0,
+        }];
+        let mut nodes = 0;
+        while tree[0].proof != 0 && tree[0].disproof != 0 && nodes < self.max_nodes {
+            let index = Self::most_proving_node(&tree, player);
+            nodes += 1;
+            for (child, mov) in tree[index].state.states_and_moves() {
+                let (proof, disproof) = leaf_numbers(&child, player);
+                let child_index = tree.len();
+                tree.push(Node {
+                    state: child,
+                    mov: Some(mov),
+                    parent: Some(index),
+                    children: Vec::new(),
+                    proof,
+                    disproof,
+                    height: 0,
+                });
+                tree[index].children.push(child_index);
+            }
+            Self::update_ancestors(&mut tree, index, player);
+        }
+
+        let proven = tree[0].proof == 0;
+        ProofSearch {
+            result: if proven {
+                GameResult::Win(player)
+            } else {
+                GameResult::Undetermined
+            },
+            proof_number: tree[0].proof,
+            disproof_number: tree[0].disproof,
+            nodes,
+            line: if proven {
+                Self::winning_line(&tree, player)
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+impl DfPn {
+    pub fn new(max_nodes: usize) -> Self {
+        Self { max_nodes }
+    }
+
+    /// Returns the proof and disproof numbers of the state, from the table if it has been
+    /// searched before.
+    fn numbers<G>(
+        table: &HashMap<G, (ProofNumber, ProofNumber)>,
+        state: &G,
+        attacker: Player,
+    ) -> (ProofNumber, ProofNumber)
+    where
+        G: GameState + Hash + Eq,
+    {
+        table
+            .get(state)
+            .copied()
+            .unwrap_or_else(|| leaf_numbers(state, attacker))
+    }
+
+    /// Searches below the state until its proof number reaches max_proof or its disproof number
+    /// reaches max_disproof, then stores its numbers in the table. Children are searched with
+    /// thresholds that make them return as soon as a sibling becomes the better one to search.
+    fn multiple_iterative_deepening<G>(
+        &self,
+        state: &G,
+        max_proof: ProofNumber,
+        max_disproof: ProofNumber,
+        attacker: Player,
+        table: &mut HashMap<G, (ProofNumber, ProofNumber)>,
+        nodes: &mut usize,
+    ) where
+        G: GameState + Clone + Hash + Eq,
+    {
+        if state.game_result().is_determined() {
+            table.insert(state.clone(), leaf_numbers(state, attacker));
+            return;
+        }
+        *nodes += 1;
+        let children = state.reachable_states();
+        if children.is_empty() {
+            table.insert(state.clone(), (INFINITY, 0));
+            return;
+        }
+
+        let is_attacker = state.current_player() == attacker;
+        loop {
+            let numbers: Vec<(ProofNumber, ProofNumber)> = children
+                .iter()
+                .map(|child| Self::numbers(table, child, attacker))
+                .collect();
+            let proofs = numbers.iter().map(|&(proof, _)| proof);
+            let disproofs = numbers.iter().map(|&(_, disproof)| disproof);
+            let (proof, disproof) = if is_attacker {
+                (proofs.min().unwrap(), sum(disproofs))
+            } else {
+                (sum(proofs), disproofs.min().unwrap())
+            };
+            if proof >= max_proof || disproof >= max_disproof || *nodes >= self.max_nodes {
+                table.insert(state.clone(), (proof, disproof));
+                return;
+            }
+
+            // Search the child with the smallest proof number at the attacker's nodes, or the
+            // smallest disproof number at the defender's, until it is no longer the smallest.
+            let key = |&(child_proof, child_disproof): &(ProofNumber, ProofNumber)| {
+                if is_attacker {
+                    child_proof
+                } else {
+                    child_disproof
+                }
+            };
+            let best = (0..numbers.len())
+                .min_by_key(|&i| key(&numbers[i]))
+                .unwrap();
+            let second = (0..numbers.len())
+                .filter(|&i| i != best)
+                .map(|i| key(&numbers[i]))
+                .min()
+                .unwrap_or(INFINITY);
+            let (best_proof, best_disproof) = numbers[best];
+            let (child_max_proof, child_max_disproof) = if is_attacker {
+                (
+                    max_proof.min(second.saturating_add(1)),
+                    Self::child_threshold(max_disproof, disproof, best_disproof),
+                )
+            } else {
+                (
+                    Self::child_threshold(max_proof, proof, best_proof),
+                    max_disproof.min(second.saturating_add(1)),
+                )
+            };
+            self.multiple_iterative_deepening(
+                &children[best],
+                child_max_proof,
+                child_max_disproof,
+                attacker,
+                table,
+                nodes,
+            );
+        }
+    }
+
+    /// Returns the number of moves in the winning line from a proven state, with the fastest win
+    /// at the attacker's nodes and the longest defence at the defender's.
+    fn line_length<G>(
+        table: &HashMap<G, (ProofNumber, ProofNumber)>,
+        state: &G,
+        attacker: Player,
+        lengths: &mut HashMap<G, usize>,
+    ) -> usize
+    where
+        G: GameState + Clone + Hash + Eq,
+    {
+        if state.game_result().is_determined() {
+            return 0;
+        }
+        if let Some(&length) = lengths.get(state) {
+            return length;
+        }
+        let children = state
+            .reachable_states()
+            .into_iter()
+            .filter(|child| Self::numbers(table, child, attacker).0 == 0)
+            .map(|child| 1 + Self::line_length(table, &child, attacker, lengths));
+        let length = if state.current_player() == attacker {
+            children.min()
+        } else {
+            children.max()
+        }
+        .unwrap_or(0);
+        lengths.insert(state.clone(), length);
+        length
+    }
+
+    /// The threshold for a child whose number is summed into its parent's: the parent stops once
+    /// the sum reaches its own threshold.
+    fn child_threshold(
+        threshold: ProofNumber,
+        total: ProofNumber,
+        child: ProofNumber,
+    ) -> ProofNumber {
+        if threshold == INFINITY {
+            INFINITY
+        } else {
+            threshold - total + child
+        }
+    }
+}
+
+impl<G> Prover<G> for DfPn
+where
+    G: GameState + Clone + Hash + Eq,
+{
+    fn prove_win(&self, state: &G, player: Player) -> ProofSearch<G::Move> {
+        let mut table = HashMap::new();
+        let mut nodes = 0;
+        self.multiple_iterative_deepening(
+            state, INFINITY, INFINITY, player, &mut table, &mut nodes,
+        );
+        let (proof_number, disproof_number) = Self::numbers(&table, state, player);
+
+        // Follow proven positions through the table to recover a winning line, taking the
+        // fastest win for the attacker and the longest defence for the defender.
+        let mut line = Vec::new();
+        if proof_number == 0 {
+            let mut lengths = HashMap::new();
+            let mut current = state.clone();
+            while !current.game_result().is_determined() {
+                let proven = current
+                    .states_and_moves()
+                    .into_iter()
+                    .filter(|(next, _)| Self::numbers(&table, next, player).0 == 0)
+                    .map(|(next, mov)| {
+                        let length = Self::line_length(&table, &next, player, &mut lengths);
+                        (next, mov, length)
+                    });
+                let next = if current.current_player() == player {
+                    proven.min_by_key(|&(_, _, length)| length)
+                } else {
+                    proven.max_by_key(|&(_, _, length)| length)
+                };
+                let Some((next, mov, _)) = next else {
+                    break;
+                };
+                line.push(mov);
+                current = next;
+            }
+        }
+
+        ProofSearch {
+            result: if proof_number == 0 {
+                GameResult::Win(player)
+            } else {
+                GameResult::Undetermined
+            },
+            proof_number,
+            disproof_number,
+            nodes,
+            line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DfPn, ProofNumberSearch, Prover};
+    use crate::game::{GameResult, GameState, Player};
+    use crate::tic_tac_toe::{BoardState, PerfectPlayTable};
+
+    #[test]
+    fn test_proof_number_search() {
+        let table = PerfectPlayTable::new();
+        for (board, result) in table.iter() {
+            for solution in [
+                ProofNumberSearch::new(usize::MAX).solve(board),
+                DfPn::new(usize::MAX).solve(board),
+            ] {
+                assert_eq!(solution.result, *result, "{}", board);
+                if let GameResult::Win(winner) = result {
+                    let mut end = *board;
+                    solution.line.iter().for_each(|mov| end.apply_move(mov));
+                    assert_eq!(end.game_result(), GameResult::Win(*winner));
+                }
+            }
+        }
+
+        let board: BoardState = "XO_/___/___ x".parse().unwrap();
+        for solution in [
+            ProofNumberSearch::new(usize::MAX).prove_win(&board, Player::One),
+            DfPn::new(usize::MAX).prove_win(&board, Player::One),
+        ] {
+            assert_eq!((solution.proof_number, solution.line.len()), (0, 5));
+        }
+        let solution = ProofNumberSearch::new(10).solve(&BoardState::new());
+        assert_eq!(solution.result, GameResult::Undetermined);
+        assert_eq!(solution.nodes, 10);
+    }
+}
@@ -0,0 +1,506 @@
+use crate::{
+    evaluate::{Distribution, ResultDistribution},
+    game::*,
+    strategy::{Score, SearchInfo},
+};
+use std::{fmt::Display, time::Duration};
+
+/// A single move of a recorded game, along with how long it took to choose and whatever the
+/// strategy had to say about it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoveRecord<M> {
+    pub mov: M,
+    /// Time spent choosing the move, if it was measured.
+    pub time: Option<Duration>,
+    /// What the strategy's search found when it chose the move, if it was recorded.
+    pub info: Option<SearchInfo<M>>,
+}
+
+/// A complete record of a game, from which the game can be replayed move by move.
+///
+/// Records have a line based text format, similar in spirit to PGN:
+/// ```text
+/// [Position "___/___/___ x"]
+/// [PlayerOne "alpha-beta"]
+/// [PlayerTwo "random"]
+/// [Result "1-0"]
+/// b2 12ms # depth 9 nodes 5123 value 0.25 pv b2 a1 c3
+/// a1 1ms
+/// ```
+/// The initial state is written with PositionNotation, so games started from a book opening or
+/// any other position can be reloaded from the text alone; without a Position header the game
+/// starts from new(). Header values escape quotes, backslashes and newlines with a backslash.
+/// Every move line starts with the move in the game's MoveNotation, optionally followed by the
+/// time taken in milliseconds and a comment holding the search information: the depth, the
+/// nodes, the score as one of "result 1-0", "value 0.25", "margin 3" or "distribution 0.6 0.1"
+/// (the chances of a win and a loss) and last the principal variation. The search's elapsed time
+/// is read back as the move's time.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "G: serde::Serialize, G::Move: serde::Serialize",
+        deserialize = "G: serde::Deserialize<'de>, G::Move: serde::Deserialize<'de>"
+    ))
+)]
+pub struct GameRecord<G>
+where
+    G: GameState,
+{
+    pub initial: G,
+    /// The names of Player One and Player Two, respectively.
+    pub players: [String; 2],
+    pub moves: Vec<MoveRecord<G::Move>>,
+    pub result: GameResult,
+}
+
+/// Errors that can occur while parsing a game record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordError {
+    /// A header line on the given (1-based) line is malformed or unknown, or the Position header
+    /// comes after the first move.
+    InvalidHeader { line: usize },
+    /// The Position header on the given line could not be parsed.
+    InvalidPosition { line: usize },
+    /// The move on the given line could not be parsed.
+    InvalidMove { line: usize, token: String },
+    /// The move on the given line is not legal in the position it is played from.
+    IllegalMove { line: usize, token: String },
+    /// The move time on the given line could not be parsed.
+    InvalidTime { line: usize },
+    /// The search information on the given line could not be parsed.
+    InvalidInfo { line: usize },
+}
+
+/// Iterator over the states of a recorded game. Yields the state after each move, together with
+/// the record of that move.
+pub struct Replay<'a, G>
+where
+    G: GameState,
+{
+    state: G,
+    moves: std::slice::Iter<'a, MoveRecord<G::Move>>,
+}
+
+impl<M> MoveRecord<M> {
+    pub fn new(mov: M) -> Self {
+        Self {
+            mov,
+            time: None,
+            info: None,
+        }
+    }
+}
+
+impl<G> GameRecord<G>
+where
+    G: GameState + Clone,
+{
+    /// Returns an empty record starting from the given state.
+    pub fn new(initial: G) -> Self {
+        Self {
+            initial,
+            players: Default::default(),
+            moves: Vec::new(),
+            result: GameResult::Undetermined,
+        }
+    }
+
+    /// Appends a move to the record.
+    pub fn push(
+        &mut self,
+        mov: G::Move,
+        time: Option<Duration>,
+        info: Option<SearchInfo<G::Move>>,
+    ) {
+        self.moves.push(MoveRecord { mov, time, info });
+    }
+
+    /// Steps through the game from the initial state, applying each recorded move in turn.
+    pub fn replay(&self) -> Replay<'_, G> {
+        Replay {
+            state: self.initial.clone(),
+            moves: self.moves.iter(),
+        }
+    }
+
+    /// Returns the state reached after playing every recorded move.
+    pub fn final_state(&self) -> G {
+        let mut state = self.initial.clone();
+        for record in &self.moves {
+            state.apply_move(&record.mov);
+        }
+        state
+    }
+
+    /// Parses a record in the text format described on GameRecord. Every move is checked for
+    /// legality as it is replayed.
+    pub fn from_text(text: &str) -> Result<Self, RecordError>
+    where
+        G: PositionNotation + MoveNotation,
+    {
+        let mut record = Self::new(G::new());
+        let mut state = record.initial.clone();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                record.parse_header(line, line_number)?;
+                if record.moves.is_empty() {
+                    state = record.initial.clone();
+                }
+                continue;
+            }
+
+            let (line, info) = match line.split_once('#') {
+                Some((line, info)) => (line, Some(info)),
+                None => (line, None),
+            };
+            let mut tokens = line.split_whitespace();
+            let token = tokens.next().unwrap_or_default();
+            let mov = state.parse_move(token).map_err(|error| match error {
+                ParseMoveError::Invalid(token) => RecordError::InvalidMove {
+                    line: line_number,
+                    token,
+                },
+                ParseMoveError::Illegal(token) => RecordError::IllegalMove {
+                    line: line_number,
+                    token,
+                },
+            })?;
+            let time = match tokens.next() {
+                Some(time) => {
+                    Some(parse_time(time).ok_or(RecordError::InvalidTime { line: line_number })?)
+                }
+                None => None,
+            };
+            let info = match info {
+                Some(info) => Some(
+                    parse_info(&state, info, time.unwrap_or_default())
+                        .ok_or(RecordError::InvalidInfo { line: line_number })?,
+                ),
+                None => None,
+            };
+            state.apply_move(&mov);
+            record.push(mov, time, info);
+        }
+        Ok(record)
+    }
+
+    fn parse_header(&mut self, line: &str, line_number: usize) -> Result<(), RecordError>
+    where
+        G: PositionNotation,
+    {
+        let error = RecordError::InvalidHeader { line: line_number };
+        let (key, value) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+            .and_then(|line| line.split_once(' '))
+            .ok_or(error.clone())?;
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .and_then(unescape)
+            .ok_or(error.clone())?;
+        let value = value.as_str();
+        match key {
+            "Position" if self.moves.is_empty() => {
+                self.initial = G::from_notation(value)
+                    .map_err(|_| RecordError::InvalidPosition { line: line_number })?;
+            }
+            "PlayerOne" => self.players[0] = value.to_string(),
+            "PlayerTwo" => self.players[1] = value.to_string(),
+            "Result" => self.result = parse_result(value).ok_or(error)?,
+            _ => return Err(error),
+        }
+        Ok(())
+    }
+}
+
+impl<G> Display for GameRecord<G>
+where
+    G: PositionNotation + MoveNotation + Clone,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "[Position \"{}\"]", self.initial.notation())?;
+        writeln!(f, "[PlayerOne \"{}\"]", escape(&self.players[0]))?;
+        writeln!(f, "[PlayerTwo \"{}\"]", escape(&self.players[1]))?;
+        writeln!(f, "[Result \"{}\"]", result_to_str(&self.result))?;
+        let mut state = self.initial.clone();
+        for record in &self.moves {
+            write!(f, "{}", state.format_move(&record.mov))?;
+            if let Some(time) = record.time {
+                write!(f, " {}ms", time.as_millis())?;
+            }
+            if let Some(info) = &record.info {
+                write!(f, " # {}", format_info(&state, info))?;
+            }
+            state.apply_move(&record.mov);
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, G> Iterator for Replay<'a, G>
+where
+    G: GameState + Clone,
+{
+    type Item = (G, &'a MoveRecord<G::Move>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.moves.next()?;
+        self.state.apply_move(&record.mov);
+        Some((self.state.clone(), record))
+    }
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::InvalidHeader { line } => write!(f, "line {}: invalid header", line),
+            RecordError::InvalidPosition { line } => write!(f, "line {}: invalid position", line),
+            RecordError::InvalidMove { line, token } => {
+                write!(f, "line {}: could not parse move '{}'", line, token)
+            }
+            RecordError::IllegalMove { line, token } => {
+                write!(f, "line {}: move '{}' is not legal here", line, token)
+            }
+            RecordError::InvalidTime { line } => write!(f, "line {}: invalid move time", line),
+            RecordError::InvalidInfo { line } => {
+                write!(f, "line {}: invalid search information", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+/// Results are written like in PGN, with Player One in the place of white.
+fn result_to_str(result: &GameResult) -> &'static str {
+    match result {
+        GameResult::Win(Player::One) => "1-0",
+        GameResult::Win(Player::Two) => "0-1",
+        GameResult::Draw => "1/2-1/2",
+        GameResult::Undetermined => "*",
+    }
+}
+
+fn parse_result(result: &str) -> Option<GameResult> {
+    match result {
+        "1-0" => Some(GameResult::Win(Player::One)),
+        "0-1" => Some(GameResult::Win(Player::Two)),
+        "1/2-1/2" => Some(GameResult::Draw),
+        "*" => Some(GameResult::Undetermined),
+        _ => None,
+    }
+}
+
+/// The name a recorded game gives a player using the given strategy: the strategy's type name
+/// without its module path or type parameters.
+pub(crate) fn type_name<S>() -> String {
+    let name = std::any::type_name::<S>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name).to_string()
+}
+
+/// Writes the search information for a move played from the given state, in the format described
+/// on GameRecord.
+fn format_info<G>(state: &G, info: &SearchInfo<G::Move>) -> String
+where
+    G: MoveNotation + Clone,
+{
+    let mut text = format!("depth {} nodes {}", info.depth, info.nodes);
+    match info.score {
+        Some(Score::Result(result)) => text += &format!(" result {}", result_to_str(&result)),
+        Some(Score::Value(value)) => text += &format!(" value {}", value),
+        Some(Score::Margin(margin)) => text += &format!(" margin {}", margin),
+        Some(Score::Distribution(distribution)) => {
+            text += &format!(
+                " distribution {} {}",
+                distribution.win_prob(),
+                distribution.loss_prob()
+            )
+        }
+        None => {}
+    }
+    if !info.principal_variation.is_empty() {
+        text += " pv";
+        let mut state = state.clone();
+        for mov in &info.principal_variation {
+            text += " ";
+            text += &state.format_move(mov);
+            state.apply_move(mov);
+        }
+    }
+    text
+}
+
+/// Parses search information written by format_info() for a move played from the given state.
+/// Every move of the principal variation has to be legal.
+fn parse_info<G>(state: &G, text: &str, elapsed: Duration) -> Option<SearchInfo<G::Move>>
+where
+    G: MoveNotation + Clone,
+{
+    let mut info = SearchInfo {
+        principal_variation: Vec::new(),
+        score: None,
+        depth: 0,
+        nodes: 0,
+        elapsed,
+    };
+    let mut tokens = text.split_whitespace();
+    while let Some(key) = tokens.next() {
+        match key {
+            "depth" => info.depth = tokens.next()?.parse().ok()?,
+            "nodes" => info.nodes = tokens.next()?.parse().ok()?,
+            "result" => info.score = Some(Score::Result(parse_result(tokens.next()?)?)),
+            "value" => info.score = Some(Score::Value(tokens.next()?.parse().ok()?)),
+            "margin" => info.score = Some(Score::Margin(tokens.next()?.parse().ok()?)),
+            "distribution" => {
+                let win: f32 = tokens.next()?.parse().ok()?;
+                let loss: f32 = tokens.next()?.parse().ok()?;
+                if win < 0.0 || loss < 0.0 || win + loss > 1.0 + 1e-6 {
+                    return None;
+                }
+                let distribution = Distribution::with_probabilities(win, loss);
+                info.score = Some(Score::Distribution(distribution));
+            }
+            "pv" => {
+                let mut state = state.clone();
+                for token in tokens.by_ref() {
+                    let mov = state.parse_move(token).ok()?;
+                    state.apply_move(&mov);
+                    info.principal_variation.push(mov);
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some(info)
+}
+
+/// Escapes a header value, so that it stays on one line and its closing quote can be found.
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Undoes escape(), returning None if the value has an unknown escape or an unescaped quote.
+fn unescape(value: &str) -> Option<String> {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            }),
+            '"' => return None,
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+fn parse_time(time: &str) -> Option<Duration> {
+    time.strip_suffix("ms")?
+        .parse()
+        .ok()
+        .map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameRecord, RecordError};
+    use crate::evaluate::Distribution;
+    use crate::game::{GameResult, GameState};
+    use crate::strategy::{Score, SearchInfo};
+    use crate::tic_tac_toe::{BoardState, Move};
+
+    #[test]
+    fn test_record_round_trip() {
+        let mut record = GameRecord::new(BoardState::new());
+        for square in [4, 0, 2, 6, 3, 5, 1, 7, 8] {
+            record.push(Move::from_square(square).unwrap(), None, None);
+        }
+        let time = std::time::Duration::from_millis(12);
+        record.moves[0].time = Some(time);
+        record.moves[0].info = Some(SearchInfo {
+            principal_variation: vec![Move::from_square(4).unwrap(), Move::from_square(0).unwrap()],
+            score: Some(Score::Result(GameResult::Draw)),
+            depth: 9,
+            nodes: 120,
+            elapsed: time,
+        });
+        record.moves[1].info = Some(SearchInfo {
+            principal_variation: Vec::new(),
+            score: Some(Score::Distribution(Distribution::with_probabilities(
+                0.5, 0.25,
+            ))),
+            depth: 2,
+            nodes: 7,
+            elapsed: Default::default(),
+        });
+        record.players = ["alpha-beta".to_string(), "\"quoted\"\nname\\".to_string()];
+        record.result = record.final_state().game_result();
+        assert_eq!(record.result, GameResult::Draw);
+
+        let text = record.to_string();
+        assert!(text.starts_with("[Position \"___/___/___ x\"]\n"));
+        assert!(text.contains("\nb2 12ms # depth 9 nodes 120 result 1/2-1/2 pv b2 a3\n"));
+        assert!(text.contains("\na3 # depth 2 nodes 7 distribution 0.5 0.25\n"));
+        assert!(text.contains("[PlayerTwo \"\\\"quoted\\\"\\nname\\\\\"]\n"));
+        let reloaded = GameRecord::<BoardState>::from_text(&text).unwrap();
+        assert_eq!(reloaded.moves, record.moves);
+        assert_eq!(reloaded.players, record.players);
+        assert_eq!(reloaded.result, record.result);
+        assert_eq!(reloaded.replay().count(), 9);
+        assert_eq!(reloaded.replay().last().unwrap().0, record.final_state());
+
+        // A game from a later position reloads without being told where it started.
+        let mut record = GameRecord::new("X__/_O_/___ x".parse::<BoardState>().unwrap());
+        record.push(Move::from_square(8).unwrap(), None, None);
+        let reloaded = GameRecord::<BoardState>::from_text(&record.to_string()).unwrap();
+        assert_eq!(reloaded.initial, record.initial);
+        assert_eq!(reloaded.final_state(), record.final_state());
+
+        let illegal = "[Result \"*\"]\nb2\nb2";
+        assert_eq!(
+            GameRecord::<BoardState>::from_text(illegal).unwrap_err(),
+            RecordError::IllegalMove {
+                line: 3,
+                token: "b2".to_string()
+            }
+        );
+        let late_position = "b2\n[Position \"___/___/___ x\"]";
+        assert!(GameRecord::<BoardState>::from_text(late_position).is_err());
+        let bad_info = "b2 # depth 9 pv b2 b2";
+        assert_eq!(
+            GameRecord::<BoardState>::from_text(bad_info).unwrap_err(),
+            RecordError::InvalidInfo { line: 1 }
+        );
+        let bad_position = "[Position \"XXX\"]";
+        assert_eq!(
+            GameRecord::<BoardState>::from_text(bad_position).unwrap_err(),
+            RecordError::InvalidPosition { line: 1 }
+        );
+    }
+}
@@ -0,0 +1,558 @@
+use crate::{evaluate::*, game::*};
+use std::{
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    marker::PhantomData,
+    path::Path,
+};
+
+/// Games whose positions can be numbered. Every legal position gets a unique index below
+/// index_count(), which lets a database store one entry per position in a flat array. Some
+/// indices may not correspond to any position.
+pub trait Enumerable: GameState {
+    /// Returns the number of indices i.e. one more than the largest index.
+    fn index_count() -> usize;
+
+    /// Returns the index of the position.
+    fn index(&self) -> usize;
+
+    /// Returns the position with the given index, or None if the index isn't used.
+    fn from_index(index: usize) -> Option<Self>;
+}
+
+/// Games that can generate the positions a move could have come from.
+pub trait Retractable: GameState {
+    /// Returns every position from which a legal move leads to this one, once for each such
+    /// move. Positions in which the game was already over may be included; they are ignored.
+    fn predecessors(&self) -> Vec<Self>;
+}
+
+/// The value of a position under perfect play, from the perspective of the player to move.
+/// Distances count the plies until the game ends, with the winner ending the game as quickly as
+/// possible and the loser holding out as long as possible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatabaseValue {
+    Win(u16),
+    Draw,
+    Loss(u16),
+}
+
+/// The solution of every position of a game, found by retrograde analysis and indexed as in
+/// Enumerable.
+///
+/// On disk a database is the magic bytes "MMDB", a little endian u32 version, a little endian u64
+/// number of entries and then one little endian u16 per entry: 0 if the index is unused, 1 for a
+/// draw, 2 + 2d for a win in d plies and 3 + 2d for a loss in d plies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndgameDatabase<G> {
+    entries: Vec<u16>,
+    game: PhantomData<G>,
+}
+
+/// Returned by EndgameDatabase::build() and DatabaseEvaluator::build() when the database can't be
+/// built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// The position with the given index is won or lost in more plies than an entry can hold.
+    DistanceTooLong { index: usize },
+    /// The predecessors of the position with the given index include one that isn't a position
+    /// under Enumerable, or more than its moves account for.
+    InconsistentPredecessors { index: usize },
+}
+
+/// Evaluates moves by looking up the position they lead to in an endgame database.
+#[derive(Debug, Clone)]
+pub struct DatabaseEvaluator<G> {
+    database: EndgameDatabase<G>,
+}
+
+const MAGIC: &[u8; 4] = b"MMDB";
+const VERSION: u32 = 1;
+
+impl Display for DatabaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DatabaseError::DistanceTooLong { index } => write!(
+                f,
+                "position {} is decided too many plies away to be stored",
+                index
+            ),
+            DatabaseError::InconsistentPredecessors { index } => write!(
+                f,
+                "position {} has predecessors that don't match the game's moves",
+                index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl DatabaseValue {
+    /// Returns the value from the other player's perspective.
+    pub fn other_perspective(&self) -> DatabaseValue {
+        match *self {
+            DatabaseValue::Win(distance) => DatabaseValue::Loss(distance),
+            DatabaseValue::Draw => DatabaseValue::Draw,
+            DatabaseValue::Loss(distance) => DatabaseValue::Win(distance),
+        }
+    }
+
+    /// Returns the value of the position one ply earlier, for a player who moves into a position
+    /// with this value for their opponent.
+    pub fn previous_ply(&self) -> DatabaseValue {
+        match self.other_perspective() {
+            DatabaseValue::Win(distance) => DatabaseValue::Win(distance + 1),
+            DatabaseValue::Draw => DatabaseValue::Draw,
+            DatabaseValue::Loss(distance) => DatabaseValue::Loss(distance + 1),
+        }
+    }
+
+    /// Returns the result of the game, given the player to move.
+    pub fn result(&self, player: Player) -> GameResult {
+        match *self {
+            DatabaseValue::Win(_) => GameResult::Win(player),
+            DatabaseValue::Draw => GameResult::Draw,
+            DatabaseValue::Loss(_) => GameResult::Win(player.other_player()),
+        }
+    }
+
+    /// Ranks values from worst to best: slow losses beat fast ones and fast wins beat slow ones.
+    fn rank(&self) -> i32 {
+        match *self {
+            DatabaseValue::Win(distance) => i32::MAX - distance as i32,
+            DatabaseValue::Draw => 0,
+            DatabaseValue::Loss(distance) => i32::MIN + distance as i32,
+        }
+    }
+
+    /// Returns the entry for the value, or None if the distance is too long to fit in a u16.
+    fn encode(value: Option<DatabaseValue>) -> Option<u16> {
+        match value {
+            None => Some(0),
+            Some(DatabaseValue::Draw) => Some(1),
+            Some(DatabaseValue::Win(distance)) => distance.checked_mul(2)?.checked_add(2),
+            Some(DatabaseValue::Loss(distance)) => distance.checked_mul(2)?.checked_add(3),
+        }
+    }
+
+    fn decode(entry: u16) -> Option<DatabaseValue> {
+        match entry {
+            0 => None,
+            1 => Some(DatabaseValue::Draw),
+            entry if entry % 2 == 0 => Some(DatabaseValue::Win((entry - 2) / 2)),
+            entry => Some(DatabaseValue::Loss((entry - 3) / 2)),
+        }
+    }
+}
+
+impl EvaluationValue for DatabaseValue {
+    /// Database values are already from the point of view of the player moving.
+    fn value(&self, _player: Player) -> f64 {
+        match self {
+            DatabaseValue::Win(_) => 1.0,
+            DatabaseValue::Draw => 0.0,
+            DatabaseValue::Loss(_) => -1.0,
+        }
+    }
+}
+
+impl PartialOrd for DatabaseValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DatabaseValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
+
+impl<G> EndgameDatabase<G>
+where
+    G: Enumerable + Retractable,
+{
+    /// Solves every position of the game by retrograde analysis.
+    ///
+    /// Finished positions are valued first. Working backwards from them, a position is a win as
+    /// soon as one of its moves leads to a loss for the opponent, and a loss once every one of its
+    /// moves has been found to lead to a win for the opponent. Positions are visited in order of
+    /// distance, so the first win found is the fastest and the last move of a loss the slowest.
+    /// Whatever is never resolved is a draw. Fails if some position takes too many plies to
+    /// decide for its distance to be stored, or if Retractable::predecessors() lists a position
+    /// that has no index or more times than it has moves.
+    pub fn build() -> Result<Self, DatabaseError> {
+        let count = G::index_count();
+        let mut values: Vec<Option<DatabaseValue>> = vec![None; count];
+        let mut valid = vec![false; count];
+        let mut unresolved_moves = vec![0usize; count];
+        let mut queue = VecDeque::new();
+
+        for index in 0..count {
+            let Some(state) = G::from_index(index) else {
+                continue;
+            };
+            valid[index] = true;
+            let result = state.game_result();
+            if result.is_determined() {
+                values[index] = Some(match result.score(state.current_player()) {
+                    1 => DatabaseValue::Win(0),
+                    -1 => DatabaseValue::Loss(0),
+                    _ => DatabaseValue::Draw,
+                });
+                queue.push_back(index);
+            } else {
+                unresolved_moves[index] = state.legal_moves().len();
+            }
+        }
+
+        while let Some(index) = queue.pop_front() {
+            let state = G::from_index(index).expect("only valid indices are queued");
+            let value = values[index].expect("only solved positions are queued");
+            for parent in state.predecessors() {
+                if parent.game_result().is_determined() {
+                    continue;
+                }
+                let parent_index = parent.index();
+                if !valid.get(parent_index).copied().unwrap_or(false) {
+                    return Err(DatabaseError::InconsistentPredecessors { index });
+                }
+                if values[parent_index].is_some() {
+                    continue;
+                }
+                let value = if parent.current_player() == state.current_player() {
+                    value
+                } else {
+                    value.other_perspective()
+                };
+                match value {
+                    DatabaseValue::Win(distance) => {
+                        values[parent_index] = Some(DatabaseValue::Win(distance.saturating_add(1)));
+                        queue.push_back(parent_index);
+                    }
+                    DatabaseValue::Loss(distance) => {
+                        unresolved_moves[parent_index] = unresolved_moves[parent_index]
+                            .checked_sub(1)
+                            .ok_or(DatabaseError::InconsistentPredecessors { index })?;
+                        if unresolved_moves[parent_index] == 0 {
+                            values[parent_index] =
+                                Some(DatabaseValue::Loss(distance.saturating_add(1)));
+                            queue.push_back(parent_index);
+                        }
+                    }
+                    DatabaseValue::Draw => {}
+                }
+            }
+        }
+
+        // Distances saturate above, so anything that overflowed fails to encode here.
+        let entries = values
+            .into_iter()
+            .zip(valid)
+            .enumerate()
+            .map(|(index, (value, valid))| {
+                let value = match value {
+                    None if valid => Some(DatabaseValue::Draw),
+                    value => value,
+                };
+                DatabaseValue::encode(value).ok_or(DatabaseError::DistanceTooLong { index })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            entries,
+            game: PhantomData,
+        })
+    }
+}
+
+impl<G> EndgameDatabase<G>
+where
+    G: Enumerable,
+{
+    /// Returns the value of the position, or None if it isn't in the database.
+    pub fn probe(&self, state: &G) -> Option<DatabaseValue> {
+        self.entries
+            .get(state.index())
+            .and_then(|&entry| DatabaseValue::decode(entry))
+    }
+
+    /// Writes the database to the given file in the format described on EndgameDatabase.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Writes the database in the format described on EndgameDatabase.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for entry in &self.entries {
+            writer.write_all(&entry.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a database written by save(). Fails if the file isn't a database or was built for a
+    /// game with a different number of indices.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads a database written by write_to(), failing as load() does.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut header = [0u8; 16];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(invalid("not an endgame database"));
+        }
+        if u32::from_le_bytes(header[4..8].try_into().unwrap()) != VERSION {
+            return Err(invalid("unsupported endgame database version"));
+        }
+        let count = u64::from_le_bytes(header[8..16].try_into().unwrap()) as usize;
+        if count != G::index_count() {
+            return Err(invalid("endgame database is for a different game"));
+        }
+
+        let mut bytes = vec![0u8; 2 * count];
+        reader.read_exact(&mut bytes)?;
+        let entries = bytes
+            .chunks_exact(2)
+            .map(|entry| u16::from_le_bytes([entry[0], entry[1]]))
+            .collect();
+        Ok(Self {
+            entries,
+            game: PhantomData,
+        })
+    }
+}
+
+impl<G> DatabaseEvaluator<G>
+where
+    G: Enumerable + Retractable,
+{
+    /// Builds the database from scratch, like Evaluator::new() but returning the error instead of
+    /// panicking if it can't be built.
+    pub fn build() -> Result<Self, DatabaseError> {
+        EndgameDatabase::build().map(Self::from_database)
+    }
+}
+
+impl<G> DatabaseEvaluator<G> {
+    /// Evaluates with a database that has already been built or read from a file.
+    pub fn from_database(database: EndgameDatabase<G>) -> Self {
+        Self { database }
+    }
+
+    pub fn database(&self) -> &EndgameDatabase<G> {
+        &self.database
+    }
+}
+
+impl<G> Evaluator<G> for DatabaseEvaluator<G>
+where
+    G: Enumerable + Retractable,
+{
+    /// Draw if the position after the move isn't in the database.
+    type Evaluation = DatabaseValue;
+
+    /// Builds the database from scratch, which can take a while for larger games. Panics if the
+    /// database can't be built; DatabaseEvaluator::build() returns the error instead.
+    fn new() -> Self {
+        Self::build().expect("failed to build endgame database")
+    }
+
+    /// Returns the value of the move for the player making it.
+    fn evaluate(&self, state: &G, mov: &G::Move) -> DatabaseValue {
+        let next_state = state.next_state(mov);
+        let value = self
+            .database
+            .probe(&next_state)
+            .unwrap_or(DatabaseValue::Draw);
+        if next_state.current_player() == state.current_player() {
+            match value {
+                DatabaseValue::Win(distance) => DatabaseValue::Win(distance + 1),
+                DatabaseValue::Draw => DatabaseValue::Draw,
+                DatabaseValue::Loss(distance) => DatabaseValue::Loss(distance + 1),
+            }
+        } else {
+            value.previous_ply()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tic_tac_toe::{BoardState, Move, PerfectPlayTable};
+
+    #[test]
+    fn test_encode_round_trip() {
+        for value in [
+            None,
+            Some(DatabaseValue::Draw),
+            Some(DatabaseValue::Win(0)),
+            Some(DatabaseValue::Loss(7)),
+            Some(DatabaseValue::Win(32766)),
+            Some(DatabaseValue::Loss(32766)),
+        ] {
+            let entry = DatabaseValue::encode(value).unwrap();
+            assert_eq!(DatabaseValue::decode(entry), value);
+        }
+        assert_eq!(DatabaseValue::encode(Some(DatabaseValue::Win(32767))), None);
+        assert_eq!(
+            DatabaseValue::encode(Some(DatabaseValue::Loss(u16::MAX))),
+            None
+        );
+    }
+
+    /// A pile of up to two stones that players take one at a time, whose predecessors wrongly
+    /// include a pile of three.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    struct Countdown {
+        left: u8,
+        to_move: Player,
+    }
+
+    impl GameState for Countdown {
+        type Move = ();
+
+        fn new() -> Self {
+            Self {
+                left: 2,
+                to_move: Player::One,
+            }
+        }
+
+        fn legal_moves(&self) -> Vec<()> {
+            if self.left > 0 {
+                vec![()]
+            } else {
+                vec![]
+            }
+        }
+
+        fn apply_move(&mut self, _mov: &()) {
+            self.left -= 1;
+            self.to_move.flip_player();
+        }
+
+        fn next_state(&self, mov: &()) -> Self {
+            let mut next = *self;
+            next.apply_move(mov);
+            next
+        }
+
+        fn validate_move(&self, _mov: &()) -> Result<(), MoveError> {
+            if self.left > 0 {
+                Ok(())
+            } else {
+                Err(MoveError::GameOver)
+            }
+        }
+
+        fn game_result(&self) -> GameResult {
+            if self.left == 0 {
+                GameResult::Win(self.to_move.other_player())
+            } else {
+                GameResult::Undetermined
+            }
+        }
+
+        fn current_player(&self) -> Player {
+            self.to_move
+        }
+    }
+
+    impl Enumerable for Countdown {
+        fn index_count() -> usize {
+            6
+        }
+
+        fn index(&self) -> usize {
+            2 * self.left as usize + self.to_move.index()
+        }
+
+        fn from_index(index: usize) -> Option<Self> {
+            (index < 6).then(|| Self {
+                left: (index / 2) as u8,
+                to_move: Player::from_index(index % 2).unwrap(),
+            })
+        }
+    }
+
+    impl Retractable for Countdown {
+        fn predecessors(&self) -> Vec<Self> {
+            vec![Self {
+                left: self.left + 1,
+                to_move: self.to_move.other_player(),
+            }]
+        }
+    }
+
+    #[test]
+    fn test_inconsistent_predecessors() {
+        let two_left = Countdown::new().index();
+        assert_eq!(
+            EndgameDatabase::<Countdown>::build(),
+            Err(DatabaseError::InconsistentPredecessors { index: two_left })
+        );
+    }
+
+    #[test]
+    fn test_endgame_database() {
+        for index in 0..BoardState::index_count() {
+            if let Some(board) = BoardState::from_index(index) {
+                assert_eq!(board.index(), index);
+                for previous in board.predecessors() {
+                    assert!(previous.reachable_states().contains(&board));
+                }
+            }
+        }
+
+        let database = EndgameDatabase::<BoardState>::build().unwrap();
+        let table = PerfectPlayTable::new();
+        for (board, result) in table.iter() {
+            let value = database.probe(board).unwrap();
+            assert_eq!(value.result(board.current_player()), *result);
+        }
+        assert_eq!(
+            database.probe(&BoardState::new()),
+            Some(DatabaseValue::Draw)
+        );
+        let board: BoardState = "XO_/_X_/O__ x".parse().unwrap();
+        assert_eq!(database.probe(&board), Some(DatabaseValue::Win(1)));
+        let board: BoardState = "XO_/___/___ x".parse().unwrap();
+        assert_eq!(database.probe(&board), Some(DatabaseValue::Win(5)));
+
+        let mut bytes = Vec::new();
+        database.write_to(&mut bytes).unwrap();
+        let loaded = EndgameDatabase::<BoardState>::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(loaded, database);
+        assert!(EndgameDatabase::<BoardState>::read_from(&bytes[..bytes.len() - 1]).is_err());
+
+        // The evaluator prefers the immediate win to the slower one.
+        assert_eq!(
+            DatabaseEvaluator::<BoardState>::build().unwrap().database(),
+            &loaded
+        );
+        let evaluator = DatabaseEvaluator::from_database(loaded);
+        let board: BoardState = "XO_/_X_/O__ x".parse().unwrap();
+        assert_eq!(
+            evaluator.evaluate(&board, &Move::from_square(8).unwrap()),
+            DatabaseValue::Win(1)
+        );
+        assert_eq!(
+            evaluator.evaluate(&board, &Move::from_square(3).unwrap()),
+            DatabaseValue::Win(3)
+        );
+        assert!(
+            evaluator.evaluate(&board, &Move::from_square(8).unwrap())
+                > evaluator.evaluate(&board, &Move::from_square(3).unwrap())
+        );
+    }
+}
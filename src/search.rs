@@ -0,0 +1,250 @@
+use crate::{evaluate::*, game::*, strategy::*};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Tells a search to stop. Clones share the same flag, so one can be handed to the search and
+/// another kept by whoever decides when it should end.
+#[derive(Debug, Clone, Default)]
+pub struct StopFlag(Arc<AtomicBool>);
+
+/// A search running on its own thread. The latest report is kept, so the best move so far can be
+/// read at any time, and the search can be stopped early.
+#[derive(Debug)]
+pub struct SearchHandle<M> {
+    stop: StopFlag,
+    latest: Arc<Mutex<Option<SearchInfo<M>>>>,
+    thread: JoinHandle<SearchInfo<M>>,
+}
+
+/// A search of the position expected after the opponent's reply, run while the opponent is
+/// thinking. If they play the expected move the search carries on from where it got to, otherwise
+/// it is thrown away.
+///
+/// The uci module drives it from UCI's `go ponder` and `ponderhit` commands.
+#[derive(Debug)]
+pub struct Ponder<M> {
+    expected: M,
+    handle: SearchHandle<M>,
+}
+
+impl StopFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl<M> SearchHandle<M>
+where
+    M: Clone + Send + 'static,
+{
+    /// Starts the strategy searching the state on a new thread.
+    pub fn spawn<G, E, S>(state: G, evaluator: E, strategy: S) -> Self
+    where
+        G: GameState<Move = M> + Send + 'static,
+        E: Evaluator<G, Evaluation = S::Evaluation> + Send + 'static,
+        S: Strategy<G, E> + Send + 'static,
+    {
+        let stop = StopFlag::new();
+        let latest = Arc::new(Mutex::new(None));
+        let thread = {
+            let stop = stop.clone();
+            let latest = Arc::clone(&latest);
+            thread::spawn(move || {
+                strategy.search_until(&state, &evaluator, &stop, &mut |info| {
+                    *latest.lock().unwrap() = Some(info.clone());
+                })
+            })
+        };
+        Self {
+            stop,
+            latest,
+            thread,
+        }
+    }
+
+    /// Returns a flag that stops this search, e.g. for a time manager on another thread.
+    pub fn stop_flag(&self) -> StopFlag {
+        self.stop.clone()
+    }
+
+    /// Asks the search to stop. It finishes shortly afterwards; use wait() to get its result.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Returns the most recent report of the search, if it has made one.
+    pub fn latest(&self) -> Option<SearchInfo<M>> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the search to finish and returns its result. Panics if the search panicked.
+    pub fn wait(self) -> SearchInfo<M> {
+        match self.thread.join() {
+            Ok(info) => info,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// Stops the search and returns the best it found.
+    pub fn stop_and_wait(self) -> SearchInfo<M> {
+        self.stop();
+        self.wait()
+    }
+
+    /// Lets the search run for at most the given time, then stops it and returns its result.
+    pub fn wait_for(self, limit: Duration) -> SearchInfo<M> {
+        let start = Instant::now();
+        while !self.is_finished() {
+            let elapsed = start.elapsed();
+            if elapsed >= limit {
+                break;
+            }
+            thread::sleep((limit - elapsed).min(Duration::from_millis(1)));
+        }
+        self.stop_and_wait()
+    }
+}
+
+impl<M> Ponder<M>
+where
+    M: Clone + PartialEq + Send + 'static,
+{
+    /// Starts pondering once our move has been played. The state is the position after our move
+    /// and info is the search that chose it, whose principal variation predicts the reply. Returns
+    /// None if it doesn't predict one or the predicted reply isn't legal.
+    ///
+    /// The strategy is moved to the search thread. For one that keeps something between moves,
+    /// pass a clone that shares it, like a clone of ReusingMctsStrategy, so the work carries over.
+    pub fn start<G, E, S>(state: G, info: &SearchInfo<M>, evaluator: E, strategy: S) -> Option<Self>
+    where
+        G: GameState<Move = M> + Send + 'static,
+        E: Evaluator<G, Evaluation = S::Evaluation> + Send + 'static,
+        S: Strategy<G, E> + Send + 'static,
+    {
+        let expected = info.principal_variation.get(1)?.clone();
+        Self::expecting(state, expected, evaluator, strategy)
+    }
+
+    /// Starts pondering on the given reply, for when something other than our own search
+    /// predicted it, like a GUI sending UCI's `go ponder`. Returns None if the reply isn't legal.
+    pub fn expecting<G, E, S>(state: G, expected: M, evaluator: E, strategy: S) -> Option<Self>
+    where
+        G: GameState<Move = M> + Send + 'static,
+        E: Evaluator<G, Evaluation = S::Evaluation> + Send + 'static,
+        S: Strategy<G, E> + Send + 'static,
+    {
+        state.validate_move(&expected).ok()?;
+        let handle = SearchHandle::spawn(state.next_state(&expected), evaluator, strategy);
+        Some(Self { expected, handle })
+    }
+
+    /// Returns the most recent report of the search, if it has made one.
+    pub fn latest(&self) -> Option<SearchInfo<M>> {
+        self.handle.latest()
+    }
+
+    /// Returns the reply the search is pondering on.
+    pub fn expected_move(&self) -> &M {
+        &self.expected
+    }
+
+    /// Tells the ponder search which move the opponent actually played. On a ponder hit the
+    /// search keeps running and is returned, to be stopped or waited for like any other. On a
+    /// miss it is stopped and None is returned, so a new search has to be started.
+    pub fn opponent_moved(self, mov: &M) -> Option<SearchHandle<M>> {
+        if *mov == self.expected {
+            Some(self.handle)
+        } else {
+            self.handle.stop_and_wait();
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Ponder, SearchHandle, StopFlag};
+    use crate::evaluate::TerminalStateEvaluator;
+    use crate::game::GameResult;
+    use crate::strategy::{AlphaBetaStrategy, Score, Strategy};
+    use crate::tic_tac_toe::BoardState;
+
+    #[test]
+    fn test_cancellable_search() {
+        let board = BoardState::new();
+
+        // Stopped before it starts, the search still returns a legal move.
+        let stop = StopFlag::new();
+        stop.stop();
+        let info = AlphaBetaStrategy::with_depth(usize::MAX).search_until(
+            &board,
+            &TerminalStateEvaluator,
+            &stop,
+            &mut |_| {},
+        );
+        assert_eq!((info.depth, info.score), (0, None));
+        assert!(board.validate_move(info.best_move().unwrap()).is_ok());
+
+        let handle = SearchHandle::spawn(
+            board,
+            TerminalStateEvaluator,
+            AlphaBetaStrategy::with_depth(usize::MAX),
+        );
+        let info = handle.wait();
+        assert_eq!(info.score, Some(Score::Result(GameResult::Draw)));
+
+        let handle = SearchHandle::spawn(
+            board,
+            TerminalStateEvaluator,
+            AlphaBetaStrategy::with_depth(usize::MAX),
+        );
+        let info = handle.wait_for(std::time::Duration::ZERO);
+        assert!(board.validate_move(info.best_move().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_ponder() {
+        let strategy = AlphaBetaStrategy::with_depth(usize::MAX);
+        let board = BoardState::new();
+        let info = strategy.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        let after = board.next_state(info.best_move().unwrap());
+        let reply = info.principal_variation[1];
+
+        let ponder = Ponder::start(after, &info, TerminalStateEvaluator, strategy).unwrap();
+        assert_eq!(*ponder.expected_move(), reply);
+        let handle = ponder.opponent_moved(&reply).unwrap();
+        let pondered = handle.wait();
+        assert_eq!(pondered.principal_variation, info.principal_variation[2..]);
+
+        let ponder = Ponder::start(after, &info, TerminalStateEvaluator, strategy).unwrap();
+        let other = after
+            .legal_moves()
+            .into_iter()
+            .find(|mov| *mov != reply)
+            .unwrap();
+        assert!(ponder.opponent_moved(&other).is_none());
+
+        // There is nothing to ponder once the game is over.
+        let board: BoardState = "XXX/OO_/___ o".parse().unwrap();
+        let info = strategy.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        assert!(Ponder::start(board, &info, TerminalStateEvaluator, strategy).is_none());
+    }
+}
@@ -0,0 +1,360 @@
+use crate::game::*;
+use std::{
+    fmt::{Display, Write as _},
+    fs, io,
+    path::Path,
+};
+
+/// One position of a recorded search. Everything the search knows about the position is kept as
+/// text or numbers, so trees from different games and searches can be exported the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeNode {
+    /// None for the root.
+    pub parent: Option<usize>,
+    /// Plies below the root.
+    pub depth: usize,
+    /// The position, as printed by its Display implementation.
+    pub state: String,
+    /// The move that led here from the parent.
+    pub mov: Option<String>,
+    /// The value the search gave the position, from the perspective of the player to move.
+    pub value: Option<f64>,
+    /// For searches that revisit positions like MCTS, the number of visits.
+    pub visits: Option<u64>,
+    /// For alpha-beta searches, the (alpha, beta) window the position was searched with.
+    pub bounds: Option<(f64, f64)>,
+    /// True if the search skipped this position after a cutoff.
+    pub pruned: bool,
+}
+
+/// The positions explored by a search, in the order they were reached. Children always come after
+/// their parent, so the root is the first node.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchTree {
+    nodes: Vec<TreeNode>,
+}
+
+/// Follows a depth-first search position by position. The search calls enter() when it reaches a
+/// position and leave() once it is done with it, so the calls nest like the search's recursion.
+/// AlphaBetaStrategy::search_observed() and LazySmpStrategy::search_observed() drive one.
+pub trait SearchObserver<G>
+where
+    G: GameState,
+{
+    /// Called before each iteration of an iterative deepening search. Everything observed since
+    /// the last call belongs to the previous iteration. Does nothing by default.
+    fn begin_iteration(&mut self) {}
+
+    /// The search reached the state by the given move, or None for the root, with the given
+    /// (alpha, beta) window if it has one.
+    fn enter(&mut self, state: &G, mov: Option<&G::Move>, bounds: Option<(f64, f64)>);
+
+    /// The search is done with the position entered last, which it gave the given value from the
+    /// perspective of the player to move. None if the search was stopped first.
+    fn leave(&mut self, value: Option<f64>);
+
+    /// The search skipped the given move from the position entered last, after a cutoff.
+    fn prune(&mut self, state: &G, mov: &G::Move);
+}
+
+/// A SearchObserver that builds a SearchTree of the last iteration it observed.
+#[derive(Debug, Clone, Default)]
+pub struct TreeRecorder {
+    tree: SearchTree,
+    /// The nodes entered and not yet left, from the root down.
+    path: Vec<usize>,
+}
+
+impl TreeNode {
+    /// Returns a node that hasn't been scored yet.
+    pub fn new<G>(state: &G, mov: Option<&G::Move>, parent: Option<usize>, depth: usize) -> Self
+    where
+        G: GameState + Display,
+        G::Move: Display,
+    {
+        Self {
+            parent,
+            depth,
+            state: state.to_string(),
+            mov: mov.map(|mov| mov.to_string()),
+            value: None,
+            visits: None,
+            bounds: None,
+            pruned: false,
+        }
+    }
+
+    /// The label shown for the node in DOT output.
+    fn label(&self) -> String {
+        let mut label = String::new();
+        if let Some(mov) = &self.mov {
+            label.push_str(&format!("move {}\n", mov));
+        }
+        label.push_str(&self.state);
+        if !label.ends_with('\n') {
+            label.push('\n');
+        }
+        if let Some(value) = self.value {
+            label.push_str(&format!("value {}\n", value));
+        }
+        if let Some(visits) = self.visits {
+            label.push_str(&format!("visits {}\n", visits));
+        }
+        if let Some((alpha, beta)) = self.bounds {
+            label.push_str(&format!("alpha {} beta {}\n", alpha, beta));
+        }
+        if self.pruned {
+            label.push_str("pruned\n");
+        }
+        label
+    }
+}
+
+impl SearchTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node and returns its index. The parent must already be in the tree.
+    pub fn add_node(&mut self, node: TreeNode) -> usize {
+        debug_assert!(node.parent.is_none_or(|parent| parent < self.nodes.len()));
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    pub fn node(&self, index: usize) -> &TreeNode {
+        &self.nodes[index]
+    }
+
+    pub fn node_mut(&mut self, index: usize) -> &mut TreeNode {
+        &mut self.nodes[index]
+    }
+
+    pub fn nodes(&self) -> &[TreeNode] {
+        &self.nodes
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the tree in Graphviz DOT format, keeping at most max_nodes nodes no more than
+    /// max_depth plies below the root. Pruned nodes and the edges to them are dashed and grey.
+    pub fn to_dot(&self, max_depth: usize, max_nodes: usize) -> String {
+        let mut included = vec![false; self.nodes.len()];
+        let mut dot =
+            String::from("digraph search {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut count = 0;
+        for (index, node) in self.nodes.iter().enumerate() {
+            if count == max_nodes {
+                break;
+            }
+            if node.depth > max_depth || node.parent.is_some_and(|parent| !included[parent]) {
+                continue;
+            }
+            included[index] = true;
+            count += 1;
+
+            let style = if node.pruned {
+                ", style=dashed, color=grey, fontcolor=grey"
+            } else {
+                ""
+            };
+            writeln!(
+                dot,
+                "    n{} [label=\"{}\"{}];",
+                index,
+                escape(&node.label()),
+                style
+            )
+            .unwrap();
+            if let Some(parent) = node.parent {
+                let label = node.mov.as_deref().map(escape).unwrap_or_default();
+                writeln!(
+                    dot,
+                    "    n{} -> n{} [label=\"{}\"{}];",
+                    parent,
+                    index,
+                    label,
+                    if node.pruned {
+                        ", style=dashed, color=grey"
+                    } else {
+                        ""
+                    }
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Writes the tree to the given file in the format of to_dot().
+    pub fn write_dot<P: AsRef<Path>>(
+        &self,
+        path: P,
+        max_depth: usize,
+        max_nodes: usize,
+    ) -> io::Result<()> {
+        fs::write(path, self.to_dot(max_depth, max_nodes))
+    }
+}
+
+impl TreeRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tree(&self) -> &SearchTree {
+        &self.tree
+    }
+
+    pub fn into_tree(self) -> SearchTree {
+        self.tree
+    }
+}
+
+impl<G> SearchObserver<G> for TreeRecorder
+where
+    G: GameState + Display,
+    G::Move: Display,
+{
+    fn begin_iteration(&mut self) {
+        self.tree = SearchTree::new();
+        self.path.clear();
+    }
+
+    fn enter(&mut self, state: &G, mov: Option<&G::Move>, bounds: Option<(f64, f64)>) {
+        let mut node = TreeNode::new(state, mov, self.path.last().copied(), self.path.len());
+        node.bounds = bounds;
+        let index = self.tree.add_node(node);
+        self.path.push(index);
+    }
+
+    fn leave(&mut self, value: Option<f64>) {
+        if let Some(index) = self.path.pop() {
+            self.tree.nodes[index].value = value;
+        }
+    }
+
+    fn prune(&mut self, state: &G, mov: &G::Move) {
+        let mut node = TreeNode::new(
+            &state.next_state(mov),
+            Some(mov),
+            self.path.last().copied(),
+            self.path.len(),
+        );
+        node.pruned = true;
+        self.tree.add_node(node);
+    }
+}
+
+/// Escapes text for a DOT label, left-justifying each line.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\l"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TreeRecorder;
+    use crate::evaluate::TerminalStateEvaluator;
+    use crate::lazy_smp::LazySmpStrategy;
+    use crate::mcts::MctsStrategy;
+    use crate::strategy::AlphaBetaStrategy;
+    use crate::tic_tac_toe::BoardState;
+
+    #[test]
+    fn test_search_tree_dot() {
+        // X wins, but only after several moves, so the deepest iteration has cutoffs.
+        let board: BoardState = "XO_/___/___ x".parse().unwrap();
+        let mut recorder = TreeRecorder::new();
+        let info = AlphaBetaStrategy::with_depth(9).search_observed(
+            &board,
+            &TerminalStateEvaluator,
+            &mut recorder,
+        );
+        let tree = recorder.into_tree();
+        assert_eq!(tree.node(0).value, Some(1.0));
+        assert_eq!(tree.node(0).bounds, Some((-1.0, 1.0)));
+        assert!(tree.nodes().iter().any(|node| node.pruned));
+        assert!(tree
+            .nodes()
+            .iter()
+            .all(|node| node.pruned == node.value.is_none()));
+        let searched = tree.nodes().iter().filter(|node| !node.pruned).count();
+        assert!(searched as u64 <= info.nodes);
+
+        let dot = tree.to_dot(1, 3);
+        assert!(dot.starts_with("digraph search {"));
+        assert!(dot.trim_end().ends_with('}'));
+        let nodes = dot
+            .lines()
+            .filter(|line| line.contains("[label=") && !line.contains("->"));
+        assert_eq!(nodes.count(), 3);
+        assert_eq!(dot.matches("->").count(), 2);
+
+        // One ply deep every move is searched, and the evaluator finds a draw below each of them.
+        let mut recorder = TreeRecorder::new();
+        AlphaBetaStrategy::with_depth(1).search_observed(
+            &BoardState::new(),
+            &TerminalStateEvaluator,
+            &mut recorder,
+        );
+        assert_eq!(recorder.tree().len(), 10);
+        assert!(recorder
+            .tree()
+            .nodes()
+            .iter()
+            .all(|node| node.value == Some(0.0)));
+
+        // Lazy SMP records its main thread's search.
+        let mut recorder = TreeRecorder::new();
+        LazySmpStrategy::with_threads(1).search_observed(
+            &board,
+            &TerminalStateEvaluator,
+            &mut recorder,
+        );
+        assert_eq!(recorder.tree().node(0).value, Some(1.0));
+        assert!(recorder.tree().len() > 1);
+    }
+
+    #[test]
+    fn test_mcts_search_tree() {
+        let board = BoardState::new();
+        let mcts = MctsStrategy::with_iterations(200, 7);
+        let tree = mcts.build_tree(&board);
+        let search_tree = tree.to_search_tree();
+        assert_eq!(search_tree.len(), tree.len());
+        assert_eq!(search_tree.node(0).visits, Some(200));
+
+        // Every iteration passes through one child of the root.
+        let child_visits: u64 = search_tree
+            .nodes()
+            .iter()
+            .filter(|node| node.parent == Some(0))
+            .map(|node| node.visits.unwrap())
+            .sum();
+        assert_eq!(child_visits, 200);
+        for node in search_tree.nodes() {
+            assert!(node
+                .value
+                .is_some_and(|value| (-1.0..=1.0).contains(&value)));
+            if let Some(parent) = node.parent {
+                assert_eq!(node.depth, search_tree.node(parent).depth + 1);
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use crate::{evaluate::*, game::*};
-use rand::seq::SliceRandom;
+use crate::{evaluate::*, game::*, record::*, search::StopFlag, search_tree::SearchObserver};
+use std::time::{Duration, Instant};
 
 /// This trait is used to actually compute the move taken given the current state of the game. It
 /// is intended that structs implementing this trait use information provided by the evaluator to
@@ -12,12 +12,100 @@ where
 {
     type Evaluation;
 
-    fn new() -> Self;
+    fn new() -> Self
+    where
+        Self: Sized;
     fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move>;
+
+    /// Chooses a move like choose_move(), but also explains the choice. Strategies that search in
+    /// iterations call report after each one, so a protocol or a log can follow along, and return
+    /// the last report. By default this just times choose_move() and reports once.
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let best_move = self.choose_move(state, evaluator);
+        let info = SearchInfo {
+            depth: usize::from(best_move.is_some()),
+            principal_variation: best_move.into_iter().collect(),
+            score: None,
+            nodes: 0,
+            elapsed: start.elapsed(),
+        };
+        report(&info);
+        info
+    }
+
+    /// Called with every move actually played in the game, whoever played it, along with the state
+    /// it was played from. Strategies that keep something between moves, like a search tree, can
+    /// use it to follow the game. Does nothing by default.
+    fn notify_move(&self, _state: &G, _mov: &G::Move) {}
+
+    /// Like search(), but returns early with the best move found so far once stop is set, e.g. by
+    /// a protocol's stop command or a time manager on another thread. Strategies that can't be
+    /// interrupted ignore the flag.
+    fn search_until(
+        &self,
+        state: &G,
+        evaluator: &E,
+        _stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search(state, evaluator, report)
+    }
+}
+
+/// How good the position is according to a search. GameResult is a proven outcome, while
+/// distributions and values are from the perspective of the player to move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Score {
+    Result(GameResult),
+    Distribution(Distribution),
+    Value(f64),
+    /// A proven final score, as the margin of the player to move.
+    Margin(i32),
+}
+
+/// What a search found and how much work it took.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchInfo<M> {
+    /// The line the search expects both players to follow, starting with the chosen move.
+    pub principal_variation: Vec<M>,
+    /// None if the strategy doesn't score positions.
+    pub score: Option<Score>,
+    /// The depth of the last completed iteration, in plies.
+    pub depth: usize,
+    /// The number of positions searched, or 0 if the strategy doesn't count them.
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl<M> SearchInfo<M> {
+    /// Returns the move the search chose, if there was one.
+    pub fn best_move(&self) -> Option<&M> {
+        self.principal_variation.first()
+    }
+
+    /// Returns the number of nodes searched per second.
+    pub fn nodes_per_second(&self) -> u64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            (self.nodes as f64 / seconds) as u64
+        } else {
+            0
+        }
+    }
 }
 
 /*
 TODO: This is broken after changes to API.
+use rand::seq::SliceRandom;
+
 pub struct RandomStrategy;
 
 impl<G, E> Strategy<G, E> for RandomStrategy
@@ -38,11 +126,10 @@ where
 }
 */
 
+/*
 /// This struct is intended to be used when your evaluator returns a value that can be ordered from
 /// least to most favorable e.g. Q-values. In this case, the evaluator should evaluate favorability
 /// from the perspective of whose turn the board state says it is.
-
-/*
 pub struct GreedyStrategy;
 
 TODO: This is broken with latest changes to API.
@@ -69,12 +156,33 @@ where
 }
 */
 
+/// The outcome of a match between two strategies, from the first one's point of view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl MatchScore {
+    /// Counts a game with the given result, played by the first strategy as the given player.
+    pub(crate) fn add(&mut self, result: GameResult, player: Player) {
+        match result.score(player) {
+            1 => self.wins += 1,
+            -1 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+}
+
+/// Plays perfectly by solving the game from every reachable state with the
+/// TerminalStateEvaluator. Only feasible for small games like tic-tac-toe.
 #[derive(Debug)]
 pub struct TerminalStateStrategy;
 
 impl<G> Strategy<G, TerminalStateEvaluator> for TerminalStateStrategy
 where
-    G: GameState,
+    G: UndoMove,
     G::Move: Clone,
 {
     type Evaluation = GameResult;
@@ -84,8 +192,7 @@ where
 
     // Computes the best move and returns Some(move). If there are no moves available return None.
     fn choose_move(&self, state: &G, evaluator: &TerminalStateEvaluator) -> Option<<G>::Move> {
-        let current_result = state.game_result();
-        if current_result != GameResult::Undetermined {
+        if state.game_result().is_determined() {
             return None;
         }
 
@@ -93,19 +200,628 @@ where
         let states_and_moves = state.states_and_moves();
         for (future_state, mov) in &states_and_moves {
             if future_state.game_result() == GameResult::Win(current_player) {
-                // TODO: If mov is expensive to clone this is suboptimal
                 return Some(mov.clone());
             }
         }
 
-        for (future_state, mov) in &states_and_moves {
-            if !future_state.game_result().is_determined() {
-                if let Some(opponents_move) = self.choose_move(future_state, evaluator) {
-                    //let expected_next_state = future_state.apply_move(&opponents_move)
+        let mut best: Option<(G::Move, i8)> = None;
+        for (_, mov) in states_and_moves {
+            let score = evaluator.evaluate(state, &mov).score(current_player);
+            if score == 1 {
+                return Some(mov);
+            }
+            if best
+                .as_ref()
+                .is_none_or(|(_, best_score)| score > *best_score)
+            {
+                best = Some((mov, score));
+            }
+        }
+        best.map(|(mov, _)| mov)
+    }
+
+    /// Reports the solved result of the position and the line of perfect play from it.
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &TerminalStateEvaluator,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let mut principal_variation = Vec::new();
+        let mut result = state.game_result();
+        if let Some(mov) = self.choose_move(state, evaluator) {
+            let mut current = state.next_state(&mov);
+            principal_variation.push(mov);
+            while let Some(mov) = self.choose_move(&current, evaluator) {
+                current.apply_move(&mov);
+                principal_variation.push(mov);
+            }
+            result = current.game_result();
+        }
+        let info = SearchInfo {
+            depth: principal_variation.len(),
+            principal_variation,
+            score: Some(Score::Result(result)),
+            nodes: 0,
+            elapsed: start.elapsed(),
+        };
+        report(&info);
+        info
+    }
+}
+
+/// Plays to win by as much as possible, by solving the final score from every reachable state with
+/// the MarginEvaluator. Only feasible for small games like Kalah with few seeds.
+#[derive(Debug)]
+pub struct MarginStrategy;
+
+impl<G> Strategy<G, MarginEvaluator> for MarginStrategy
+where
+    G: UndoMove + Clone,
+    G::Move: Clone,
+{
+    type Evaluation = FinalScore;
+
+    fn new() -> Self {
+        Self
+    }
+
+    /// Chooses the move with the largest margin, or None if the game is over. Ties go to the
+    /// earliest move.
+    fn choose_move(&self, state: &G, evaluator: &MarginEvaluator) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    /// Reports the solved final score of the position and the line of play that reaches it.
+    fn search(
+        &self,
+        state: &G,
+        _evaluator: &MarginEvaluator,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        let start = Instant::now();
+        let (score, principal_variation, nodes) = MarginEvaluator::solve_line(&mut state.clone());
+        let info = SearchInfo {
+            depth: principal_variation.len(),
+            principal_variation,
+            score: Some(Score::Margin(score.margin(state.current_player()))),
+            nodes,
+            elapsed: start.elapsed(),
+        };
+        report(&info);
+        info
+    }
+}
+
+/// Alpha-beta search with iterative deepening: it searches one ply deeper each iteration until it
+/// finds a forced result, sees the whole game or reaches max_depth. Finished games are scored by
+/// their final score, so among wins it goes for the biggest margin. A position at the depth limit
+/// is worth the value of the evaluator's best move there, which is never mistaken for a forced
+/// result, so it only plays perfectly once it can see to the end of the game.
+#[derive(Debug, Clone, Copy)]
+pub struct AlphaBetaStrategy {
+    pub max_depth: usize,
+}
+
+/// The score of winning on the spot. Wins further away score one less per ply, so the search
+/// prefers quick wins and slow losses.
+pub(crate) const WIN: i32 = 1_000_000;
+
+/// Scores this close to WIN are forced results rather than guesses.
+pub(crate) const WIN_THRESHOLD: i32 = WIN - 10_000;
+
+/// What an evaluation of 1 scores at the depth limit. Far below WIN_THRESHOLD, so however the
+/// evaluator rates a position it is never taken for a forced result.
+pub(crate) const EVALUATION_SCALE: i32 = 10_000;
+
+/// The most a finished game's margin can add to its score, which keeps wins scoring below WIN and
+/// above WIN_THRESHOLD.
+const MAX_MARGIN: i32 = 1_000;
+
+/// Scores a finished game, ply plies below the root, for the player to move, and returns None if
+/// the game isn't over. A win scores WIN less MAX_MARGIN and a point per ply, plus the winning
+/// margin from GameState::final_score(), and a loss the negation of that, so the search prefers
+/// bigger wins, then quicker ones, and smaller, slower losses.
+pub(crate) fn terminal_score<G>(state: &G, ply: i32) -> Option<i32>
+where
+    G: GameState,
+{
+    let margin = FinalScore(state.final_score()?).margin(state.current_player());
+    let margin = margin.clamp(-MAX_MARGIN, MAX_MARGIN);
+    Some(margin.signum() * (WIN - MAX_MARGIN - ply) + margin)
+}
+
+/// Scores a position at the depth limit for the player to move: the value of the evaluator's best
+/// move there, scaled by EVALUATION_SCALE. 0 if there are no moves.
+pub(crate) fn leaf_score<G, E>(state: &G, evaluator: &E) -> i32
+where
+    G: GameState,
+    E: Evaluator<G>,
+    E::Evaluation: EvaluationValue,
+{
+    let player = state.current_player();
+    state
+        .legal_moves()
+        .iter()
+        .map(|mov| evaluator.evaluate(state, mov).value(player))
+        .reduce(f64::max)
+        .map_or(0, |value| {
+            (value.clamp(-1.0, 1.0) * f64::from(EVALUATION_SCALE)).round() as i32
+        })
+}
+
+/// Turns the score of an alpha-beta search into a Score for the player to move, who is given.
+/// Scores that aren't forced results are proven draws if the search saw the end of every line,
+/// and otherwise the evaluator's values between -1 and 1.
+pub(crate) fn alpha_beta_score(score: i32, player: Player, complete: bool) -> Score {
+    if score > WIN_THRESHOLD {
+        Score::Result(GameResult::Win(player))
+    } else if score < -WIN_THRESHOLD {
+        Score::Result(GameResult::Win(player.other_player()))
+    } else if complete {
+        Score::Result(GameResult::Draw)
+    } else {
+        Score::Value(f64::from(score) / f64::from(EVALUATION_SCALE))
+    }
+}
+
+/// Turns the score of an alpha-beta search into a value for a SearchObserver: 1 or -1 for a
+/// forced win or loss, otherwise the evaluator's value.
+pub(crate) fn observed_value(score: i32) -> f64 {
+    if score > WIN_THRESHOLD {
+        1.0
+    } else if score < -WIN_THRESHOLD {
+        -1.0
+    } else {
+        f64::from(score) / f64::from(EVALUATION_SCALE)
+    }
+}
+
+impl AlphaBetaStrategy {
+    pub fn with_depth(max_depth: usize) -> Self {
+        Self { max_depth }
+    }
+
+    /// Searches like search(), telling the observer about every position searched. Each
+    /// iteration starts with begin_iteration(), so a TreeRecorder ends up with the tree of the
+    /// deepest one.
+    pub fn search_observed<G, E>(
+        &self,
+        state: &G,
+        evaluator: &E,
+        observer: &mut dyn SearchObserver<G>,
+    ) -> SearchInfo<G::Move>
+    where
+        G: UndoMove + Clone,
+        G::Move: Clone + PartialEq,
+        E: Evaluator<G>,
+        E::Evaluation: EvaluationValue,
+    {
+        self.run(
+            state,
+            evaluator,
+            &StopFlag::new(),
+            &mut |_| {},
+            Some(observer),
+        )
+    }
+
+    fn run<G, E>(
+        &self,
+        state: &G,
+        evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+        mut observer: Option<&mut dyn SearchObserver<G>>,
+    ) -> SearchInfo<G::Move>
+    where
+        G: UndoMove + Clone,
+        G::Move: Clone + PartialEq,
+        E: Evaluator<G>,
+        E::Evaluation: EvaluationValue,
+    {
+        let start = Instant::now();
+        let mut state = state.clone();
+        let player = state.current_player();
+        let mut info = SearchInfo {
+            principal_variation: Vec::new(),
+            score: Some(Score::Result(state.game_result())),
+            depth: 0,
+            nodes: 0,
+            elapsed: Duration::ZERO,
+        };
+        if state.game_result().is_determined() {
+            if let Some(observer) = observer {
+                let value = state.game_result().score(player);
+                observer.begin_iteration();
+                observer.enter(&state, None, None);
+                observer.leave(Some(value as f64));
+            }
+            report(&info);
+            return info;
+        }
+
+        for depth in 1..=self.max_depth.max(1) {
+            if let Some(observer) = observer.as_deref_mut() {
+                observer.begin_iteration();
+            }
+            let mut search = AlphaBetaSearch {
+                evaluator,
+                nodes: 0,
+                complete: true,
+                stop,
+                observer: observer.as_deref_mut(),
+            };
+            let mut principal_variation = Vec::new();
+            let score = search.negamax(
+                &mut state,
+                None,
+                depth,
+                0,
+                -WIN,
+                WIN,
+                &info.principal_variation,
+                &mut principal_variation,
+            );
+            info.nodes += search.nodes;
+            info.elapsed = start.elapsed();
+            if stop.is_stopped() {
+                if info.principal_variation.is_empty() {
+                    info.principal_variation = state.legal_moves().into_iter().take(1).collect();
+                    info.score = None;
                 }
+                break;
+            }
+
+            info.principal_variation = principal_variation;
+            info.depth = depth;
+            info.score = Some(alpha_beta_score(score, player, search.complete));
+            report(&info);
+            if search.complete || score.abs() > WIN_THRESHOLD {
+                break;
+            }
+        }
+        info
+    }
+}
+
+/// The state shared by every node of one iteration of AlphaBetaStrategy's search.
+struct AlphaBetaSearch<'a, 'o, G, E> {
+    evaluator: &'a E,
+    nodes: u64,
+    /// Cleared if any line was cut short by the depth limit.
+    complete: bool,
+    stop: &'a StopFlag,
+    observer: Option<&'a mut (dyn SearchObserver<G> + 'o)>,
+}
+
+impl<G, E> AlphaBetaSearch<'_, '_, G, E>
+where
+    G: UndoMove,
+    G::Move: Clone + PartialEq,
+    E: Evaluator<G>,
+    E::Evaluation: EvaluationValue,
+{
+    /// Searches depth plies below the state, reached by the given move, returning its score for
+    /// the player to move and filling in the principal variation. The previous iteration's
+    /// principal variation is tried first, which makes the cutoffs come much sooner. Once the
+    /// stop flag is set every node returns straight away, so the caller has to throw the result
+    /// away.
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        state: &mut G,
+        mov: Option<&G::Move>,
+        depth: usize,
+        ply: i32,
+        alpha: i32,
+        beta: i32,
+        previous: &[G::Move],
+        principal_variation: &mut Vec<G::Move>,
+    ) -> i32 {
+        if let Some(observer) = self.observer.as_deref_mut() {
+            let bounds = (observed_value(alpha), observed_value(beta));
+            observer.enter(state, mov, Some(bounds));
+        }
+        let score = self.search_node(
+            state,
+            depth,
+            ply,
+            alpha,
+            beta,
+            previous,
+            principal_variation,
+        );
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.leave((!self.stop.is_stopped()).then(|| observed_value(score)));
+        }
+        score
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn search_node(
+        &mut self,
+        state: &mut G,
+        depth: usize,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        previous: &[G::Move],
+        principal_variation: &mut Vec<G::Move>,
+    ) -> i32 {
+        principal_variation.clear();
+        if self.stop.is_stopped() {
+            return 0;
+        }
+        self.nodes += 1;
+        if let Some(score) = terminal_score(state, ply) {
+            return score;
+        }
+        if depth == 0 {
+            self.complete = false;
+            return leaf_score(state, self.evaluator);
+        }
+
+        let mut moves = state.legal_moves();
+        if moves.is_empty() {
+            return 0;
+        }
+        if let Some(index) = previous
+            .first()
+            .and_then(|first| moves.iter().position(|m| m == first))
+        {
+            moves[..=index].rotate_right(1);
+        }
+
+        let mut best = -WIN;
+        let mut line = Vec::new();
+        let mut moves = moves.iter().enumerate();
+        for (index, mov) in moves.by_ref() {
+            let hint = if index == 0 && previous.first() == Some(mov) {
+                &previous[1..]
+            } else {
+                &[]
+            };
+            let player = state.current_player();
+            let undo = state.do_move(mov);
+            let score = if state.current_player() == player {
+                self.negamax(
+                    state,
+                    Some(mov),
+                    depth - 1,
+                    ply + 1,
+                    alpha,
+                    beta,
+                    hint,
+                    &mut line,
+                )
+            } else {
+                -self.negamax(
+                    state,
+                    Some(mov),
+                    depth - 1,
+                    ply + 1,
+                    -beta,
+                    -alpha,
+                    hint,
+                    &mut line,
+                )
+            };
+            state.undo_move(undo);
+            if score > best {
+                best = score;
+                principal_variation.clear();
+                principal_variation.push(mov.clone());
+                principal_variation.append(&mut line);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        if let Some(observer) = self.observer.as_deref_mut() {
+            for (_, mov) in moves {
+                observer.prune(state, mov);
+            }
+        }
+        best
+    }
+}
+
+impl<G, E> Strategy<G, E> for AlphaBetaStrategy
+where
+    G: UndoMove + Clone,
+    G::Move: Clone + PartialEq,
+    E: Evaluator<G>,
+    E::Evaluation: EvaluationValue,
+{
+    type Evaluation = E::Evaluation;
+
+    /// Searches until the result is known, however deep that is.
+    fn new() -> Self {
+        Self::with_depth(usize::MAX)
+    }
+
+    fn choose_move(&self, state: &G, evaluator: &E) -> Option<G::Move> {
+        self.search(state, evaluator, &mut |_| {})
+            .principal_variation
+            .into_iter()
+            .next()
+    }
+
+    /// Reports after every iteration.
+    fn search(
+        &self,
+        state: &G,
+        evaluator: &E,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.search_until(state, evaluator, &StopFlag::new(), report)
+    }
+
+    /// When stopped, the unfinished iteration is thrown away and the last one is returned. If not
+    /// even the first iteration finished, the first legal move is returned without a score.
+    fn search_until(
+        &self,
+        state: &G,
+        evaluator: &E,
+        stop: &StopFlag,
+        report: &mut dyn FnMut(&SearchInfo<G::Move>),
+    ) -> SearchInfo<G::Move> {
+        self.run(state, evaluator, stop, report, None)
+    }
+}
+
+/// Plays games between two strategies, alternating which one moves first, and returns the score
+/// of the first. A strategy that returns no move before the game is over loses the game. The
+/// record of each game is passed to archive as soon as the game is over.
+pub fn play_match<G, E, A, B>(
+    first: &A,
+    second: &B,
+    evaluator: &E,
+    games: usize,
+    archive: &mut dyn FnMut(GameRecord<G>),
+) -> MatchScore
+where
+    G: GameState + Clone,
+    G::Move: Clone,
+    E: Evaluator<G>,
+    A: Strategy<G, E, Evaluation = E::Evaluation>,
+    B: Strategy<G, E, Evaluation = E::Evaluation>,
+{
+    let mut score = MatchScore::default();
+    for game in 0..games {
+        let first_player = if game % 2 == 0 {
+            Player::One
+        } else {
+            Player::Two
+        };
+        let record = play_game(G::new(), first_player, first, second, evaluator);
+        score.add(record.result, first_player);
+        archive(record);
+    }
+    score
+}
+
+/// Plays one game of a match from the given state, with the first strategy playing
+/// first_player, and returns its record, with what each search found for every move. A strategy
+/// that returns no move before the game is over loses the game.
+pub(crate) fn play_game<G, E, A, B>(
+    mut state: G,
+    first_player: Player,
+    first: &A,
+    second: &B,
+    evaluator: &E,
+) -> GameRecord<G>
+where
+    G: GameState + Clone,
+    G::Move: Clone,
+    E: Evaluator<G>,
+    A: Strategy<G, E, Evaluation = E::Evaluation>,
+    B: Strategy<G, E, Evaluation = E::Evaluation>,
+{
+    let mut record = GameRecord::new(state.clone());
+    record.players = [type_name::<A>(), type_name::<B>()];
+    if first_player == Player::Two {
+        record.players.swap(0, 1);
+    }
+    loop {
+        record.result = state.game_result();
+        if record.result.is_determined() {
+            return record;
+        }
+        let start = Instant::now();
+        let info = if state.current_player() == first_player {
+            first.search(&state, evaluator, &mut |_| {})
+        } else {
+            second.search(&state, evaluator, &mut |_| {})
+        };
+        match info.principal_variation.first().cloned() {
+            Some(mov) => {
+                first.notify_move(&state, &mov);
+                second.notify_move(&state, &mov);
+                state.apply_move(&mov);
+                record.push(mov, Some(start.elapsed()), Some(info));
             }
+            None => {
+                record.result = GameResult::Win(state.current_player().other_player());
+                return record;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlphaBetaStrategy, Score, Strategy, TerminalStateStrategy};
+    use crate::evaluate::{Distribution, Evaluator, TerminalStateEvaluator};
+    use crate::game::{GameResult, GameState, Player};
+    use crate::tic_tac_toe::{BoardState, Move};
+
+    /// Rates each move by its square alone: a move to square s wins with probability s / 10.
+    struct SquareEvaluator;
+
+    impl Evaluator<BoardState> for SquareEvaluator {
+        type Evaluation = Distribution;
+
+        fn new() -> Self {
+            Self
+        }
+
+        fn evaluate(&self, _state: &BoardState, mov: &Move) -> Distribution {
+            Distribution::with_probabilities(mov.square() as f32 / 10.0, 0.0)
         }
+    }
+
+    #[test]
+    fn test_search_info() {
+        // After O answers a corner with the opposite corner, X can force a fork.
+        let board: BoardState = "X__/___/__O x".parse().unwrap();
+        let mut depths = Vec::new();
+        let info = AlphaBetaStrategy::with_depth(usize::MAX).search(
+            &board,
+            &TerminalStateEvaluator,
+            &mut |info| depths.push(info.depth),
+        );
+        assert_eq!(depths, (1..=info.depth).collect::<Vec<_>>());
+        assert_eq!(
+            info.score,
+            Some(Score::Result(GameResult::Win(Player::One)))
+        );
+        let mut end = board;
+        info.principal_variation
+            .iter()
+            .for_each(|mov| end.apply_move(mov));
+        assert_eq!(end.game_result(), GameResult::Win(Player::One));
+        assert!(info.nodes > 0);
+
+        let board = BoardState::new();
+        let info =
+            AlphaBetaStrategy::with_depth(2).search(&board, &TerminalStateEvaluator, &mut |_| {});
+        assert_eq!((info.depth, info.score), (2, Some(Score::Value(0.0))));
+        let info = AlphaBetaStrategy::with_depth(usize::MAX).search(
+            &board,
+            &TerminalStateEvaluator,
+            &mut |_| {},
+        );
+        assert_eq!(info.score, Some(Score::Result(GameResult::Draw)));
+        assert_eq!(info.principal_variation.len(), 9);
+
+        let info = TerminalStateStrategy.search(&board, &TerminalStateEvaluator, &mut |_| {});
+        assert_eq!(info.score, Some(Score::Result(GameResult::Draw)));
+        assert_eq!(info.principal_variation.len(), 9);
+    }
 
-        todo!()
+    #[test]
+    fn test_depth_limit_uses_evaluator() {
+        // Below one ply O's best square is 8 unless X took it, in which case it is 7.
+        let board = BoardState::new();
+        let info = AlphaBetaStrategy::with_depth(1).search(&board, &SquareEvaluator, &mut |_| {});
+        assert_eq!(info.best_move(), Move::from_square(8).as_ref());
+        assert_eq!((info.depth, info.score), (1, Some(Score::Value(-0.7))));
     }
 }
@@ -0,0 +1,155 @@
+use crate::game::*;
+
+/// Games whose boards have symmetries, i.e. transformations that turn a position into an
+/// equivalent one. Transposition tables, solvers and training data augmentation can use these to
+/// treat all the equivalent positions as a single one.
+pub trait Symmetric: GameState {
+    /// Identifies one element of the game's symmetry group.
+    type Symmetry: Copy + 'static;
+
+    /// Returns every symmetry of the game, starting with the identity.
+    fn symmetries() -> &'static [Self::Symmetry];
+
+    /// Returns the state transformed by the given symmetry.
+    fn transform(&self, symmetry: Self::Symmetry) -> Self;
+
+    /// Returns the move transformed by the given symmetry. Playing the transformed move in the
+    /// transformed state gives the transformed version of the state the original move leads to.
+    fn transform_move(mov: &Self::Move, symmetry: Self::Symmetry) -> Self::Move;
+
+    /// Returns the symmetry that undoes the given one.
+    fn inverse(symmetry: Self::Symmetry) -> Self::Symmetry;
+
+    /// Returns the canonical form of the state, which is the same for all equivalent states,
+    /// along with the symmetry that transforms the state into it. Moves found for the canonical
+    /// form can be mapped back with the inverse of that symmetry.
+    fn canonical(&self) -> (Self, Self::Symmetry)
+    where
+        Self: Ord,
+    {
+        Self::symmetries()
+            .iter()
+            .map(|&symmetry| (self.transform(symmetry), symmetry))
+            .min_by(|(a, _), (b, _)| a.cmp(b))
+            .expect("every game has at least the identity symmetry")
+    }
+}
+
+/// The symmetries of a square grid: the four rotations and four reflections. Rotations are
+/// clockwise and all of them keep the grid in place, so they work for any n by n board. Grids that
+/// aren't square only have the half turn and the two mirrors along the sides, see RECTANGLE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BoardSymmetry {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirrors the board top to bottom.
+    FlipRows,
+    /// Mirrors the board left to right.
+    FlipColumns,
+    /// Mirrors the board in the diagonal running from the upper left corner.
+    Transpose,
+    /// Mirrors the board in the diagonal running from the upper right corner.
+    AntiTranspose,
+}
+
+impl BoardSymmetry {
+    /// Every symmetry of a square grid, starting with the identity.
+    pub const ALL: [BoardSymmetry; 8] = [
+        BoardSymmetry::Identity,
+        BoardSymmetry::Rotate90,
+        BoardSymmetry::Rotate180,
+        BoardSymmetry::Rotate270,
+        BoardSymmetry::FlipRows,
+        BoardSymmetry::FlipColumns,
+        BoardSymmetry::Transpose,
+        BoardSymmetry::AntiTranspose,
+    ];
+
+    /// The symmetries of a grid that isn't square, starting with the identity.
+    pub const RECTANGLE: [BoardSymmetry; 4] = [
+        BoardSymmetry::Identity,
+        BoardSymmetry::Rotate180,
+        BoardSymmetry::FlipRows,
+        BoardSymmetry::FlipColumns,
+    ];
+
+    /// Returns where the square at (row, column) of a size by size board ends up.
+    pub fn map(&self, row: usize, column: usize, size: usize) -> (usize, usize) {
+        self.map_rectangle(row, column, size, size)
+    }
+
+    /// Returns where the square at (row, column) of a board with the given number of rows and
+    /// columns ends up. Panics for the quarter turns and the diagonal mirrors unless the board is
+    /// square.
+    pub fn map_rectangle(
+        &self,
+        row: usize,
+        column: usize,
+        rows: usize,
+        columns: usize,
+    ) -> (usize, usize) {
+        let (last_row, last_column) = (rows - 1, columns - 1);
+        if !Self::RECTANGLE.contains(self) {
+            assert_eq!(rows, columns, "{:?} only maps square boards", self);
+        }
+        match *self {
+            BoardSymmetry::Identity => (row, column),
+            BoardSymmetry::Rotate90 => (column, last_row - row),
+            BoardSymmetry::Rotate180 => (last_row - row, last_column - column),
+            BoardSymmetry::Rotate270 => (last_column - column, row),
+            BoardSymmetry::FlipRows => (last_row - row, column),
+            BoardSymmetry::FlipColumns => (row, last_column - column),
+            BoardSymmetry::Transpose => (column, row),
+            BoardSymmetry::AntiTranspose => (last_column - column, last_row - row),
+        }
+    }
+
+    /// Returns the symmetry that undoes this one. Only the quarter turns aren't their own inverse.
+    pub fn inverse(&self) -> BoardSymmetry {
+        match *self {
+            BoardSymmetry::Rotate90 => BoardSymmetry::Rotate270,
+            BoardSymmetry::Rotate270 => BoardSymmetry::Rotate90,
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoardSymmetry, Symmetric};
+    use crate::game::GameState;
+    use crate::tic_tac_toe::BoardState;
+
+    #[test]
+    fn test_symmetry() {
+        let board: BoardState = "XO_/_X_/__O x".parse().unwrap();
+        let rotated = board.transform(BoardSymmetry::Rotate90);
+        assert_eq!(rotated.notation(), "__X/_XO/O__ x");
+        for &symmetry in BoardState::symmetries() {
+            let transformed = board.transform(symmetry);
+            assert_eq!(transformed.transform(symmetry.inverse()), board);
+            assert_eq!(transformed.canonical().0, board.canonical().0);
+            for mov in board.legal_moves() {
+                assert_eq!(
+                    board.next_state(&mov).transform(symmetry),
+                    transformed.next_state(&BoardState::transform_move(&mov, symmetry))
+                );
+            }
+        }
+
+        let (canonical, symmetry) = board.canonical();
+        assert_eq!(board.transform(symmetry), canonical);
+
+        // The 5478 reachable positions collapse into 765 essentially different ones.
+        let mut stack = vec![BoardState::new()];
+        let mut seen = std::collections::HashSet::new();
+        while let Some(board) = stack.pop() {
+            if seen.insert(board.canonical().0) && !board.game_result().is_determined() {
+                stack.extend(board.reachable_states());
+            }
+        }
+        assert_eq!(seen.len(), 765);
+    }
+}
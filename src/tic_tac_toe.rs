@@ -1,8 +1,9 @@
-use crate::game::*;
-use std::fmt::Display;
+use crate::{evaluate::TerminalStateEvaluator, game::*, retrograde::*, symmetry::*};
+use std::{collections::HashMap, fmt::Display, str::FromStr, sync::OnceLock};
 
 /// Used to represent the pieces on the board.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Piece {
     #[default]
     X,
@@ -22,14 +23,27 @@ pub enum Piece {
 type Position = u16;
 
 /// Represents a move. A single 1 bit denotes which position to move to. Note that only the 9
-/// rightmost logical bits may be 1, since we have only 9 squares.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// rightmost logical bits may be 1, since we have only 9 squares. Moves are written as
+/// coordinates like "b2" everywhere: by Display and FromStr, by MoveNotation and, with serde, in
+/// serialised data, so a move that is off the board can't be read back in. MoveNotation also
+/// reads square indices like "4".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", try_from = "String"))]
 pub struct Move(Position);
 
+/// Iterator over the legal moves of a board. It holds the mask of empty squares and yields its
+/// set bits from the lowest up, one move per square, so generating moves never allocates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Moves(Position);
+
 /// The state of the board. player1 and player2 encode the position for Player 1 and Player 2,
 /// respectively. to_move encodes which player's turn it is. player1_piece encodes whether player
-/// 1 is X's or O's.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// 1 is X's or O's. With serde, boards are written in the notation described on notation(), which
+/// is validated when read back in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", try_from = "String"))]
 pub struct BoardState {
     player1: Position,
     player2: Position,
@@ -58,21 +72,6 @@ const WINNING_POSITIONS: [Position; 8] = [
     0b0000_0000_0101_0100,
 ];
 
-/// Array of all potential moves that can be made. These are all u16's with a single 1 bit i.e.
-/// Move(2^k) represents moving to position k on the board. This could also be an iterator but I
-/// think (hope?) this is faster.
-const ALL_MOVES: [Move; 9] = [
-    Move(1),
-    Move(2),
-    Move(4),
-    Move(8),
-    Move(16),
-    Move(32),
-    Move(64),
-    Move(128),
-    Move(256),
-];
-
 /// If all of these positions are occupied and there is no winner yet then the game is a draw.
 /// If A and B are the positions of players A and B then the game is a draw only if:
 /// (A | B) & DRAW == DRAW
@@ -99,29 +98,241 @@ impl Display for Piece {
     }
 }
 
+impl Display for Move {
+    /// Writes the move as coordinates, see FromStr.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (row, column) = (self.square() / 3, self.square() % 3);
+        let column = (b'a' + column as u8) as char;
+        let row = (b'3' - row as u8) as char;
+        write!(f, "{}{}", column, row)
+    }
+}
+
+impl FromStr for Move {
+    type Err = ParseMoveError;
+
+    /// Parses coordinates like "b2": the column a to c from left to right, then the row 1 to 3
+    /// from bottom to top, as on a chess board.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseMoveError::Invalid(s.to_string());
+        let mut chars = s.chars();
+        let column = match chars.next() {
+            Some(c @ 'a'..='c') => c as u16 - 'a' as u16,
+            _ => return Err(invalid()),
+        };
+        let row = match chars.next() {
+            Some(c @ '1'..='3') => '3' as u16 - c as u16,
+            _ => return Err(invalid()),
+        };
+        if chars.next().is_some() {
+            return Err(invalid());
+        }
+        Move::from_square(row * 3 + column).ok_or_else(invalid)
+    }
+}
+
+impl From<Move> for String {
+    fn from(mov: Move) -> String {
+        mov.to_string()
+    }
+}
+
+impl TryFrom<String> for Move {
+    type Error = ParseMoveError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Move {
+    /// Returns the move to the given square, numbered as in Position. Returns None if the square
+    /// is off the board.
+    pub fn from_square(square: u16) -> Option<Move> {
+        (square < 9).then(|| Move(1 << square))
+    }
+
+    /// Returns the square this move goes to, numbered as in Position.
+    pub fn square(&self) -> u16 {
+        self.0.trailing_zeros() as u16
+    }
+}
+
+impl Iterator for Moves {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        if self.0 == 0 {
+            return None;
+        }
+        let lowest = self.0 & self.0.wrapping_neg();
+        self.0 ^= lowest;
+        Some(Move(lowest))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for Moves {}
+
 impl Display for BoardState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for i in 0u16..3 {
-            let _ = self.display_row(f, i)?;
+        for row in 0u16..3 {
+            self.display_row(f, row)?;
         }
-        println!();
-        Ok(())
+        writeln!(f)
     }
 }
 
+impl FromStr for BoardState {
+    type Err = ParsePositionError;
+
+    /// Parses a position in the notation described on BoardState::notation().
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rows, side) = s
+            .trim()
+            .split_once(' ')
+            .ok_or(ParsePositionError::MissingSideToMove)?;
+        let side = match side.trim() {
+            "x" | "X" => Piece::X,
+            "o" | "O" => Piece::O,
+            other => return Err(ParsePositionError::InvalidSideToMove(other.to_string())),
+        };
+
+        let rows: Vec<&str> = rows.split('/').collect();
+        if rows.len() != 3 {
+            return Err(ParsePositionError::WrongRowCount(rows.len()));
+        }
+        let (mut x, mut o): (Position, Position) = (0, 0);
+        for (row_index, row) in rows.into_iter().enumerate() {
+            if row.chars().count() != 3 {
+                return Err(ParsePositionError::WrongRowLength { row: row_index });
+            }
+            for (column, c) in row.chars().enumerate() {
+                let square = 1 << (row_index * 3 + column);
+                match c {
+                    'X' | 'x' => x |= square,
+                    'O' | 'o' => o |= square,
+                    '_' => {}
+                    other => return Err(ParsePositionError::InvalidPiece(other)),
+                }
+            }
+        }
+
+        // Player One always moves first, so whoever has the extra piece is Player One. With equal
+        // counts, it is Player One's turn again.
+        let (x_count, o_count) = (x.count_ones(), o.count_ones());
+        let (player1_piece, to_move) = match (x_count, o_count) {
+            (x_count, o_count) if x_count == o_count => (side, Player::One),
+            (x_count, o_count) if x_count == o_count + 1 => (Piece::X, Player::Two),
+            (x_count, o_count) if o_count == x_count + 1 => (Piece::O, Player::Two),
+            (x, o) => return Err(ParsePositionError::WrongPieceCounts { x, o }),
+        };
+        if to_move == Player::Two && side == player1_piece {
+            return Err(ParsePositionError::WrongSideToMove);
+        }
+        let (player1, player2) = if player1_piece == Piece::X {
+            (x, o)
+        } else {
+            (o, x)
+        };
+        let board = BoardState {
+            player1,
+            player2,
+            to_move,
+            player1_piece,
+        };
+
+        match (board.is_winner(&Player::One), board.is_winner(&Player::Two)) {
+            (true, true) => Err(ParsePositionError::BothPlayersWin),
+            (true, false) | (false, true) if board.current_player_is_winner() => {
+                Err(ParsePositionError::MovedAfterWin)
+            }
+            _ => Ok(board),
+        }
+    }
+}
+
+impl From<BoardState> for String {
+    fn from(board: BoardState) -> String {
+        board.notation()
+    }
+}
+
+impl TryFrom<String> for BoardState {
+    type Error = ParsePositionError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Errors that can occur when parsing a position with BoardState::from_str().
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsePositionError {
+    /// The rows are not followed by a space and the side to move.
+    MissingSideToMove,
+    /// The side to move is not one of x or o.
+    InvalidSideToMove(String),
+    /// There are not exactly 3 rows separated by '/'. Holds the number of rows found.
+    WrongRowCount(usize),
+    /// The given (0-based) row does not have exactly 3 squares.
+    WrongRowLength { row: usize },
+    /// A square holds something other than X, O or _.
+    InvalidPiece(char),
+    /// The piece counts can't be reached by alternating moves.
+    WrongPieceCounts { x: u32, o: u32 },
+    /// The piece counts say it is the other side's turn.
+    WrongSideToMove,
+    /// Both players have three in a row.
+    BothPlayersWin,
+    /// A player has three in a row but the game carried on afterwards.
+    MovedAfterWin,
+}
+
+impl Display for ParsePositionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParsePositionError::MissingSideToMove => write!(f, "missing side to move"),
+            ParsePositionError::InvalidSideToMove(side) => {
+                write!(f, "invalid side to move '{}', expected x or o", side)
+            }
+            ParsePositionError::WrongRowCount(count) => {
+                write!(f, "expected 3 rows but found {}", count)
+            }
+            ParsePositionError::WrongRowLength { row } => {
+                write!(f, "row {} does not have 3 squares", row)
+            }
+            ParsePositionError::InvalidPiece(piece) => write!(f, "invalid piece '{}'", piece),
+            ParsePositionError::WrongPieceCounts { x, o } => {
+                write!(f, "impossible piece counts: {} X and {} O", x, o)
+            }
+            ParsePositionError::WrongSideToMove => {
+                write!(f, "side to move does not match the piece counts")
+            }
+            ParsePositionError::BothPlayersWin => write!(f, "both players have three in a row"),
+            ParsePositionError::MovedAfterWin => {
+                write!(f, "a move was made after the game was won")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParsePositionError {}
+
 impl BoardState {
     fn display_row(&self, f: &mut std::fmt::Formatter<'_>, row: Position) -> std::fmt::Result {
         let row_offset = row * 3;
-        let mut buffer = [Piece::Empty; 3];
-        buffer.iter_mut().enumerate().for_each(|(i, piece)| {
-            let shift = row_offset + i as u16;
-            if (self.player1 >> shift) & 1 == 1 {
-                *piece = self.player1_piece;
-            } else if (self.player2 >> shift) & 1 == 1 {
-                *piece = self.player1_piece.other();
-            }
-        });
-        write!(f, "{}|{}|{}\n", buffer[0], buffer[1], buffer[2])
+        writeln!(
+            f,
+            "{}|{}|{}",
+            self.piece_at(row_offset),
+            self.piece_at(row_offset + 1),
+            self.piece_at(row_offset + 2)
+        )
     }
 
     pub fn new() -> BoardState {
@@ -135,6 +346,38 @@ impl BoardState {
         }
     }
 
+    /// Returns the piece on the given square, numbered as in Position.
+    pub fn piece_at(&self, square: Position) -> Piece {
+        if (self.player1 >> square) & 1 == 1 {
+            self.player1_piece
+        } else if (self.player2 >> square) & 1 == 1 {
+            self.player1_piece.other()
+        } else {
+            Piece::Empty
+        }
+    }
+
+    /// Returns the position in a compact, FEN-like notation: the rows from top to bottom
+    /// separated by '/', using X, O and _ for the squares, then a space and the piece whose turn
+    /// it is in lowercase e.g. "XO_/_X_/__O o". It can be read back with BoardState::from_str().
+    pub fn notation(&self) -> String {
+        let mut notation = String::with_capacity(13);
+        for square in 0..9 {
+            if square > 0 && square % 3 == 0 {
+                notation.push('/');
+            }
+            notation.push_str(&self.piece_at(square).to_string());
+        }
+        let side = if self.to_move == Player::One {
+            self.player1_piece
+        } else {
+            self.player1_piece.other()
+        };
+        notation.push(' ');
+        notation.push_str(&side.to_string().to_lowercase());
+        notation
+    }
+
     /// Returns true if the given move is legal i.e. the desired position is unoccupied
     pub fn move_is_legal(&self, move_candidate: &Move) -> bool {
         let filled_positions = self.player1 | self.player2;
@@ -143,21 +386,37 @@ impl BoardState {
 
     /// Gets all the legal moves given the current board state.
     pub fn legal_moves(&self) -> Vec<Move> {
-        ALL_MOVES
-            .into_iter()
-            .filter(|candidate| self.move_is_legal(candidate))
-            .collect()
+        self.moves().collect()
+    }
+
+    /// Returns an iterator over the legal moves, i.e. the empty squares, without allocating.
+    pub fn moves(&self) -> Moves {
+        Moves(!(self.player1 | self.player2) & DRAW)
     }
 
     pub fn next_state(&self, mov: &Move) -> BoardState {
-        let mut board_clone = self.clone();
+        let mut board_clone = *self;
         board_clone.apply_move(mov);
         board_clone
     }
 
+    /// Returns Ok if the move is a single square on the board that is still empty, in a game that
+    /// hasn't ended yet.
+    pub fn validate_move(&self, mov: &Move) -> Result<(), MoveError> {
+        if self.game_result().is_determined() {
+            Err(MoveError::GameOver)
+        } else if mov.0.count_ones() != 1 || mov.0 & !DRAW != 0 {
+            Err(MoveError::OutOfRange)
+        } else if !self.move_is_legal(mov) {
+            Err(MoveError::Occupied)
+        } else {
+            Ok(())
+        }
+    }
+
     /// This function does not check whether a move is valid or not. The safer option is to use
-    /// try_move(), which will check to see if a move is legal before doing it. This should only
-    /// be used for performance reasons or if you have already checked that the move is legal.
+    /// try_apply_move(), which will check to see if a move is legal before doing it. This should
+    /// only be used for performance reasons or if you have already checked that the move is legal.
     pub fn apply_move(&mut self, mov: &Move) {
         *self.current_player_position_mut() |= mov.0;
         self.to_move.flip_player()
@@ -266,6 +525,10 @@ impl GameState for BoardState {
         self.legal_moves()
     }
 
+    fn legal_moves_into(&self, moves: &mut Vec<Self::Move>) {
+        moves.extend(self.moves())
+    }
+
     fn next_state(&self, mov: &Self::Move) -> Self {
         self.next_state(mov)
     }
@@ -274,6 +537,10 @@ impl GameState for BoardState {
         self.apply_move(mov)
     }
 
+    fn validate_move(&self, mov: &Self::Move) -> Result<(), MoveError> {
+        self.validate_move(mov)
+    }
+
     fn current_player(&self) -> Player {
         self.current_player()
     }
@@ -289,21 +556,500 @@ impl GameState for BoardState {
     }
 }
 
+impl UndoMove for BoardState {
+    /// Undoing only needs to know which square was filled.
+    type Undo = Move;
+
+    fn do_move(&mut self, mov: &Move) -> Move {
+        self.apply_move(mov);
+        *mov
+    }
+
+    fn undo_move(&mut self, undo: Move) {
+        self.to_move.flip_player();
+        *self.current_player_position_mut() &= !undo.0;
+    }
+}
+
+impl Symmetric for BoardState {
+    type Symmetry = BoardSymmetry;
+
+    fn symmetries() -> &'static [BoardSymmetry] {
+        &BoardSymmetry::ALL
+    }
+
+    fn transform(&self, symmetry: BoardSymmetry) -> Self {
+        BoardState {
+            player1: transform_position(self.player1, symmetry),
+            player2: transform_position(self.player2, symmetry),
+            ..*self
+        }
+    }
+
+    fn transform_move(mov: &Move, symmetry: BoardSymmetry) -> Move {
+        Move(transform_position(mov.0, symmetry))
+    }
+
+    fn inverse(symmetry: BoardSymmetry) -> BoardSymmetry {
+        symmetry.inverse()
+    }
+}
+
+/// Positions are numbered by reading the squares as the digits of a base 3 number, square 0 being
+/// the least significant, with 0 for empty, 1 for Player One and 2 for Player Two. Positions where
+/// Player One plays O come after all those where they play X.
+impl Enumerable for BoardState {
+    fn index_count() -> usize {
+        2 * 3usize.pow(9)
+    }
+
+    fn index(&self) -> usize {
+        let squares = (0..9).rev().fold(0, |index, square| {
+            let digit = if (self.player1 >> square) & 1 == 1 {
+                1
+            } else if (self.player2 >> square) & 1 == 1 {
+                2
+            } else {
+                0
+            };
+            index * 3 + digit
+        });
+        let piece_offset = if self.player1_piece == Piece::O {
+            3usize.pow(9)
+        } else {
+            0
+        };
+        piece_offset + squares
+    }
+
+    /// Returns None for indices outside the board's range and for positions that can't come up
+    /// in a game, i.e. ones with impossible piece counts or where play continued after a win.
+    fn from_index(index: usize) -> Option<Self> {
+        if index >= Self::index_count() {
+            return None;
+        }
+        let player1_piece = if index >= 3usize.pow(9) {
+            Piece::O
+        } else {
+            Piece::X
+        };
+        let mut squares = index % 3usize.pow(9);
+        let (mut player1, mut player2): (Position, Position) = (0, 0);
+        for square in 0..9 {
+            match squares % 3 {
+                1 => player1 |= 1 << square,
+                2 => player2 |= 1 << square,
+                _ => {}
+            }
+            squares /= 3;
+        }
+
+        let to_move = match (player1.count_ones(), player2.count_ones()) {
+            (ones, twos) if ones == twos => Player::One,
+            (ones, twos) if ones == twos + 1 => Player::Two,
+            _ => return None,
+        };
+        let board = BoardState {
+            player1,
+            player2,
+            to_move,
+            player1_piece,
+        };
+        // Only the player who moved last can have won.
+        (!board.current_player_is_winner()).then_some(board)
+    }
+}
+
+impl Retractable for BoardState {
+    /// Takes back each of the last player's pieces in turn.
+    fn predecessors(&self) -> Vec<Self> {
+        let last_position = *self.last_player_position();
+        (0..9)
+            .filter(|square| (last_position >> square) & 1 == 1)
+            .map(|square| {
+                let mut previous = *self;
+                previous.undo_move(Move(1 << square));
+                previous
+            })
+            .collect()
+    }
+}
+
+/// Moves every occupied square of the position to where the symmetry takes it.
+fn transform_position(position: Position, symmetry: BoardSymmetry) -> Position {
+    (0..9)
+        .filter(|square| (position >> square) & 1 == 1)
+        .map(|square| {
+            let (row, column) = symmetry.map(square / 3, square % 3, 3);
+            1 << (row * 3 + column)
+        })
+        .fold(0, |transformed, square| transformed | square)
+}
+
+impl MoveNotation for BoardState {
+    /// Accepts either coordinates like "b2", as Move's FromStr does, or a square index like "4".
+    fn parse_move(&self, s: &str) -> Result<Move, ParseMoveError> {
+        let index = match s.as_bytes() {
+            [digit @ b'0'..=b'8'] => Move::from_square(u16::from(digit - b'0')),
+            _ => None,
+        };
+        let mov = match index {
+            Some(mov) => mov,
+            None => s.parse()?,
+        };
+        if self.move_is_legal(&mov) {
+            Ok(mov)
+        } else {
+            Err(ParseMoveError::Illegal(s.to_string()))
+        }
+    }
+
+    /// Writes the move as coordinates like "b2", as Move's Display does.
+    fn format_move(&self, mov: &Move) -> String {
+        mov.to_string()
+    }
+}
+
+impl PositionNotation for BoardState {
+    type Err = ParsePositionError;
+
+    /// The notation described on BoardState::notation().
+    fn notation(&self) -> String {
+        BoardState::notation(self)
+    }
+
+    fn from_notation(s: &str) -> Result<Self, ParsePositionError> {
+        s.parse()
+    }
+}
+
+/// The result under perfect play of every position reachable in a game of tic-tac-toe, for either
+/// choice of Player One's piece. This is ground truth for testing strategies.
+#[derive(Debug, Clone)]
+pub struct PerfectPlayTable {
+    results: HashMap<BoardState, GameResult>,
+}
+
+impl PerfectPlayTable {
+    /// Builds the table by retrograde analysis: every reachable position is enumerated and
+    /// grouped by the number of pieces on the board, then the positions are valued from full
+    /// boards back to empty ones. Terminal positions take their own result, and every other
+    /// position the best result among the positions one move later, which have all been valued
+    /// by then.
+    pub fn new() -> Self {
+        let mut layers: Vec<Vec<BoardState>> = vec![Vec::new(); 10];
+        let mut seen = std::collections::HashSet::new();
+        let mut stack = vec![BoardState::new(), BoardState::pick_piece_new(Piece::O)];
+        while let Some(board) = stack.pop() {
+            if !seen.insert(board) {
+                continue;
+            }
+            layers[(board.player1 | board.player2).count_ones() as usize].push(board);
+            if !board.game_result().is_determined() {
+                stack.extend(board.reachable_states());
+            }
+        }
+
+        let mut results = HashMap::with_capacity(seen.len());
+        for board in layers.into_iter().rev().flatten() {
+            let result = board.game_result();
+            let result = if result.is_determined() {
+                result
+            } else {
+                let player = board.current_player();
+                board
+                    .reachable_states()
+                    .iter()
+                    .map(|next| results[next])
+                    .max_by_key(|result: &GameResult| result.score(player))
+                    .expect("an undetermined position has a move")
+            };
+            results.insert(board, result);
+        }
+        Self { results }
+    }
+
+    /// Returns the result of the game from the given position under perfect play, or None if the
+    /// position can't be reached in a game.
+    pub fn result(&self, board: &BoardState) -> Option<GameResult> {
+        self.results.get(board).copied()
+    }
+
+    /// Returns every move that keeps the perfect play result of the position, i.e. every move a
+    /// perfect player might make. Empty if the game is over or the position can't be reached.
+    pub fn perfect_moves(&self, board: &BoardState) -> Vec<Move> {
+        let Some(result) = self.result(board) else {
+            return Vec::new();
+        };
+        if board.game_result().is_determined() {
+            return Vec::new();
+        }
+        board
+            .moves()
+            .filter(|mov| self.result(&board.next_state(mov)) == Some(result))
+            .collect()
+    }
+
+    /// Returns every position in the table along with its result, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&BoardState, &GameResult)> {
+        self.results.iter()
+    }
+
+    /// Returns the number of positions in the table.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    /// Returns true if the table has no positions, which never happens for a built table.
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+impl Default for PerfectPlayTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The table behind perfect_result() and perfect_moves(), built on first use.
+fn perfect_play_table() -> &'static PerfectPlayTable {
+    static TABLE: OnceLock<PerfectPlayTable> = OnceLock::new();
+    TABLE.get_or_init(PerfectPlayTable::new)
+}
+
+/// Returns the result of the game from the given position if both players play perfectly.
+/// Positions that can't be reached in a game are solved by search instead.
+pub fn perfect_result(board: &BoardState) -> GameResult {
+    perfect_play_table()
+        .result(board)
+        .unwrap_or_else(|| TerminalStateEvaluator::solve(&mut board.clone()))
+}
+
+/// Returns every move a perfect player might make from the given position.
+pub fn perfect_moves(board: &BoardState) -> Vec<Move> {
+    perfect_play_table().perfect_moves(board)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::tic_tac_toe::Move;
+    use crate::evaluate::{Evaluator, TerminalStateEvaluator};
+    use crate::game::{
+        GameResult, GameState, MoveError, MoveNotation, ParseMoveError, Player, UndoMove,
+    };
+    use crate::strategy::{Strategy, TerminalStateStrategy};
+    use crate::tic_tac_toe::{Move, Piece};
 
-    use super::BoardState;
+    use super::{perfect_moves, perfect_result, BoardState, ParsePositionError};
 
     #[test]
     fn test_move() {
         let mut board1 = BoardState::new();
         let mut board2 = BoardState::new();
-        board1.apply_move(&Move(0));
         board1.apply_move(&Move(1));
+        board1.apply_move(&Move(16));
+        board1.apply_move(&Move(256));
+        board2.apply_move(&Move(256));
+        board2.apply_move(&Move(16));
         board2.apply_move(&Move(1));
-        board2.apply_move(&Move(0));
 
         assert_eq!(board1, board2)
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use crate::record::GameRecord;
+        use crate::strategy::{Score, SearchInfo};
+
+        let mut record = GameRecord::new(BoardState::new().next_state(&Move(16)));
+        let info = SearchInfo {
+            principal_variation: vec![Move(1), Move(4)],
+            score: Some(Score::Value(-0.5)),
+            depth: 2,
+            nodes: 9,
+            elapsed: std::time::Duration::from_millis(3),
+        };
+        record.push(Move(1), None, Some(info));
+        record.result = record.final_state().game_result();
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains("\"initial\":\"___/_X_/___ o\""));
+        assert!(json.contains("\"mov\":\"a3\""));
+        assert!(json.contains("\"principal_variation\":[\"a3\",\"c3\"]"));
+        let reloaded: GameRecord<BoardState> = serde_json::from_str(&json).unwrap();
+        assert_eq!(reloaded.initial, record.initial);
+        assert_eq!(reloaded.moves, record.moves);
+        assert_eq!(reloaded.final_state(), record.final_state());
+
+        assert_eq!(serde_json::to_string(&Move(16)).unwrap(), "\"b2\"");
+        assert!(serde_json::from_str::<Move>("\"d4\"").is_err());
+        assert!(serde_json::from_str::<Move>("16").is_err());
+        assert!(serde_json::from_str::<BoardState>("\"XXX/XX_/___ o\"").is_err());
+    }
+
+    #[test]
+    fn test_notation_round_trip() {
+        let mut stack = vec![BoardState::new(), BoardState::pick_piece_new(Piece::O)];
+        let mut seen = std::collections::HashSet::new();
+        while let Some(board) = stack.pop() {
+            if !seen.insert(board) {
+                continue;
+            }
+            let notation = board.notation();
+            assert_eq!(notation.parse::<BoardState>(), Ok(board), "{}", notation);
+            if !board.game_result().is_determined() {
+                stack.extend(board.reachable_states());
+            }
+        }
+        // 5478 reachable positions for each choice of Player One's piece.
+        assert_eq!(seen.len(), 2 * 5478);
+
+        let board: BoardState = "XO_/_X_/__O x".parse().unwrap();
+        assert_eq!(board.notation(), "XO_/_X_/__O x");
+        assert_eq!(board.piece_at(4), Piece::X);
+    }
+
+    #[test]
+    fn test_notation_errors() {
+        let parse = |s: &str| s.parse::<BoardState>().unwrap_err();
+        assert_eq!(parse("XO_/_X_/__O"), ParsePositionError::MissingSideToMove);
+        assert_eq!(
+            parse("XO_/_X_/__O z"),
+            ParsePositionError::InvalidSideToMove("z".to_string())
+        );
+        assert_eq!(parse("XO_/_X_ x"), ParsePositionError::WrongRowCount(2));
+        assert_eq!(
+            parse("XO_/_X/__O x"),
+            ParsePositionError::WrongRowLength { row: 1 }
+        );
+        assert_eq!(
+            parse("XO_/_Y_/__O x"),
+            ParsePositionError::InvalidPiece('Y')
+        );
+        assert_eq!(
+            parse("XX_/_X_/__O o"),
+            ParsePositionError::WrongPieceCounts { x: 3, o: 1 }
+        );
+        assert_eq!(parse("XO_/_X_/___ x"), ParsePositionError::WrongSideToMove);
+        assert_eq!(parse("XXX/OOO/___ x"), ParsePositionError::BothPlayersWin);
+        assert_eq!(parse("XXX/OO_/O__ x"), ParsePositionError::MovedAfterWin);
+    }
+
+    #[test]
+    fn test_move_notation() {
+        let board: BoardState = "XO_/_X_/__O x".parse().unwrap();
+        assert_eq!(board.parse_move("c3"), Ok(Move(4)));
+        assert_eq!(Move(4).to_string(), "c3");
+        assert_eq!("c3".parse::<Move>(), Ok(Move(4)));
+        assert_eq!(board.parse_move("a1"), Ok(Move(64)));
+        assert_eq!(
+            board.parse_move("b2"),
+            Err(ParseMoveError::Illegal("b2".to_string()))
+        );
+        assert_eq!(
+            board.parse_move("d1"),
+            Err(ParseMoveError::Invalid("d1".to_string()))
+        );
+        // Square indices are read too, but moves are always written as coordinates.
+        assert_eq!(board.parse_move("2"), board.parse_move("c3"));
+        assert_eq!(board.format_move(&board.parse_move("2").unwrap()), "c3");
+        assert_eq!(
+            board.parse_move("9"),
+            Err(ParseMoveError::Invalid("9".to_string()))
+        );
+        assert_eq!(
+            "2".parse::<Move>(),
+            Err(ParseMoveError::Invalid("2".to_string()))
+        );
+        for mov in board.legal_moves() {
+            assert_eq!(board.parse_move(&board.format_move(&mov)), Ok(mov));
+        }
+    }
+
+    #[test]
+    fn test_try_apply_move() {
+        let mut board: BoardState = "XO_/_X_/__O x".parse().unwrap();
+        assert_eq!(board.try_next_state(&Move(0)), Err(MoveError::OutOfRange));
+        assert_eq!(board.try_next_state(&Move(3)), Err(MoveError::OutOfRange));
+        assert_eq!(board.try_next_state(&Move(512)), Err(MoveError::OutOfRange));
+        assert_eq!(board.try_apply_move(&Move(16)), Err(MoveError::Occupied));
+        assert_eq!(board, "XO_/_X_/__O x".parse().unwrap());
+
+        assert_eq!(board.try_apply_move(&Move(64)), Ok(()));
+        assert_eq!(board.try_apply_move(&Move(4)), Ok(()));
+        assert_eq!(board.try_apply_move(&Move(8)), Ok(()));
+        assert_eq!(board.game_result(), GameResult::Win(Player::One));
+        assert_eq!(board.try_apply_move(&Move(32)), Err(MoveError::GameOver));
+    }
+
+    #[test]
+    fn test_undo_move() {
+        let start: BoardState = "XO_/_X_/__O x".parse().unwrap();
+        let mut board = start;
+        for mov in start.legal_moves() {
+            let undo = board.do_move(&mov);
+            assert_eq!(board, start.next_state(&mov));
+            let second = board.legal_moves()[0];
+            let second_undo = board.do_move(&second);
+            board.undo_move(second_undo);
+            board.undo_move(undo);
+            assert_eq!(board, start);
+        }
+    }
+
+    #[test]
+    fn test_terminal_state_strategy() {
+        assert_eq!(
+            TerminalStateEvaluator::solve(&mut BoardState::new()),
+            GameResult::Draw
+        );
+
+        // X wins immediately at c1, while a2 also wins a move later by threatening both c1 and c2.
+        let board: BoardState = "XO_/_X_/O__ x".parse().unwrap();
+        assert_eq!(
+            TerminalStateEvaluator.evaluate(&board, &Move(8)),
+            GameResult::Win(Player::One)
+        );
+        let mov = TerminalStateStrategy.choose_move(&board, &TerminalStateEvaluator);
+        assert_eq!(mov, Some(Move(256)));
+
+        // O must block the diagonal.
+        let board: BoardState = "X__/_X_/O__ o".parse().unwrap();
+        let mov = TerminalStateStrategy.choose_move(&board, &TerminalStateEvaluator);
+        assert_eq!(mov, Some(Move(256)));
+    }
+
+    #[test]
+    fn test_moves_iterator() {
+        let board: BoardState = "XO_/_X_/__O x".parse().unwrap();
+        let moves: Vec<Move> = board.moves().collect();
+        assert_eq!(moves, vec![Move(4), Move(8), Move(32), Move(64), Move(128)]);
+        assert_eq!(board.moves().len(), 5);
+
+        let mut buffer = vec![Move(1)];
+        GameState::legal_moves_into(&board, &mut buffer);
+        assert_eq!(buffer[1..], moves[..]);
+    }
+
+    #[test]
+    fn test_perfect_play() {
+        assert_eq!(perfect_result(&BoardState::new()), GameResult::Draw);
+        assert_eq!(perfect_moves(&BoardState::new()).len(), 9);
+        let board: BoardState = "XO_/___/___ x".parse().unwrap();
+        assert_eq!(perfect_result(&board), GameResult::Win(Player::One));
+        let board: BoardState = "X__/___/___ o".parse().unwrap();
+        assert_eq!(perfect_moves(&board), vec![Move(16)]);
+
+        // The table agrees with the exhaustive search, and TerminalStateStrategy only ever plays
+        // perfect moves.
+        let table = super::PerfectPlayTable::new();
+        assert_eq!(table.len(), 2 * 5478);
+        for (board, result) in &table.results {
+            assert_eq!(TerminalStateEvaluator::solve(&mut board.clone()), *result);
+            if let Some(mov) = TerminalStateStrategy.choose_move(board, &TerminalStateEvaluator) {
+                assert!(table.perfect_moves(board).contains(&mov), "{}", board);
+            }
+        }
+    }
 }
@@ -0,0 +1,474 @@
+//! A chess engine speaking the Universal Chess Interface, searching with LazySmpStrategy and the
+//! MaterialEvaluator. The uci binary connects it to standard input and output.
+//!
+//! It understands uci, isready, setoption for Threads, ucinewgame, position, go, ponderhit, stop
+//! and quit. go takes depth, movetime, wtime, btime, winc, binc, infinite and ponder; with
+//! neither a depth nor any time given it searches to DEFAULT_DEPTH. Unknown commands are ignored,
+//! as the protocol asks.
+//!
+//! While searching it sends an info line with the depth, score, nodes, nodes per second, time and
+//! principal variation after every iteration. Scores are in centipawns, worked back from the
+//! MaterialEvaluator's value, or mate in however many moves the principal variation takes.
+use crate::{chess::*, game::*, lazy_smp::LazySmpStrategy, search::*, strategy::*};
+use ::chess::{Board, ChessMove, Color};
+use std::{
+    io::{self, Write},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How deep go searches when it is given no limits at all.
+pub const DEFAULT_DEPTH: usize = 5;
+
+/// Plays chess over UCI, writing its replies to the given writer. Searches run on their own
+/// threads: handle() takes each command as it arrives, and poll() has to be called regularly to
+/// stop searches that are out of time and to send their best moves.
+pub struct UciEngine<W> {
+    out: W,
+    board: Board,
+    /// The position before the last move of the position command and that move, which go ponder
+    /// takes to be the reply to ponder on.
+    last_move: Option<(Board, ChessMove)>,
+    strategy: LazySmpStrategy,
+    search: Option<Running>,
+}
+
+/// A search in progress and the go command that started it.
+struct Running {
+    search: Searching,
+    limits: Limits,
+    /// When the clock started: at go, or at ponderhit for a ponder search.
+    start: Instant,
+    /// The depth and node count of the last report sent as an info line.
+    reported: Option<(usize, u64)>,
+}
+
+enum Searching {
+    Pondering(Ponder<ChessMove>),
+    Thinking(SearchHandle<ChessMove>),
+}
+
+/// The arguments of a go command.
+#[derive(Debug, Clone, Copy, Default)]
+struct Limits {
+    depth: Option<usize>,
+    /// How long the search may run, from movetime or worked out from the clock.
+    time: Option<Duration>,
+    /// Whether the best move waits for stop even if the search finishes first.
+    infinite: bool,
+    /// Whether the search waits for ponderhit or stop before anything else.
+    ponder: bool,
+}
+
+impl<W> UciEngine<W>
+where
+    W: Write,
+{
+    /// Starts from the initial position, with one search thread per core.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            board: Board::default(),
+            last_move: None,
+            strategy: LazySmpStrategy::with_threads(
+                thread::available_parallelism().map_or(1, |threads| threads.get()),
+            ),
+            search: None,
+        }
+    }
+
+    pub fn writer(&self) -> &W {
+        &self.out
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Handles one line of input. Returns false once the GUI has said quit.
+    pub fn handle(&mut self, line: &str) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("uci") => {
+                writeln!(self.out, "id name {}", env!("CARGO_PKG_NAME"))?;
+                writeln!(
+                    self.out,
+                    "option name Threads type spin default {} min 1 max 256",
+                    self.strategy.threads
+                )?;
+                writeln!(self.out, "option name Ponder type check default true")?;
+                writeln!(self.out, "uciok")?;
+            }
+            Some("isready") => writeln!(self.out, "readyok")?,
+            Some("setoption") => self.set_option(words.collect::<Vec<_>>())?,
+            Some("ucinewgame") => {
+                self.abandon_search();
+                self.strategy.clear_table();
+            }
+            Some("position") => {
+                self.abandon_search();
+                self.set_position(words.collect::<Vec<_>>())?;
+            }
+            Some("go") => {
+                self.abandon_search();
+                self.go(Limits::parse(words, self.board.side_to_move()))?;
+            }
+            Some("ponderhit") => self.ponder_hit(),
+            Some("stop") => {
+                if let Some(running) = self.search.take() {
+                    self.finish(running)?;
+                }
+            }
+            Some("quit") => {
+                self.abandon_search();
+                return Ok(false);
+            }
+            _ => {}
+        }
+        self.out.flush()?;
+        Ok(true)
+    }
+
+    /// Checks on the running search, sending an info line for anything new it has reported. Once
+    /// it has run out of time, or has finished on its own and isn't waiting for stop or
+    /// ponderhit, sends its best move.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let Some(running) = &mut self.search else {
+            return Ok(());
+        };
+        let latest = match &running.search {
+            Searching::Pondering(ponder) => ponder.latest(),
+            Searching::Thinking(handle) => handle.latest(),
+        };
+        if let Some(info) = latest {
+            report(&mut self.out, &self.board, &info, &mut running.reported)?;
+        }
+        let Searching::Thinking(handle) = &running.search else {
+            return self.out.flush();
+        };
+        let out_of_time = running
+            .limits
+            .time
+            .is_some_and(|time| running.start.elapsed() >= time);
+        let done = handle.is_finished() && !running.limits.infinite;
+        if !running.limits.ponder && (out_of_time || done) {
+            let running = self.search.take().expect("checked above");
+            self.finish(running)?;
+        }
+        self.out.flush()
+    }
+
+    fn set_option(&mut self, words: Vec<&str>) -> io::Result<()> {
+        if let ["name", "Threads", "value", threads] = words[..] {
+            match threads.parse() {
+                Ok(threads) if threads > 0 => self.strategy.threads = threads,
+                _ => writeln!(self.out, "info string invalid thread count {}", threads)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets up the board from `startpos` or `fen <fen>`, followed by `moves` and the moves in UCI
+    /// notation. Stops at the first move that isn't legal.
+    fn set_position(&mut self, words: Vec<&str>) -> io::Result<()> {
+        let moves_at = words
+            .iter()
+            .position(|&word| word == "moves")
+            .unwrap_or(words.len());
+        let board = match words[..moves_at] {
+            ["startpos"] => Ok(Board::default()),
+            ["fen", ref fen @ ..] => Board::from_str(&fen.join(" ")),
+            _ => {
+                writeln!(self.out, "info string invalid position")?;
+                return Ok(());
+            }
+        };
+        let Ok(board) = board else {
+            writeln!(self.out, "info string invalid fen")?;
+            return Ok(());
+        };
+        self.board = board;
+        self.last_move = None;
+        for word in words.iter().skip(moves_at + 1) {
+            match self.board.parse_move(word) {
+                Ok(mov) => {
+                    self.last_move = Some((self.board, mov));
+                    self.board.apply_move(&mov);
+                }
+                Err(_) => {
+                    writeln!(self.out, "info string illegal move {}", word)?;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn go(&mut self, limits: Limits) -> io::Result<()> {
+        let mut strategy = self.strategy.clone();
+        strategy.max_depth = match limits {
+            Limits {
+                depth: Some(depth), ..
+            } => depth,
+            Limits {
+                time: None,
+                infinite: false,
+                ponder: false,
+                ..
+            } => DEFAULT_DEPTH,
+            _ => usize::MAX,
+        };
+        let ponder = match (limits.ponder, self.last_move) {
+            (true, Some((before, expected))) => {
+                Ponder::expecting(before, expected, MaterialEvaluator, strategy.clone())
+            }
+            _ => None,
+        };
+        let search = match ponder {
+            Some(ponder) => Searching::Pondering(ponder),
+            None => {
+                Searching::Thinking(SearchHandle::spawn(self.board, MaterialEvaluator, strategy))
+            }
+        };
+        self.search = Some(Running {
+            search,
+            limits,
+            start: Instant::now(),
+            reported: None,
+        });
+        Ok(())
+    }
+
+    /// The opponent played the move we were pondering on, so the search carries on as if go had
+    /// been sent without ponder, with its clock starting now.
+    fn ponder_hit(&mut self) {
+        let Some(running) = self.search.take() else {
+            return;
+        };
+        let handle = match running.search {
+            Searching::Pondering(ponder) => {
+                let expected = *ponder.expected_move();
+                ponder
+                    .opponent_moved(&expected)
+                    .expect("the move was expected")
+            }
+            // With no reply to ponder on, the position as it was has been searched all along.
+            Searching::Thinking(handle) => handle,
+        };
+        self.search = Some(Running {
+            search: Searching::Thinking(handle),
+            limits: Limits {
+                ponder: false,
+                ..running.limits
+            },
+            start: Instant::now(),
+            ..running
+        });
+    }
+
+    /// Stops any search without sending its best move.
+    fn abandon_search(&mut self) {
+        if let Some(running) = self.search.take() {
+            running.search.into_handle().stop_and_wait();
+        }
+    }
+
+    /// Stops the search and sends its best move, with the reply it expects to ponder on if it
+    /// has one.
+    fn finish(&mut self, mut running: Running) -> io::Result<()> {
+        let info = running.search.into_handle().stop_and_wait();
+        report(&mut self.out, &self.board, &info, &mut running.reported)?;
+        match info.principal_variation[..] {
+            [] => writeln!(self.out, "bestmove 0000"),
+            [mov] => writeln!(self.out, "bestmove {}", uci(&mov)),
+            [mov, reply, ..] => writeln!(self.out, "bestmove {} ponder {}", uci(&mov), uci(&reply)),
+        }
+    }
+}
+
+/// Sends an info line for a search of the board, unless it is the last one sent again.
+fn report<W: Write>(
+    out: &mut W,
+    board: &Board,
+    info: &SearchInfo<ChessMove>,
+    reported: &mut Option<(usize, u64)>,
+) -> io::Result<()> {
+    if *reported == Some((info.depth, info.nodes)) {
+        return Ok(());
+    }
+    *reported = Some((info.depth, info.nodes));
+    write!(out, "info depth {}", info.depth)?;
+    let moves = info.principal_variation.len() as i64;
+    match info.score {
+        Some(Score::Result(GameResult::Win(player))) if player == board.current_player() => {
+            write!(out, " score mate {}", (moves + 1) / 2)?
+        }
+        Some(Score::Result(GameResult::Win(_))) => write!(out, " score mate {}", -(moves / 2))?,
+        Some(Score::Result(GameResult::Draw)) => write!(out, " score cp 0")?,
+        // MaterialEvaluator's value is tanh(balance / 8), with the balance in pawns.
+        Some(Score::Value(value)) => {
+            let value = value.clamp(-0.999_999, 0.999_999);
+            write!(out, " score cp {}", (800.0 * value.atanh()).round() as i64)?
+        }
+        _ => {}
+    }
+    write!(
+        out,
+        " nodes {} nps {} time {}",
+        info.nodes,
+        info.nodes_per_second(),
+        info.elapsed.as_millis()
+    )?;
+    if !info.principal_variation.is_empty() {
+        write!(out, " pv")?;
+        for mov in &info.principal_variation {
+            write!(out, " {}", uci(mov))?;
+        }
+    }
+    writeln!(out)
+}
+
+impl Searching {
+    /// Returns the search itself. A ponder search stopped before ponderhit reports on the
+    /// position after the expected reply, which is what stop asks for.
+    fn into_handle(self) -> SearchHandle<ChessMove> {
+        match self {
+            Searching::Pondering(ponder) => {
+                let expected = *ponder.expected_move();
+                ponder
+                    .opponent_moved(&expected)
+                    .expect("the move was expected")
+            }
+            Searching::Thinking(handle) => handle,
+        }
+    }
+}
+
+impl Limits {
+    /// Parses the arguments of go for the side to move. With only the clock to go by, a search
+    /// takes a thirtieth of the time left plus half the increment.
+    fn parse<'a>(words: impl Iterator<Item = &'a str>, side: Color) -> Self {
+        let mut limits = Limits::default();
+        let (mut left, mut increment) = (None, 0);
+        let mut words = words.peekable();
+        while let Some(word) = words.next() {
+            let number = match word {
+                "depth" | "movetime" | "wtime" | "btime" | "winc" | "binc" => words
+                    .next_if(|value| value.parse::<u64>().is_ok())
+                    .and_then(|value| value.parse::<u64>().ok()),
+                _ => None,
+            };
+            match (word, side) {
+                ("depth", _) => limits.depth = number.map(|depth| depth as usize),
+                ("movetime", _) => limits.time = number.map(Duration::from_millis),
+                ("wtime", Color::White) | ("btime", Color::Black) => left = number,
+                ("winc", Color::White) | ("binc", Color::Black) => increment = number.unwrap_or(0),
+                ("infinite", _) => limits.infinite = true,
+                ("ponder", _) => limits.ponder = true,
+                _ => {}
+            }
+        }
+        if let (None, Some(left)) = (limits.time, left) {
+            limits.time = Some(Duration::from_millis(left / 30 + increment / 2));
+        }
+        limits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UciEngine;
+    use std::time::{Duration, Instant};
+
+    /// Sends the commands, then polls until the engine sends a best move and returns it.
+    fn best_move(engine: &mut UciEngine<Vec<u8>>, commands: &[&str]) -> String {
+        for command in commands {
+            assert!(engine.handle(command).unwrap());
+        }
+        let start = Instant::now();
+        while engine.is_searching() {
+            assert!(start.elapsed() < Duration::from_secs(60), "no best move");
+            engine.poll().unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        last_line(engine)
+    }
+
+    fn last_line(engine: &UciEngine<Vec<u8>>) -> String {
+        let output = String::from_utf8(engine.writer().clone()).unwrap();
+        output.lines().last().unwrap_or_default().to_string()
+    }
+
+    #[test]
+    fn test_uci_session() {
+        let mut engine = UciEngine::new(Vec::new());
+        assert!(engine.handle("uci").unwrap());
+        assert_eq!(last_line(&engine), "uciok");
+        assert!(engine.handle("setoption name Threads value 1").unwrap());
+        assert!(engine.handle("isready").unwrap());
+        assert_eq!(last_line(&engine), "readyok");
+
+        // Back-rank mate in one.
+        let mate = best_move(
+            &mut engine,
+            &[
+                "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+                "go depth 2",
+            ],
+        );
+        assert!(mate.starts_with("bestmove a1a8"), "{}", mate);
+        let output = String::from_utf8(engine.writer().clone()).unwrap();
+        let info = output.lines().rev().nth(1).unwrap();
+        assert!(
+            info.starts_with("info depth 1 score mate 1 nodes "),
+            "{}",
+            info
+        );
+        assert!(info.ends_with(" pv a1a8"), "{}", info);
+        let reply = best_move(
+            &mut engine,
+            &["position startpos moves e2e4 e7e5 g1f3", "go movetime 50"],
+        );
+        assert!(reply.starts_with("bestmove "), "{}", reply);
+
+        // An infinite search only ends with stop.
+        engine.handle("position startpos").unwrap();
+        engine.handle("go infinite").unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        engine.poll().unwrap();
+        assert!(engine.is_searching());
+        let stopped = best_move(&mut engine, &["stop"]);
+        assert!(stopped.starts_with("bestmove "), "{}", stopped);
+        assert!(!engine.handle("quit").unwrap());
+    }
+
+    #[test]
+    fn test_uci_ponder() {
+        let mut engine = UciEngine::new(Vec::new());
+        engine.handle("setoption name Threads value 1").unwrap();
+
+        // Pondering on black's reply to e4, the search reports its iterations but waits for
+        // ponderhit even once it has reached its depth.
+        engine.handle("position startpos moves e2e4 e7e5").unwrap();
+        engine.handle("go ponder depth 1").unwrap();
+        let start = Instant::now();
+        while !last_line(&engine).starts_with("info depth 1 ") {
+            assert!(start.elapsed() < Duration::from_secs(60), "no info line");
+            engine.poll().unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        engine.poll().unwrap();
+        assert!(engine.is_searching());
+        assert!(last_line(&engine).starts_with("info depth 1 "));
+        let hit = best_move(&mut engine, &["ponderhit"]);
+        assert!(hit.starts_with("bestmove "), "{}", hit);
+
+        // Stopped while pondering, it sends the best move for the position it pondered.
+        engine.handle("position startpos moves d2d4 d7d5").unwrap();
+        engine.handle("go ponder").unwrap();
+        let stopped = best_move(&mut engine, &["stop"]);
+        assert!(stopped.starts_with("bestmove "), "{}", stopped);
+    }
+}